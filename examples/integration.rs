@@ -30,13 +30,13 @@ extern crate mscorlib_safe_derive;
 use winapi::um::oaidl::{LPSAFEARRAY};
 
 use mscorlib_sys::system::reflection::_Type;
-use mscorlib_safe::PtrContainer;
+use mscorlib_safe::{ComPtr, PtrContainer};
 use mscorlib_safe::new_variant::Variant;
 use mscorlib_safe::new_safearray::RSafeArray;
 
 #[derive(Debug,PtrContainer)]
 struct Fit {
-    ptr: *mut _Type
+    ptr: ComPtr<_Type>
 }
 
 fn main() {
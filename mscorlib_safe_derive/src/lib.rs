@@ -26,60 +26,189 @@ extern crate syn;
 #[macro_use]
 extern crate quote;
 
-use proc_macro2::{Ident, Span};
-use syn::{DeriveInput, Data, Type};
+use proc_macro2::{Span, TokenStream};
+use syn::{Data, DeriveInput, Field, GenericArgument, Index, Lit, Member, Meta, NestedMeta, PathArguments, Type};
+use syn::spanned::Spanned;
 
-// impl PtrContainer<_Type> for ClrType {
+// #[derive(PtrContainer)]
+// #[ptr_container(field = "ptr")]
+// struct ClrType<M> {
+//     ptr: ComPtr<_Type>,
+//     marker: PhantomData<M>,
+// }
+//
+// impl<M> PtrContainer<_Type> for ClrType<M> {
 //     fn ptr(&self) -> *const _Type {
-//         self.ptr
+//         self.ptr.as_ptr()
 //     }
-
+//
 //     fn ptr_mut(&self) -> *mut _Type {
-//         self.ptr
+//         self.ptr.as_ptr()
+//     }
+//
+//     fn from(p: *mut _Type) -> ClrType<M> {
+//         ClrType {ptr: unsafe {<ComPtr<_Type>>::from_raw_add_ref(p)}, marker: Default::default()}
 //     }
+//
+//     unsafe fn from_raw(p: *mut _Type) -> ClrType<M> {
+//         ClrType {ptr: <ComPtr<_Type>>::from_raw(p), marker: Default::default()}
+//     }
+//     ...
 // }
 
-#[proc_macro_derive(PtrContainer)]
-pub fn pointer_container_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
-    let inp: DeriveInput = syn::parse(input).unwrap();
-    let name = inp.ident;
-    if let Data::Struct(dta) = inp.data {
-        for (ix, field) in dta.fields.iter().enumerate() { 
-            if let Type::Ptr( ref tptr) = &field.ty {
-                let f_name = match field.ident {
-                    Some(ref fnn) => fnn.to_string(), 
-                    None => ix.to_string()
-                };
-                let f_name = Ident::new(&f_name, Span::call_site());
-                //let f_name = f_name.unwrap();
-                let elem = &tptr.elem;
-                let mut expanded = quote! {
-                    //impl ToVariantUnknown<_Type> for ClrType {} 
-                    impl PtrContainer<#elem> for #name {
-                        fn ptr(&self) -> *const #elem {
-                            self.#f_name
-                        }
-                        fn ptr_mut(&self) -> *mut #elem {
-                            self.#f_name
-                        }
-                        fn from(p: *mut #elem) -> #name {
-                            #name {#f_name: p}
-                        }
-                        fn into_variant(&self) -> Variant {
-                            use std::mem;
-                            use winapi::um::unknwnbase::IUnknown;
-                            let p = self.ptr_mut();
-                            let p: *mut IUnknown = unsafe {mem::transmute::<*mut #elem, *mut IUnknown>(p)};
-                            Variant::from(p)
+fn error(span: Span, msg: &str) -> TokenStream {
+    quote_spanned! {span=>
+        compile_error!(#msg);
+    }
+}
+
+//The interface a ComPtr<T> field holds
+fn com_ptr_elem(ty: &Type) -> Option<&Type> {
+    if let Type::Path(ref tpath) = ty {
+        let seg = tpath.path.segments.iter().last()?;
+        if seg.ident != "ComPtr" {
+            return None;
+        }
+        if let PathArguments::AngleBracketed(ref args) = seg.arguments {
+            if let Some(GenericArgument::Type(ref elem)) = args.args.iter().next() {
+                return Some(elem);
+            }
+        }
+    }
+    None
+}
+
+//The interface a field points at, if it is a ComPtr or a *mut
+fn pointer_elem(ty: &Type) -> Option<&Type> {
+    match ty {
+        Type::Ptr(ref tptr) if tptr.mutability.is_some() => Some(&*tptr.elem),
+        _ => com_ptr_elem(ty)
+    }
+}
+
+fn member(ix: usize, field: &Field) -> Member {
+    match field.ident {
+        Some(ref ident) => Member::Named(ident.clone()),
+        None => Member::Unnamed(Index {index: ix as u32, span: field.span()})
+    }
+}
+
+//#[ptr_container(field = "...")], the name of the pointer field for structs with more than one
+fn field_attr(inp: &DeriveInput) -> Result<Option<(String, Span)>, TokenStream> {
+    let mut found = None;
+    for attr in inp.attrs.iter() {
+        if attr.path.segments.len() != 1 || attr.path.segments[0].ident != "ptr_container" {
+            continue;
+        }
+        if found.is_some() {
+            return Err(error(attr.path.span(), "duplicate #[ptr_container] attribute"));
+        }
+        let name = match attr.interpret_meta() {
+            Some(Meta::List(ref list)) if list.nested.len() == 1 => {
+                match list.nested[0] {
+                    NestedMeta::Meta(Meta::NameValue(ref nv)) if nv.ident == "field" => {
+                        match nv.lit {
+                            Lit::Str(ref s) => Some((s.value(), s.span())),
+                            _ => None
                         }
-                    }
-                };
+                    },
+                    _ => None
+                }
+            },
+            _ => None
+        };
+        match name {
+            Some(name) => found = Some(name),
+            None => return Err(error(attr.path.span(), "expected #[ptr_container(field = \"...\")]"))
+        }
+    }
+    Ok(found)
+}
 
-                return expanded.into()
+fn expand(inp: &DeriveInput) -> Result<TokenStream, TokenStream> {
+    let name = &inp.ident;
+    let fields = match inp.data {
+        Data::Struct(ref dta) => &dta.fields,
+        Data::Enum(ref dta) => return Err(error(dta.enum_token.span(), "PtrContainer can only be derived for structs")),
+        Data::Union(ref dta) => return Err(error(dta.union_token.span(), "PtrContainer can only be derived for structs"))
+    };
+    let fields: Vec<(usize, &Field)> = fields.iter().enumerate().collect();
+
+    let ptr_ix = match field_attr(inp)? {
+        Some((f_name, span)) => {
+            let named = fields.iter().find(|&&(ix, field)| match field.ident {
+                Some(ref ident) => *ident == f_name, 
+                None => ix.to_string() == f_name
+            });
+            match named {
+                Some(&(ix, field)) if pointer_elem(&field.ty).is_some() => ix,
+                Some(&(_, field)) => return Err(error(field.ty.span(), "the #[ptr_container] field must be a *mut pointer or a ComPtr")),
+                None => return Err(error(span, &format!("no field named `{}`", f_name)))
+            }
+        },
+        None => {
+            let candidates: Vec<usize> = fields.iter().filter(|&&(_, field)| pointer_elem(&field.ty).is_some()).map(|&(ix, _)| ix).collect();
+            match candidates.len() {
+                0 => return Err(error(name.span(), "PtrContainer needs a *mut pointer or ComPtr field")),
+                1 => candidates[0],
+                _ => return Err(error(fields[candidates[1]].1.span(), "more than one pointer field, pick one with #[ptr_container(field = \"...\")]"))
             }
         }
+    };
+
+    let ptr_field = fields[ptr_ix].1;
+    let ty = &ptr_field.ty;
+    let f_name = member(ptr_ix, ptr_field);
+    let elem = pointer_elem(ty).unwrap();
+    //A ComPtr field owns its reference, AddRef'd by from and taken over by from_raw.
+    //A raw pointer field is only lent one either way.
+    let (get, from, from_raw) = if com_ptr_elem(ty).is_some() {
+        (quote!{self.#f_name.as_ptr()}, quote!{unsafe {<#ty>::from_raw_add_ref(p)}}, quote!{<#ty>::from_raw(p)})
     }
-    let e = quote!{};
+    else {
+        (quote!{self.#f_name}, quote!{p}, quote!{p})
+    };
+    //Everything else starts out as its Default, errors point at the field that has none
+    let defaults: Vec<TokenStream> = fields.iter().filter(|&&(ix, _)| ix != ptr_ix).map(|&(ix, field)| {
+        let member = member(ix, field);
+        let fty = &field.ty;
+        quote_spanned! {fty.span()=>
+            #member: <#fty as ::std::default::Default>::default()
+        }
+    }).collect();
+    //from and from_raw both list them, quote moves what it repeats over
+    let defaults = &defaults;
 
-    return e.into();
-}
\ No newline at end of file
+    let (impl_generics, ty_generics, where_clause) = inp.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics PtrContainer<#elem> for #name #ty_generics #where_clause {
+            fn ptr(&self) -> *const #elem {
+                #get
+            }
+            fn ptr_mut(&self) -> *mut #elem {
+                #get
+            }
+            fn from(p: *mut #elem) -> #name #ty_generics {
+                #name {#f_name: #from, #(#defaults,)*}
+            }
+            unsafe fn from_raw(p: *mut #elem) -> #name #ty_generics {
+                #name {#f_name: #from_raw, #(#defaults,)*}
+            }
+            fn into_variant(&self) -> ::mscorlib_safe::Result<Variant> {
+                use mscorlib_safe::{ComPtr, query_interface};
+                use winapi::um::unknwnbase::IUnknown;
+                let unk: ComPtr<IUnknown> = unsafe {query_interface(self.ptr_mut())}?;
+                Ok(Variant::from(unk.as_ptr()))
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(PtrContainer, attributes(ptr_container))]
+pub fn pointer_container_derive(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let inp: DeriveInput = syn::parse(input).unwrap();
+    match expand(&inp) {
+        Ok(expanded) => expanded.into(),
+        Err(e) => e.into()
+    }
+}
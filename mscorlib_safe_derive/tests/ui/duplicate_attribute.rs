@@ -0,0 +1,12 @@
+#[macro_use]
+extern crate mscorlib_safe_derive;
+
+#[derive(PtrContainer)]
+#[ptr_container(field = "first")]
+#[ptr_container(field = "second")]
+struct Container {
+    first: *mut u8, 
+    second: *mut u16
+}
+
+fn main() {}
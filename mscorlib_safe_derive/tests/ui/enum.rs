@@ -0,0 +1,9 @@
+#[macro_use]
+extern crate mscorlib_safe_derive;
+
+#[derive(PtrContainer)]
+enum Container {
+    Ptr(*mut u8)
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate mscorlib_safe_derive;
+
+#[derive(PtrContainer)]
+struct Container {
+    len: usize, 
+    readonly: *const u8
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+#[macro_use]
+extern crate mscorlib_safe_derive;
+
+#[derive(PtrContainer)]
+#[ptr_container(field = "len")]
+struct Container {
+    ptr: *mut u8, 
+    len: usize
+}
+
+fn main() {}
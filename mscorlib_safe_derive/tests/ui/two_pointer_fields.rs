@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate mscorlib_safe_derive;
+
+#[derive(PtrContainer)]
+struct Container {
+    first: *mut u8, 
+    second: *mut u16
+}
+
+fn main() {}
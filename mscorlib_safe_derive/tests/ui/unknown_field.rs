@@ -0,0 +1,10 @@
+#[macro_use]
+extern crate mscorlib_safe_derive;
+
+#[derive(PtrContainer)]
+#[ptr_container(field = "missing")]
+struct Container {
+    ptr: *mut u8
+}
+
+fn main() {}
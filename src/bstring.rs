@@ -19,16 +19,23 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
+use std::borrow::Borrow;
+use std::char::{self, DecodeUtf16};
+use std::fmt;
+use std::iter::Cloned;
+use std::mem;
+use std::ops::Deref;
+use std::ptr;
 use std::slice;
 use std::ffi::{OsString};
 use std::ffi::OsStr;
 use std::os::windows::ffi::{OsStrExt, OsStringExt};
 
 use winapi::shared::minwindef::UINT;
-use winapi::um::oleauto::{SysAllocStringLen, SysStringLen};
+use winapi::shared::wtypes::BSTR;
 
-use new_variant::Variant;
-use wrappers::PtrContainer;
+use bstr_alloc::{SysAllocStringLen, SysFreeString, SysStringLen};
+use result::{ClrError, Result};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BString {
@@ -36,18 +43,41 @@ pub struct BString {
 	inner: Vec<u16>
 }
 
-//Do not derive Clone - size is not known at compile time
+//Borrowed UTF-16 contents of a BString or of a BSTR owned by someone else, without the terminator
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct BStr {
-	size: usize,
 	inner: [u16]
 }
 
+//Owns a BSTR allocated with SysAllocString*, freed on drop. Null is allowed and reads as empty.
+pub struct SysString {
+	raw: BSTR
+}
+
+//How BSTRs read out of a VARIANT or SAFEARRAY become Rust strings. 
+//CLR strings can hold unpaired surrogates, only Wide keeps those as they were. 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StringMode {
+	Lossy, //String, unpaired surrogates become U+FFFD
+	Strict, //String, an unpaired surrogate is a ClrError::InvalidUtf16
+	Wide, //BString with the UTF-16 untouched, Variant::WString and RSafeArray::WStrings
+}
+
+impl Default for StringMode {
+	fn default() -> StringMode {
+		StringMode::Lossy
+	}
+}
+
+//char iterator over a BStr, unpaired surrogates come out as U+FFFD
+pub struct Chars<'a> {
+	inner: DecodeUtf16<Cloned<slice::Iter<'a, u16>>>
+}
+
 fn os_to_wide(s: &OsStr) -> Vec<u16> {
 	s.encode_wide().collect()
 }
 
-#[allow(dead_code)]
 fn os_from_wide(s: &[u16]) -> OsString {
 	OsString::from_wide(s)
 }
@@ -83,10 +113,9 @@ impl BString {
 		String::from_utf16_lossy(&self.inner[..])
 	}
 
-	pub unsafe fn as_sys(&self) -> *mut u16 {
-		let new_ws = self.inner.clone();
-		let ws_ptr = new_ws.as_ptr();
-		SysAllocStringLen(ws_ptr, self.size as UINT)
+	//Fresh BSTR copy of the contents, freed when the SysString drops
+	pub fn to_sys(&self) -> SysString {
+		SysString::from_bstr(self)
 	}
 
 	pub unsafe fn from_ptr(p: *const u16, len: usize) -> BString {
@@ -99,13 +128,130 @@ impl BString {
 		BString::from_vec(slice)
 	}
 
-	pub fn from_ptr_safe(p: *mut u16) -> BString {
-		assert!(!p.is_null());
-		let us: u32 = unsafe {SysStringLen(p)};
-		if us == 0 {
-			return BString::new();
+	//A null BSTR is a valid empty string to COM, so it reads as empty here too. 
+	//Use from_ptr_nullable where null and "" need telling apart. p has to be null or a live BSTR.
+	pub unsafe fn from_ptr_safe(p: *mut u16) -> BString {
+		BString::from_ptr_nullable(p).unwrap_or_else(BString::new)
+	}
+
+	//None for a null BSTR. Length comes from the prefix, so embedded NULs are kept.
+	//p has to be null or a live BSTR, anything else is read through as if it had a prefix.
+	pub unsafe fn from_ptr_nullable(p: *mut u16) -> Option<BString> {
+		if p.is_null() {
+			return None;
+		}
+		let us: u32 = SysStringLen(p);
+		Some(BString::from_ptr(p, us as usize))
+	}
+}
+
+impl BStr {
+	pub fn from_slice(s: &[u16]) -> &BStr {
+		//BStr is a plain wrapper around [u16], so the fat pointer carries over as is
+		unsafe {&*(s as *const [u16] as *const BStr)}
+	}
+
+	//Borrows the string behind p for as long as the caller says it lives, nothing is copied. 
+	//A null BSTR is the empty string.
+	pub unsafe fn from_raw<'a>(p: BSTR) -> &'a BStr {
+		BStr::from_raw_nullable(p).unwrap_or_else(|| BStr::from_slice(&[]))
+	}
+
+	pub unsafe fn from_raw_nullable<'a>(p: BSTR) -> Option<&'a BStr> {
+		if p.is_null() {
+			return None;
 		}
-		unsafe {BString::from_ptr(p, us as usize)}
+		Some(BStr::from_slice(slice::from_raw_parts(p, SysStringLen(p) as usize)))
+	}
+
+	pub fn len(&self) -> usize {
+		self.inner.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.inner.is_empty()
+	}
+
+	pub fn as_slice(&self) -> &[u16] {
+		&self.inner
+	}
+
+	pub fn chars(&self) -> Chars<'_> {
+		Chars {inner: char::decode_utf16(self.inner.iter().cloned())}
+	}
+
+	pub fn to_string_lossy(&self) -> String {
+		String::from_utf16_lossy(&self.inner)
+	}
+
+	pub fn to_string_strict(&self) -> Result<String> {
+		let mut s = String::with_capacity(self.inner.len());
+		let mut index = 0;
+		for c in char::decode_utf16(self.inner.iter().cloned()) {
+			match c {
+				Ok(c) => {
+					index += c.len_utf16();
+					s.push(c);
+				},
+				Err(e) => return Err(ClrError::InvalidUtf16{unit: e.unpaired_surrogate(), index: index})
+			}
+		}
+		Ok(s)
+	}
+
+	//Lossless on Windows, where OsString holds UTF-16 as is
+	pub fn to_os_string(&self) -> OsString {
+		os_from_wide(&self.inner)
+	}
+
+	pub fn to_bstring(&self) -> BString {
+		BString::from_vec(&self.inner)
+	}
+}
+
+impl<'a> Iterator for Chars<'a> {
+	type Item = char;
+
+	fn next(&mut self) -> Option<char> {
+		self.inner.next().map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+	}
+
+	fn size_hint(&self) -> (usize, Option<usize>) {
+		self.inner.size_hint()
+	}
+}
+
+impl Deref for BString {
+	type Target = BStr;
+
+	fn deref(&self) -> &BStr {
+		BStr::from_slice(&self.inner)
+	}
+}
+
+impl AsRef<BStr> for BString {
+	fn as_ref(&self) -> &BStr {
+		self
+	}
+}
+
+impl Borrow<BStr> for BString {
+	fn borrow(&self) -> &BStr {
+		self
+	}
+}
+
+impl ToOwned for BStr {
+	type Owned = BString;
+
+	fn to_owned(&self) -> BString {
+		self.to_bstring()
+	}
+}
+
+impl<'a> From<&'a BStr> for BString {
+	fn from(s: &BStr) -> BString {
+		s.to_bstring()
 	}
 }
 
@@ -133,28 +279,195 @@ impl From<BString> for String {
 	}
 }
 
-impl From<BString> for *mut u16 {
-	fn from(bs: BString) -> *mut u16 {
-		unsafe {bs.as_sys()}
+impl SysString {
+	pub fn new() -> SysString {
+		SysString {raw: ptr::null_mut()}
+	}
+
+	pub fn from_bstr(s: &BStr) -> SysString {
+		let raw = unsafe {SysAllocStringLen(s.as_slice().as_ptr(), s.len() as UINT)};
+		assert!(!raw.is_null(), "SysAllocStringLen failed");
+		SysString {raw: raw}
+	}
+
+	//Takes ownership of raw, which must have come from SysAllocString* or be null
+	pub unsafe fn from_raw(raw: BSTR) -> SysString {
+		SysString {raw: raw}
+	}
+
+	//Hands ownership back to the caller, eg to move the string into a VARIANT
+	pub fn into_raw(self) -> BSTR {
+		let raw = self.raw;
+		mem::forget(self);
+		raw
+	}
+
+	//Lends the BSTR to an [in] parameter, it stays owned by self
+	pub fn as_in(&self) -> BSTR {
+		self.raw
+	}
+
+	//For an [out] parameter. Frees what self held, whatever the callee writes is owned by self afterwards.
+	pub fn as_out(&mut self) -> *mut BSTR {
+		unsafe {SysFreeString(self.raw)};
+		self.raw = ptr::null_mut();
+		&mut self.raw
+	}
+
+	pub fn is_null(&self) -> bool {
+		self.raw.is_null()
+	}
+
+	pub fn as_bstr(&self) -> &BStr {
+		unsafe {BStr::from_raw(self.raw)}
+	}
+
+	//None when the BSTR is null, eg an [out] parameter the callee left unset
+	pub fn as_bstr_nullable(&self) -> Option<&BStr> {
+		unsafe {BStr::from_raw_nullable(self.raw)}
+	}
+}
+
+impl Drop for SysString {
+	fn drop(&mut self) {
+		unsafe {SysFreeString(self.raw)};
 	}
 }
 
-impl PtrContainer<u16> for BString {
-	fn ptr(&self) -> *const u16 {
-		unsafe {
-			self.as_sys()
+impl Clone for SysString {
+	fn clone(&self) -> SysString {
+		if self.raw.is_null() {
+			return SysString::new();
 		}
+		SysString::from_bstr(self)
 	}
-	fn ptr_mut(&self) -> *mut u16 {
+}
+
+impl Default for SysString {
+	fn default() -> SysString {
+		SysString::new()
+	}
+}
+
+impl fmt::Debug for SysString {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.debug_tuple("SysString").field(&self.as_bstr()).finish()
+	}
+}
+
+impl Deref for SysString {
+	type Target = BStr;
+
+	fn deref(&self) -> &BStr {
+		self.as_bstr()
+	}
+}
+
+impl<'a> From<&'a BStr> for SysString {
+	fn from(s: &BStr) -> SysString {
+		SysString::from_bstr(s)
+	}
+}
+
+impl From<BString> for SysString {
+	fn from(bs: BString) -> SysString {
+		bs.to_sys()
+	}
+}
+
+impl From<String> for SysString {
+	fn from(s: String) -> SysString {
+		BString::from_str(&s).to_sys()
+	}
+}
+
+impl<'a> From<&'a str> for SysString {
+	fn from(s: &str) -> SysString {
+		BString::from_str(s).to_sys()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn borrow_raw() {
+		let bs = BString::from_str("héllo");
+		let sys = bs.to_sys();
 		unsafe {
-			self.as_sys()
+			let borrowed = BStr::from_raw(sys.as_in());
+			assert_eq!(borrowed.as_slice().as_ptr(), sys.as_in() as *const u16);
+			assert_eq!(borrowed.len(), 5);
+			assert_eq!(borrowed, &*bs);
+			assert_eq!(borrowed.to_string_lossy(), "héllo");
+			assert_eq!(borrowed.to_os_string(), OsString::from("héllo"));
+			assert!(BStr::from_raw(ptr::null_mut()).is_empty());
+		}
+	}
+
+	#[test]
+	fn chars() {
+		let bs = BString::from_vec(vec![0x61, 0xD83D, 0xDE00, 0xDC00, 0x62]);
+		assert_eq!(bs.chars().collect::<String>(), "a\u{1F600}\u{FFFD}b");
+		assert_eq!(bs.to_string_lossy(), bs.to_string());
+		let owned: BString = (*bs).to_owned();
+		assert_eq!(owned, bs);
+	}
+
+	//Stands in for a COM getter with a BSTR [out] parameter
+	unsafe fn get_name(pbs: *mut BSTR) {
+		*pbs = BString::from_str("out").to_sys().into_raw();
+	}
+
+	#[test]
+	fn sys_string_out() {
+		let mut sys: SysString = From::from("in");
+		assert_eq!(sys.to_string_lossy(), "in");
+		unsafe {get_name(sys.as_out())};
+		assert_eq!(sys.to_string_lossy(), "out");
+		let copy = sys.clone();
+		assert!(copy.as_in() != sys.as_in());
+		assert_eq!(copy.as_bstr(), sys.as_bstr());
+		assert!(SysString::new().is_null());
+		assert!(SysString::new().is_empty());
+	}
+
+	#[test]
+	fn strict() {
+		let bs = BString::from_vec(vec![0x61, 0xD83D, 0xDE00, 0x62]);
+		assert_eq!(bs.to_string_strict().unwrap(), "a\u{1F600}b");
+		let bs = BString::from_vec(vec![0x61, 0xD83D, 0xDE00, 0xDC00, 0x62]);
+		match bs.to_string_strict() {
+			Err(ClrError::InvalidUtf16{unit, index}) => {
+				assert_eq!(unit, 0xDC00);
+				assert_eq!(index, 3);
+			},
+			other => panic!("Unexpected result: {:?}", other)
 		}
 	}
-	fn from(pmu: *mut u16) -> BString {
-		BString::from_ptr_safe(pmu)
+
+	#[test]
+	fn null_and_empty() {
+		assert_eq!(unsafe {BString::from_ptr_nullable(ptr::null_mut())}, None);
+		assert_eq!(unsafe {BString::from_ptr_safe(ptr::null_mut())}, BString::new());
+		let empty = BString::new().to_sys();
+		assert!(!empty.is_null());
+		assert_eq!(unsafe {BString::from_ptr_nullable(empty.as_in())}, Some(BString::new()));
+		assert_eq!(empty.as_bstr_nullable().map(BStr::len), Some(0));
+		assert_eq!(SysString::new().as_bstr_nullable(), None);
 	}
 
-	fn into_variant(&self) -> Variant {
-		Variant::from(self.to_string())
+	#[test]
+	fn embedded_nul() {
+		let bs = BString::from_str("a\0b\0");
+		assert_eq!(bs.len(), 4);
+		let sys = bs.to_sys();
+		unsafe {
+			assert_eq!(::winapi::um::oleauto::SysStringByteLen(sys.as_in()), 8);
+			assert_eq!(*sys.as_in().offset(4), 0);
+		}
+		assert_eq!(unsafe {BString::from_ptr_safe(sys.as_in())}, bs);
+		assert_eq!(sys.to_string_lossy(), "a\0b\0");
 	}
-}
\ No newline at end of file
+}
@@ -0,0 +1,726 @@
+// coerce.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Variant::coerce_to, the VariantChangeType rules in Rust so they behave the same everywhere.
+//Following oleaut32: floating point and scaled values round half to even, out of range values
+//are an Overflow rather than wrapping, bools are -1/0 (wrapped for the unsigned types),
+//and strings that don't parse are a Coercion error.
+//Where oleaut32 would use the user's locale this uses an invariant one: '.' as the decimal point,
+//dates as YYYY-MM-DD and times as 24 hour HH:MM:SS.
+
+use rust_decimal::Decimal;
+
+use winapi::shared::wtypes::{VARTYPE, VT_BOOL, VT_BSTR, VT_CY, VT_DATE, VT_DECIMAL, VT_EMPTY, VT_ERROR,
+                             VT_I1, VT_I2, VT_I4, VT_I8, VT_INT, VT_NULL, VT_R4, VT_R8, VT_UI1, VT_UI2,
+                             VT_UI4, VT_UI8, VT_UINT};
+
+use new_variant::{Currency, Date, Int, SCode, UInt, Variant, build_c_decimal};
+use result::{ClrError, SourceLocation, Result};
+
+//oleaut32's DATE range, 100-01-01 through the end of 9999-12-31
+pub const MIN_DATE: f64 = -657434.0;
+pub const MAX_DATE: f64 = 2958466.0;
+//Days between 1899-12-30 (DATE 0) and 1970-01-01
+pub const UNIX_EPOCH_DATE: i64 = 25569;
+const SECONDS_PER_DAY: i64 = 86400;
+const MAX_DECIMAL_SCALE: u32 = 28;
+
+//A value on its way between vartypes. Exact holds mantissa * 10^-scale, so integers,
+//currency, decimals and decimal strings convert without going through f64.
+#[derive(Debug, Clone, Copy)]
+pub enum Num {
+    Exact(i128, u32),
+    Float(f64)
+}
+
+enum Source {
+    Empty,
+    Bool(bool),
+    Num(Num),
+    Str(String),
+    Error(i32),
+    Unsupported
+}
+
+impl Variant {
+    //Converts to vt the way VariantChangeType does. By-reference values are read through,
+    //and coercing to the vartype the Variant already has gives back a copy.
+    pub fn coerce_to(&self, vt: VARTYPE) -> Result<Variant> {
+        if let Some(target) = self.by_value() {
+            return target.coerce_to(vt);
+        }
+        let from = self.vartype();
+        if from == vt {
+            return Ok(self.clone());
+        }
+        let mismatch = ClrError::Coercion{from: from, to: vt};
+        let overflow = ClrError::Overflow{from: from, to: vt};
+        let source = match *self {
+            Variant::Empty(_) => Source::Empty,
+            Variant::Null(_) => {
+                return match vt as u32 {
+                    VT_EMPTY => Ok(Variant::Empty(())),
+                    _ => Err(mismatch)
+                };
+            },
+            Variant::Bool(b) => Source::Bool(b),
+            Variant::Char(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::Byte(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::Short(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::UShort(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::Long(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::ULong(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::LongLong(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::ULongLong(n) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::Int(Int(n)) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::UInt(UInt(n)) => Source::Num(Num::Exact(n as i128, 0)),
+            Variant::Float(f) => Source::Num(Num::Float(f as f64)),
+            Variant::Double(f) => Source::Num(Num::Float(f)),
+            Variant::Date(Date(d)) => Source::Num(Num::Float(d)),
+            Variant::Currency(Currency(c)) => Source::Num(Num::Exact(c as i128, 4)),
+            Variant::Decimal(d) => Source::Num(decimal_to_num(d)),
+            Variant::BString(ref s) => Source::Str(s.clone()),
+            Variant::WString(ref s) => Source::Str(s.to_string_lossy()),
+            Variant::ErrorCode(ref code) => Source::Error(code.0),
+            _ => Source::Unsupported
+        };
+
+        let target = match vt as u32 {
+            VT_EMPTY => return Ok(Variant::Empty(())),
+            VT_NULL => {
+                return match source {
+                    Source::Empty => Ok(Variant::Null(())),
+                    _ => Err(mismatch)
+                };
+            },
+            VT_ERROR => {
+                return match source {
+                    Source::Error(code) => Ok(Variant::ErrorCode(SCode(code))),
+                    _ => Err(mismatch)
+                };
+            },
+            _ => vt
+        };
+
+        //Everything left is a number, bool, string or date, which errors and interfaces don't become
+        let num = match source {
+            Source::Error(_) | Source::Unsupported => return Err(mismatch),
+            Source::Empty => match target as u32 {
+                VT_BSTR => return Ok(Variant::BString(String::new())),
+                _ => Num::Exact(0, 0)
+            },
+            Source::Bool(b) => {
+                match target as u32 {
+                    VT_BSTR => return Ok(Variant::BString(String::from(if b {"-1"} else {"0"}))),
+                    //oleaut32 truncates VARIANT_TRUE into the unsigned types rather than failing
+                    VT_UI1 => return Ok(Variant::Byte(if b {u8::max_value()} else {0})),
+                    VT_UI2 => return Ok(Variant::UShort(if b {u16::max_value()} else {0})),
+                    VT_UI4 => return Ok(Variant::ULong(if b {u32::max_value()} else {0})),
+                    VT_UINT => return Ok(Variant::UInt(UInt(if b {u32::max_value()} else {0}))),
+                    VT_UI8 => return Ok(Variant::ULongLong(if b {u64::max_value()} else {0})),
+                    _ => Num::Exact(if b {-1} else {0}, 0)
+                }
+            },
+            Source::Str(s) => {
+                match target as u32 {
+                    VT_BOOL => return parse_bool(&s).map(Variant::Bool).ok_or(mismatch),
+                    VT_DATE => return parse_date(&s).map(|d| Variant::Date(Date(d))).ok_or(mismatch),
+                    _ => parse_number(&s).ok_or(mismatch)?
+                }
+            },
+            Source::Num(num) => num
+        };
+        if let Variant::Date(Date(d)) = *self {
+            if target as u32 == VT_BSTR {
+                return format_date(d).map(Variant::BString).ok_or(overflow);
+            }
+        }
+
+        let v = match target as u32 {
+            VT_I1 => Variant::Char(to_integer(num, i8::min_value() as i128, i8::max_value() as i128).ok_or(overflow)? as i8),
+            VT_UI1 => Variant::Byte(to_integer(num, 0, u8::max_value() as i128).ok_or(overflow)? as u8),
+            VT_I2 => Variant::Short(to_integer(num, i16::min_value() as i128, i16::max_value() as i128).ok_or(overflow)? as i16),
+            VT_UI2 => Variant::UShort(to_integer(num, 0, u16::max_value() as i128).ok_or(overflow)? as u16),
+            VT_I4 => Variant::Long(to_integer(num, i32::min_value() as i128, i32::max_value() as i128).ok_or(overflow)? as i32),
+            VT_UI4 => Variant::ULong(to_integer(num, 0, u32::max_value() as i128).ok_or(overflow)? as u32),
+            VT_INT => Variant::Int(Int(to_integer(num, i32::min_value() as i128, i32::max_value() as i128).ok_or(overflow)? as i32)),
+            VT_UINT => Variant::UInt(UInt(to_integer(num, 0, u32::max_value() as i128).ok_or(overflow)? as u32)),
+            VT_I8 => Variant::LongLong(to_integer(num, i64::min_value() as i128, i64::max_value() as i128).ok_or(overflow)? as i64),
+            VT_UI8 => Variant::ULongLong(to_integer(num, 0, u64::max_value() as i128).ok_or(overflow)? as u64),
+            VT_R4 => {
+                let f = to_float(num);
+                if f.is_finite() && f.abs() > ::std::f32::MAX as f64 {
+                    return Err(overflow);
+                }
+                Variant::Float(f as f32)
+            },
+            VT_R8 => Variant::Double(to_float(num)),
+            VT_BOOL => Variant::Bool(match num {
+                Num::Exact(m, _) => m != 0,
+                Num::Float(f) => f != 0.0
+            }),
+            VT_CY => {
+                let c = match num {
+                    Num::Exact(m, scale) => rescale(m, scale, 4),
+                    Num::Float(f) => float_to_integer(f * 10000.0)
+                };
+                match c {
+                    Some(c) if c >= i64::min_value() as i128 && c <= i64::max_value() as i128 => Variant::Currency(Currency(c as i64)),
+                    _ => return Err(overflow)
+                }
+            },
+            VT_DATE => {
+                let d = to_float(num);
+                if !(d >= MIN_DATE && d < MAX_DATE) {
+                    return Err(overflow);
+                }
+                Variant::Date(Date(d))
+            },
+            VT_DECIMAL => {
+                let exact = match num {
+                    Num::Float(f) => float_to_exact(f),
+                    Num::Exact(m, scale) => Some((m, scale))
+                };
+                match exact.and_then(|(m, scale)| exact_to_decimal(m, scale)) {
+                    Some(d) => Variant::Decimal(d),
+                    None => return Err(overflow)
+                }
+            },
+            VT_BSTR => Variant::BString(match (self, num) {
+                (&Variant::Float(_), Num::Float(f)) => format_float(f, 7),
+                (_, Num::Float(f)) => format_float(f, 15),
+                (_, Num::Exact(m, scale)) => format_exact(m, scale)
+            }),
+            _ => {
+                debug!("unsupported coercion from 0x{:x} to 0x{:x}", from, vt);
+                return Err(ClrError::UnsupportedVartype{vt: vt, source: SourceLocation::Variant(line!())})
+            }
+        };
+        Ok(v)
+    }
+
+    //The value behind a by-reference arm, None for everything else
+    fn by_value(&self) -> Option<Variant> {
+        let v = match *self {
+            Variant::PByte(ref b) => Variant::Byte(**b),
+            Variant::PShort(ref b) => Variant::Short(**b),
+            Variant::PLong(ref b) => Variant::Long(**b),
+            Variant::PLongLong(ref b) => Variant::LongLong(**b),
+            Variant::PFloat(ref b) => Variant::Float(**b),
+            Variant::PDouble(ref b) => Variant::Double(**b),
+            Variant::PBool(ref b) => Variant::Bool(**b),
+            Variant::PErrorCode(ref b) => Variant::ErrorCode((**b).clone()),
+            Variant::PCurrency(ref b) => Variant::Currency(**b),
+            Variant::PDate(ref b) => Variant::Date((**b).clone()),
+            Variant::PBString(ref b) => Variant::BString((**b).clone()),
+            Variant::PWString(ref b) => Variant::WString((**b).clone()),
+            Variant::PDecimal(ref b) => Variant::Decimal(**b),
+            Variant::PChar(ref b) => Variant::Char(**b),
+            Variant::PUShort(ref b) => Variant::UShort(**b),
+            Variant::PULong(ref b) => Variant::ULong(**b),
+            Variant::PULongLong(ref b) => Variant::ULongLong(**b),
+            Variant::PInt(ref b) => Variant::Int((**b).clone()),
+            Variant::PUInt(ref b) => Variant::UInt((**b).clone()),
+            Variant::PVariant(ref b) => (**b).clone(),
+            _ => return None
+        };
+        Some(v)
+    }
+}
+
+macro_rules! TRY_INTO {
+    ($({$func:ident, $vt:ident, $out:ty, $pat:pat => $val:expr})*) => {
+        impl Variant {
+            $(
+                pub fn $func(&self) -> Result<$out> {
+                    match self.coerce_to($vt as VARTYPE)? {
+                        $pat => Ok($val),
+                        _ => unreachable!()
+                    }
+                }
+            )*
+        }
+    };
+}
+
+TRY_INTO!{
+    {try_into_i8, VT_I1, i8, Variant::Char(n) => n}
+    {try_into_u8, VT_UI1, u8, Variant::Byte(n) => n}
+    {try_into_i16, VT_I2, i16, Variant::Short(n) => n}
+    {try_into_u16, VT_UI2, u16, Variant::UShort(n) => n}
+    {try_into_i32, VT_I4, i32, Variant::Long(n) => n}
+    {try_into_u32, VT_UI4, u32, Variant::ULong(n) => n}
+    {try_into_i64, VT_I8, i64, Variant::LongLong(n) => n}
+    {try_into_u64, VT_UI8, u64, Variant::ULongLong(n) => n}
+    {try_into_f32, VT_R4, f32, Variant::Float(f) => f}
+    {try_into_f64, VT_R8, f64, Variant::Double(f) => f}
+    {try_into_bool, VT_BOOL, bool, Variant::Bool(b) => b}
+    {try_into_currency, VT_CY, Currency, Variant::Currency(c) => c}
+    {try_into_date, VT_DATE, Date, Variant::Date(d) => d}
+    {try_into_decimal, VT_DECIMAL, Decimal, Variant::Decimal(d) => d}
+}
+
+impl Variant {
+    //WString stays as it is, but comes back lossy as a String
+    pub fn try_into_string(&self) -> Result<String> {
+        match self.coerce_to(VT_BSTR as VARTYPE)? {
+            Variant::BString(s) => Ok(s),
+            Variant::WString(s) => Ok(s.to_string_lossy()),
+            _ => unreachable!()
+        }
+    }
+}
+
+pub fn pow10(n: u32) -> Option<i128> {
+    10i128.checked_pow(n)
+}
+
+//m / d rounded half to even, d > 0
+pub fn div_round_half_even(m: i128, d: i128) -> i128 {
+    let q = m / d;
+    let twice = (m % d).abs() * 2;
+    if twice > d || (twice == d && q % 2 != 0) {
+        q + m.signum()
+    } else {
+        q
+    }
+}
+
+fn round_half_even(f: f64) -> f64 {
+    if (f - f.trunc()).abs() == 0.5 {
+        2.0 * (f / 2.0).round()
+    } else {
+        f.round()
+    }
+}
+
+fn float_to_integer(f: f64) -> Option<i128> {
+    let r = round_half_even(f);
+    //i128 comfortably covers every target, this only guards the cast
+    if r.is_finite() && r.abs() < 1e38 {
+        Some(r as i128)
+    } else {
+        None
+    }
+}
+
+//mantissa * 10^-from as a mantissa at scale to
+fn rescale(m: i128, from: u32, to: u32) -> Option<i128> {
+    if from <= to {
+        m.checked_mul(pow10(to - from)?)
+    } else {
+        match pow10(from - to) {
+            Some(d) => Some(div_round_half_even(m, d)),
+            None => Some(0)
+        }
+    }
+}
+
+fn to_integer(num: Num, min: i128, max: i128) -> Option<i128> {
+    let n = match num {
+        Num::Exact(m, scale) => rescale(m, scale, 0)?,
+        Num::Float(f) => float_to_integer(f)?
+    };
+    if n >= min && n <= max {
+        Some(n)
+    } else {
+        None
+    }
+}
+
+fn to_float(num: Num) -> f64 {
+    match num {
+        //going through the string keeps the conversion correctly rounded
+        Num::Exact(m, scale) => format!("{}e-{}", m, scale).parse().unwrap_or(::std::f64::NAN),
+        Num::Float(f) => f
+    }
+}
+
+//oleaut32 keeps 15 significant digits of a double going into a DECIMAL
+pub fn float_to_exact(f: f64) -> Option<(i128, u32)> {
+    if !f.is_finite() {
+        return None;
+    }
+    match parse_number(&format!("{:.14e}", f))? {
+        Num::Exact(m, scale) => Some(normalize(m, scale)),
+        Num::Float(_) => None
+    }
+}
+
+fn normalize(mut m: i128, mut scale: u32) -> (i128, u32) {
+    while scale > 0 && m % 10 == 0 {
+        m /= 10;
+        scale -= 1;
+    }
+    (m, scale)
+}
+
+pub fn decimal_to_num(d: Decimal) -> Num {
+    let c = build_c_decimal(d);
+    let m = ((c.Hi32 as i128) << 64) | c.Lo64 as i128;
+    let m = if d.is_sign_negative() {-m} else {m};
+    Num::Exact(m, c.scale as u32)
+}
+
+fn exact_to_decimal(mut m: i128, mut scale: u32) -> Option<Decimal> {
+    //drop digits off the end until it fits in 96 bits and 28 places
+    while scale > MAX_DECIMAL_SCALE || m.abs() >= 1i128 << 96 {
+        if scale == 0 {
+            return None;
+        }
+        m = div_round_half_even(m, 10);
+        scale -= 1;
+    }
+    let abs = m.abs() as u128;
+    Some(Decimal::from_parts(abs as u32, (abs >> 32) as u32, (abs >> 64) as u32, m < 0, scale))
+}
+
+//Plain decimal notation with an optional exponent, surrounding whitespace allowed
+pub fn parse_number(s: &str) -> Option<Num> {
+    let s = s.trim();
+    let (mantissa, exp) = match s.find(|c| c == 'e' || c == 'E') {
+        Some(ix) => (&s[..ix], s[ix + 1..].parse::<i32>().ok()?),
+        None => (s, 0)
+    };
+    let (neg, digits) = if mantissa.starts_with('-') {
+        (true, &mantissa[1..])
+    } else if mantissa.starts_with('+') {
+        (false, &mantissa[1..])
+    } else {
+        (false, mantissa)
+    };
+    let (int_part, frac_part) = match digits.find('.') {
+        Some(ix) => (&digits[..ix], &digits[ix + 1..]),
+        None => (digits, "")
+    };
+    if (int_part.is_empty() && frac_part.is_empty()) ||
+        !int_part.chars().chain(frac_part.chars()).all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let exact = int_part.chars().chain(frac_part.chars()).fold(Some(0i128), |acc, c| {
+        acc.and_then(|m| m.checked_mul(10)).and_then(|m| m.checked_add(c.to_digit(10).unwrap() as i128))
+    });
+    let scale = frac_part.len() as i64 - exp as i64;
+    let exact = exact.and_then(|m| {
+        let m = if neg {-m} else {m};
+        if scale < 0 {
+            rescale(m, 0, (-scale) as u32).map(|m| (m, 0))
+        } else if scale > 38 {
+            None
+        } else {
+            Some((m, scale as u32))
+        }
+    });
+    match exact {
+        Some((m, scale)) => Some(Num::Exact(m, scale)),
+        None => s.parse::<f64>().ok().map(Num::Float)
+    }
+}
+
+fn parse_bool(s: &str) -> Option<bool> {
+    let s = s.trim();
+    if s.eq_ignore_ascii_case("true") || s.eq_ignore_ascii_case("#true#") {
+        return Some(true);
+    }
+    if s.eq_ignore_ascii_case("false") || s.eq_ignore_ascii_case("#false#") {
+        return Some(false);
+    }
+    match parse_number(s)? {
+        Num::Exact(m, _) => Some(m != 0),
+        Num::Float(f) => Some(f != 0.0)
+    }
+}
+
+pub fn format_exact(m: i128, scale: u32) -> String {
+    let (m, scale) = normalize(m, scale);
+    let digits = m.abs().to_string();
+    let sign = if m < 0 {"-"} else {""};
+    let scale = scale as usize;
+    if scale == 0 {
+        format!("{}{}", sign, digits)
+    } else if digits.len() > scale {
+        let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+        format!("{}{}.{}", sign, int_part, frac_part)
+    } else {
+        format!("{}0.{}{}", sign, "0".repeat(scale - digits.len()), digits)
+    }
+}
+
+//Like printf's %G with the given number of significant digits, which is what oleaut32 produces
+fn format_float(f: f64, precision: usize) -> String {
+    if f == 0.0 || !f.is_finite() {
+        return if f == 0.0 {String::from("0")} else {f.to_string()};
+    }
+    let sci = format!("{:.*e}", precision - 1, f);
+    let (mantissa, exp) = sci.split_at(sci.find('e').unwrap());
+    let exp: i32 = exp[1..].parse().unwrap();
+    let sign = if f < 0.0 {"-"} else {""};
+    let digits: String = mantissa.chars().filter(|c| c.is_ascii_digit()).collect();
+    let digits = digits.trim_end_matches('0');
+    let digits = if digits.is_empty() {"0"} else {digits};
+    if exp < -4 || exp >= precision as i32 {
+        let (first, rest) = digits.split_at(1);
+        let point = if rest.is_empty() {""} else {"."};
+        format!("{}{}{}{}E{}{:02}", sign, first, point, rest, if exp < 0 {"-"} else {"+"}, exp.abs())
+    } else if exp < 0 {
+        format!("{}0.{}{}", sign, "0".repeat((-exp - 1) as usize), digits)
+    } else {
+        let int_len = exp as usize + 1;
+        if digits.len() <= int_len {
+            format!("{}{}{}", sign, digits, "0".repeat(int_len - digits.len()))
+        } else {
+            let (int_part, frac_part) = digits.split_at(int_len);
+            format!("{}{}.{}", sign, int_part, frac_part)
+        }
+    }
+}
+
+//Howard Hinnant's days_from_civil, days since 1970-01-01
+pub fn days_from_civil(y: i64, m: u32, d: u32) -> i64 {
+    let y = if m <= 2 {y - 1} else {y};
+    let era = if y >= 0 {y} else {y - 399} / 400;
+    let yoe = y - era * 400;
+    let mp = (m as i64 + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + d as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+//(year, month, day) for days since 1970-01-01
+pub fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = if z >= 0 {z} else {z - 146096} / 146097;
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 {mp + 3} else {mp - 9} as u32;
+    (if m <= 2 {yoe + era * 400 + 1} else {yoe + era * 400}, m, d)
+}
+
+fn days_in_month(y: i64, m: u32) -> u32 {
+    match m {
+        2 if (y % 4 == 0 && y % 100 != 0) || y % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31
+    }
+}
+
+//A DATE as its calendar day (days since 1899-12-30) and seconds into that day.
+//The integer part is the day and the fraction the time for negative DATEs too, so -1.25 is 1899-12-29 06:00.
+pub fn split_date(d: f64) -> Option<(i64, i64)> {
+    if !(d >= MIN_DATE && d < MAX_DATE) {
+        return None;
+    }
+    let day = d.trunc();
+    let secs = ((d - day).abs() * SECONDS_PER_DAY as f64).round() as i64;
+    if secs == SECONDS_PER_DAY {
+        Some((day as i64 + 1, 0))
+    } else {
+        Some((day as i64, secs))
+    }
+}
+
+pub fn join_date(day: i64, secs: i64) -> f64 {
+    let time = secs as f64 / SECONDS_PER_DAY as f64;
+    if day < 0 {day as f64 - time} else {day as f64 + time}
+}
+
+//DATE 0 (1899-12-30) with a time prints as just the time, midnight prints as just the date
+fn format_date(d: f64) -> Option<String> {
+    let (day, secs) = split_date(d)?;
+    let time = format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60);
+    if day == 0 {
+        return Some(time);
+    }
+    let (y, m, dd) = civil_from_days(day - UNIX_EPOCH_DATE);
+    let date = format!("{:04}-{:02}-{:02}", y, m, dd);
+    if secs == 0 {
+        Some(date)
+    } else {
+        Some(format!("{} {}", date, time))
+    }
+}
+
+//YYYY-MM-DD or M/D/YYYY, a time of H:MM[:SS] with an optional AM/PM, or a date and a time.
+fn parse_date(s: &str) -> Option<f64> {
+    let s = s.trim();
+    let mut day: Option<i64> = None;
+    let mut secs: Option<i64> = None;
+    let mut parts: Vec<&str> = s.split(|c: char| c.is_whitespace() || c == 'T').filter(|p| !p.is_empty()).collect();
+    let meridiem = match parts.last().map(|p| p.to_ascii_uppercase()) {
+        Some(ref p) if p == "AM" => Some(false),
+        Some(ref p) if p == "PM" => Some(true),
+        _ => None
+    };
+    if meridiem.is_some() {
+        parts.pop();
+    }
+    for part in parts {
+        if part.contains(':') && secs.is_none() {
+            secs = Some(parse_time(part, meridiem)?);
+        } else if day.is_none() {
+            day = Some(parse_day(part)?);
+        } else {
+            return None;
+        }
+    }
+    if meridiem.is_some() && secs.is_none() {
+        return None;
+    }
+    if day.is_none() && secs.is_none() {
+        return None;
+    }
+    let d = join_date(day.unwrap_or(0), secs.unwrap_or(0));
+    if d >= MIN_DATE && d < MAX_DATE {Some(d)} else {None}
+}
+
+fn parse_day(s: &str) -> Option<i64> {
+    let fields: Vec<&str> = if s.contains('-') {s.split('-').collect()} else {s.split('/').collect()};
+    if fields.len() != 3 {
+        return None;
+    }
+    let (y, m, d) = if s.contains('-') {
+        (fields[0].parse::<i64>().ok()?, fields[1].parse::<u32>().ok()?, fields[2].parse::<u32>().ok()?)
+    } else {
+        (fields[2].parse::<i64>().ok()?, fields[0].parse::<u32>().ok()?, fields[1].parse::<u32>().ok()?)
+    };
+    if y < 100 || y > 9999 || m < 1 || m > 12 || d < 1 || d > days_in_month(y, m) {
+        return None;
+    }
+    Some(days_from_civil(y, m, d) + UNIX_EPOCH_DATE)
+}
+
+fn parse_time(s: &str, meridiem: Option<bool>) -> Option<i64> {
+    let fields: Vec<&str> = s.split(':').collect();
+    if fields.len() < 2 || fields.len() > 3 {
+        return None;
+    }
+    let mut h: i64 = fields[0].parse().ok()?;
+    let m: i64 = fields[1].parse().ok()?;
+    let sec: i64 = if fields.len() == 3 {fields[2].parse().ok()?} else {0};
+    if let Some(pm) = meridiem {
+        if h < 1 || h > 12 {
+            return None;
+        }
+        h = h % 12 + if pm {12} else {0};
+    }
+    if h < 0 || h > 23 || m < 0 || m > 59 || sec < 0 || sec > 59 {
+        return None;
+    }
+    Some(h * 3600 + m * 60 + sec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use winapi::shared::wtypes::VT_UNKNOWN;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Variant::Long(5).coerce_to(VT_R8 as VARTYPE).unwrap(), Variant::Double(5.0));
+        assert_eq!(Variant::Double(2.5).try_into_i32().unwrap(), 2);
+        assert_eq!(Variant::Double(3.5).try_into_i32().unwrap(), 4);
+        assert_eq!(Variant::Double(-2.5).try_into_i16().unwrap(), -2);
+        assert_eq!(Variant::Empty(()).try_into_i64().unwrap(), 0);
+        assert_eq!(Variant::PLong(Box::new(7)).try_into_u8().unwrap(), 7);
+        match Variant::Long(300).coerce_to(VT_UI1 as VARTYPE) {
+            Err(ClrError::Overflow{from, to}) => {
+                assert_eq!(from, VT_I4 as VARTYPE);
+                assert_eq!(to, VT_UI1 as VARTYPE);
+            },
+            other => panic!("Unexpected result: {:?}", other)
+        }
+        assert!(Variant::Short(-1).try_into_u32().is_err());
+        assert!(Variant::Double(1e40).try_into_f32().is_err());
+    }
+
+    #[test]
+    fn bools() {
+        assert_eq!(Variant::Bool(true).try_into_i32().unwrap(), -1);
+        assert_eq!(Variant::Bool(false).try_into_f64().unwrap(), 0.0);
+        assert_eq!(Variant::Bool(true).try_into_u8().unwrap(), 255);
+        assert_eq!(Variant::Bool(true).try_into_string().unwrap(), "-1");
+        assert_eq!(Variant::Long(-3).try_into_bool().unwrap(), true);
+        assert_eq!(Variant::BString(String::from("False")).try_into_bool().unwrap(), false);
+        assert_eq!(Variant::BString(String::from("0.0")).try_into_bool().unwrap(), false);
+        assert!(Variant::BString(String::from("maybe")).try_into_bool().is_err());
+    }
+
+    #[test]
+    fn strings() {
+        let d = Variant::BString(String::from("3.14")).try_into_decimal().unwrap();
+        assert_eq!(d, Decimal::from_str("3.14").unwrap());
+        assert_eq!(Variant::BString(String::from(" 42 ")).try_into_i32().unwrap(), 42);
+        assert_eq!(Variant::BString(String::from("1.5e3")).try_into_i64().unwrap(), 1500);
+        assert_eq!(Variant::BString(String::from("2.5")).try_into_u8().unwrap(), 2);
+        assert!(Variant::BString(String::from("300")).try_into_u8().is_err());
+        match Variant::BString(String::from("abc")).coerce_to(VT_I4 as VARTYPE) {
+            Err(ClrError::Coercion{..}) => {},
+            other => panic!("Unexpected result: {:?}", other)
+        }
+        assert_eq!(Variant::Double(0.1 + 0.2).try_into_string().unwrap(), "0.3");
+        assert_eq!(Variant::Float(0.1).try_into_string().unwrap(), "0.1");
+        assert_eq!(Variant::Double(1e20).try_into_string().unwrap(), "1E+20");
+        assert_eq!(Variant::Double(-0.00001).try_into_string().unwrap(), "-1E-05");
+        assert_eq!(Variant::Double(123456.5).try_into_string().unwrap(), "123456.5");
+        assert_eq!(Variant::Decimal(Decimal::new(150, 2)).try_into_string().unwrap(), "1.5");
+    }
+
+    #[test]
+    fn currency() {
+        assert_eq!(Variant::Long(3).try_into_currency().unwrap(), Currency(30000));
+        assert_eq!(Variant::Double(1.23456).try_into_currency().unwrap(), Currency(12346));
+        assert_eq!(Variant::Currency(Currency(25000)).try_into_i32().unwrap(), 2);
+        assert_eq!(Variant::Currency(Currency(35000)).try_into_i32().unwrap(), 4);
+        assert_eq!(Variant::Currency(Currency(-12345)).try_into_string().unwrap(), "-1.2345");
+        assert_eq!(Variant::Currency(Currency(12345)).try_into_decimal().unwrap(), Decimal::new(12345, 4));
+        assert_eq!(Variant::BString(String::from("9.99")).try_into_currency().unwrap(), Currency(99900));
+        assert!(Variant::Double(1e16).try_into_currency().is_err());
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(Variant::Date(Date(0.0)).try_into_string().unwrap(), "00:00:00");
+        assert_eq!(Variant::Date(Date(43101.5)).try_into_string().unwrap(), "2018-01-01 12:00:00");
+        assert_eq!(Variant::Date(Date(-1.25)).try_into_string().unwrap(), "1899-12-29 06:00:00");
+        assert_eq!(Variant::Date(Date(2.0)).try_into_string().unwrap(), "1900-01-01");
+        assert_eq!(Variant::BString(String::from("2018-01-01 12:00")).try_into_date().unwrap(), Date(43101.5));
+        assert_eq!(Variant::BString(String::from("1/1/2018 12:00:00 PM")).try_into_date().unwrap(), Date(43101.5));
+        assert_eq!(Variant::BString(String::from("1899-12-29T06:00:00")).try_into_date().unwrap(), Date(-1.25));
+        assert_eq!(Variant::BString(String::from("06:00")).try_into_date().unwrap(), Date(0.25));
+        assert!(Variant::BString(String::from("2018-02-30")).try_into_date().is_err());
+        assert!(Variant::Double(3e6).try_into_date().is_err());
+        assert_eq!(Variant::Date(Date(43101.75)).try_into_f64().unwrap(), 43101.75);
+    }
+
+    #[test]
+    fn mismatches() {
+        assert!(Variant::Null(()).try_into_i32().is_err());
+        assert_eq!(Variant::Null(()).coerce_to(VT_NULL as VARTYPE).unwrap(), Variant::Null(()));
+        assert_eq!(Variant::Empty(()).coerce_to(VT_NULL as VARTYPE).unwrap(), Variant::Null(()));
+        assert!(Variant::Long(1).coerce_to(VT_UNKNOWN as VARTYPE).is_err());
+        assert_eq!(Variant::Long(1).coerce_to(VT_I4 as VARTYPE).unwrap(), Variant::Long(1));
+    }
+}
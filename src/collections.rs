@@ -31,6 +31,7 @@ use mscorlib_sys::system::collections::{DictionaryEntry, ICollection, IComparer,
 IEnumerable, IEnumerator, IEqualityComparer, IHashCodeProvider, IList};
 
 use new_variant::Variant;
+use owned_variant::OwnedVariant;
 use wrappers::PtrContainer;
 use result::{ClrError, SourceLocation, Result};
 
@@ -85,10 +86,10 @@ pub trait Comparable where Self: PtrContainer<IComparable> {
         where R: Comparable + PtrContainer<IComparable>
     {
         let lhs_ptr: *mut IComparable = self.ptr_mut();
-        let rhs_vt : VARIANT = rhs.into_variant().into_c_variant();
+        let rhs_vt = OwnedVariant::try_new(rhs.into_variant()?)?;
         let mut ret: c_long = 0;
         let hr = unsafe {
-            (*lhs_ptr).CompareTo(rhs_vt, &mut ret)
+            (*lhs_ptr).CompareTo(*rhs_vt.as_ptr(), &mut ret)
         };
 
         SUCCEEDED!(hr, ret, IComparable)
@@ -101,11 +102,11 @@ pub trait Comparer where Self: PtrContainer<IComparer> {
               R: PtrContainer<TRight>
     {
         let p = self.ptr_mut();
-        let lhs_vt = lhs.into_variant().into_c_variant();
-        let rhs_vt = rhs.into_variant().into_c_variant();
+        let lhs_vt = OwnedVariant::try_new(lhs.into_variant()?)?;
+        let rhs_vt = OwnedVariant::try_new(rhs.into_variant()?)?;
         let mut ret: c_long = 0;
         let hr = unsafe {
-            (*p).Compare(lhs_vt, rhs_vt, &mut ret)
+            (*p).Compare(*lhs_vt.as_ptr(), *rhs_vt.as_ptr(), &mut ret)
         };
         SUCCEEDED!(hr, ret, IComparer)
     }
@@ -117,12 +118,13 @@ pub trait Dictionary where Self: PtrContainer<IDictionary> {
               V: PtrContainer<TDispatch2>
     {
         let p = self.ptr_mut();
-        let vt = key.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(key.into_variant()?)?;
         let mut ret: VARIANT = unsafe {mem::zeroed()};
         let hr = unsafe {
-            (*p).get_Item(vt, &mut ret)
+            (*p).get_Item(*vt.as_ptr(), &mut ret)
         };
-        SUCCEEDED!(hr, Variant::from_c_variant(ret), IDictionary)
+        let ret = unsafe {OwnedVariant::from_raw(ret)};
+        SUCCEEDED!(hr, ret.into_variant()?, IDictionary)
     }
 
     fn item_mut<K, V, TDispatch, TDispatch2>(&mut self, key: K, value: V) -> Result<()>
@@ -130,10 +132,10 @@ pub trait Dictionary where Self: PtrContainer<IDictionary> {
               V: PtrContainer<TDispatch2>
     {
         let p = self.ptr_mut();
-        let kvt = key.into_variant().into_c_variant();
-        let vvt = value.into_variant().into_c_variant();
+        let kvt = OwnedVariant::try_new(key.into_variant()?)?;
+        let vvt = OwnedVariant::try_new(value.into_variant()?)?;
         let hr = unsafe {
-            (*p).putref_Item(kvt, vvt)
+            (*p).putref_Item(*kvt.as_ptr(), *vvt.as_ptr())
         };
         SUCCEEDED!(hr, (), IDictionary)
     }
@@ -145,7 +147,7 @@ pub trait Dictionary where Self: PtrContainer<IDictionary> {
         let hr = unsafe {
             (*p).get_Keys(&mut ic)
         };
-        SUCCEEDED!(hr, C::from(ic), IDictionary)
+        SUCCEEDED!(hr, unsafe {C::from_raw(ic)}, IDictionary)
     }
 
     fn values<C: Collection>(&self) -> Result<C>
@@ -155,17 +157,17 @@ pub trait Dictionary where Self: PtrContainer<IDictionary> {
         let hr = unsafe {
             (*p).get_Values(&mut pic) 
         };
-        SUCCEEDED!(hr, C::from(pic), IDictionary)
+        SUCCEEDED!(hr, unsafe {C::from_raw(pic)}, IDictionary)
     }
 
     fn contains<TOut, V>(&self, obj: V) -> Result<bool>
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = obj.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(obj.into_variant()?)?;
         let mut pb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).Contains(vt, &mut pb)
+            (*p).Contains(*vt.as_ptr(), &mut pb)
         };
         SUCCEEDED!(hr, pb < 0, IDictionary)
     }
@@ -174,11 +176,11 @@ pub trait Dictionary where Self: PtrContainer<IDictionary> {
         where K: PtrContainer<TKey>,
               V: PtrContainer<TValue>
     {
-        let k = key.into_variant().into_c_variant();
-        let v = value.into_variant().into_c_variant();
+        let k = OwnedVariant::try_new(key.into_variant()?)?;
+        let v = OwnedVariant::try_new(value.into_variant()?)?;
         let p = self.ptr_mut();
         let hr = unsafe {
-            (*p).Add(k, v)
+            (*p).Add(*k.as_ptr(), *v.as_ptr())
         };
         SUCCEEDED!(hr, (), IDictionary)
     }
@@ -224,9 +226,9 @@ pub trait Dictionary where Self: PtrContainer<IDictionary> {
         where K: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = key.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(key.into_variant()?)?;
         let hr = unsafe {
-            (*p).Remove(vt)
+            (*p).Remove(*vt.as_ptr())
         };
         SUCCEEDED!(hr, (), IDictionary)
     }
@@ -240,7 +242,8 @@ pub trait DictionaryEnumerator where Self: PtrContainer<IDictionaryEnumerator> {
         let hr = unsafe {
             (*p).get_key(&mut vt)
         };
-        SUCCEEDED!(hr, Variant::from_c_variant(vt), IDictionaryEnumerator)
+        let vt = unsafe {OwnedVariant::from_raw(vt)};
+        SUCCEEDED!(hr, vt.into_variant()?, IDictionaryEnumerator)
     }
 
     fn value<DV>(&self) -> Result<Variant>
@@ -250,7 +253,8 @@ pub trait DictionaryEnumerator where Self: PtrContainer<IDictionaryEnumerator> {
         let hr = unsafe {
             (*p).get_val(&mut vt)
         };
-        SUCCEEDED!(hr, Variant::from_c_variant(vt), IDictionaryEnumerator)
+        let vt = unsafe {OwnedVariant::from_raw(vt)};
+        SUCCEEDED!(hr, vt.into_variant()?, IDictionaryEnumerator)
     }
 
     fn entry<DE>(&self) -> Result<DE>
@@ -275,7 +279,7 @@ pub trait Enumerable where Self: PtrContainer<IEnumerable> {
             (*p).GetEnumerator(&mut pie)
         };
 
-        SUCCEEDED!(hr, EN::from(pie), IEnumerable)
+        SUCCEEDED!(hr, unsafe {EN::from_raw(pie)}, IEnumerable)
     }
 }
 
@@ -296,7 +300,8 @@ pub trait Enumerator where Self: PtrContainer<IEnumerator> {
         let hr = unsafe {
             (*p).get_Current(&mut vt) 
         };
-        SUCCEEDED!(hr, Variant::from_c_variant(vt),IEnumerator)
+        let vt = unsafe {OwnedVariant::from_raw(vt)};
+        SUCCEEDED!(hr, vt.into_variant()?, IEnumerator)
     }
 
     fn reset(&self) -> Result<()>{
@@ -314,12 +319,12 @@ pub trait EqualityComparer where Self: PtrContainer<IEqualityComparer> {
               Y: PtrContainer<TOut2>, 
     {
         let p = self.ptr_mut();
-        let xvt = x.into_variant().into_c_variant();
-        let yvt = y.into_variant().into_c_variant();
+        let xvt = OwnedVariant::try_new(x.into_variant()?)?;
+        let yvt = OwnedVariant::try_new(y.into_variant()?)?;
 
         let mut vb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).Equals(xvt, yvt, &mut vb)
+            (*p).Equals(*xvt.as_ptr(), *yvt.as_ptr(), &mut vb)
         };
         SUCCEEDED!(hr, vb < 0, IEqualityComparer)
     }
@@ -328,10 +333,10 @@ pub trait EqualityComparer where Self: PtrContainer<IEqualityComparer> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = obj.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(obj.into_variant()?)?;
         let mut cl: c_long = 0;
         let hr = unsafe {
-            (*p).GetHashCode(vt, &mut cl)
+            (*p).GetHashCode(*vt.as_ptr(), &mut cl)
         };
         SUCCEEDED!(hr, cl, IEqualityComparer)
     }
@@ -342,10 +347,10 @@ pub trait HashCodeProvider where Self: PtrContainer<IHashCodeProvider> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = obj.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(obj.into_variant()?)?;
         let mut cl: c_long = 0;
         let hr = unsafe {
-            (*p).GetHashCode(vt, &mut cl)
+            (*p).GetHashCode(*vt.as_ptr(), &mut cl)
         };
         SUCCEEDED!(hr, cl, IHashCodeProvider)
     }
@@ -359,16 +364,17 @@ pub trait List where Self: PtrContainer<IList> {
         let hr = unsafe {
             (*p).get_Item(index as c_long, &mut v)
         };
-        SUCCEEDED!(hr, Variant::from_c_variant(v), IList)
+        let v = unsafe {OwnedVariant::from_raw(v)};
+        SUCCEEDED!(hr, v.into_variant()?, IList)
     }
 
     fn item_mut<V, TOut>(&self, index: i32, value: V) -> Result<()>
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let hr = unsafe {
-            (*p).putref_Item(index, vt)
+            (*p).putref_Item(index, *vt.as_ptr())
         };
         SUCCEEDED!(hr, (), IList)
     }
@@ -377,10 +383,10 @@ pub trait List where Self: PtrContainer<IList> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let mut ret: c_long = 0;
         let hr = unsafe {
-            (*p).Add(vt, &mut ret)
+            (*p).Add(*vt.as_ptr(), &mut ret)
         };
         SUCCEEDED!(hr, ret, IList)
     }
@@ -389,10 +395,10 @@ pub trait List where Self: PtrContainer<IList> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let mut vb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).Contains(vt, &mut vb)
+            (*p).Contains(*vt.as_ptr(), &mut vb)
         };
         SUCCEEDED!(hr, vb < 0, IList)
     }
@@ -427,10 +433,10 @@ pub trait List where Self: PtrContainer<IList> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let mut cl: c_long = 0;
         let hr = unsafe {
-            (*p).IndexOf(vt, &mut cl)
+            (*p).IndexOf(*vt.as_ptr(), &mut cl)
         };
         SUCCEEDED!(hr, cl, IList)
     }
@@ -439,9 +445,9 @@ pub trait List where Self: PtrContainer<IList> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let hr = unsafe {
-            (*p).Insert(index, vt)
+            (*p).Insert(index, *vt.as_ptr())
         };
         SUCCEEDED!(hr, (), IList)
     } 
@@ -450,9 +456,9 @@ pub trait List where Self: PtrContainer<IList> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let hr = unsafe {
-            (*p).Remove(vt)
+            (*p).Remove(*vt.as_ptr())
         };
         SUCCEEDED!(hr, (), IList)
     }
@@ -0,0 +1,402 @@
+// com_ptr.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Every interface starts with the IUnknown vtable, so any interface pointer can be
+//AddRef'd, Released and QueryInterface'd through a *mut IUnknown.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::ptr;
+
+use winapi::Interface;
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::GUID;
+use winapi::shared::winerror::{E_NOINTERFACE, E_POINTER};
+use winapi::um::unknwnbase::IUnknown;
+
+use result::{ClrError, SourceLocation, Result};
+
+//Owns one reference to a COM interface, released on drop. Clones take a reference of their own.
+//May be null, as [out] parameters are when a call fails, in which case it does nothing.
+pub struct ComPtr<T: Interface> {
+    ptr: *mut T,
+}
+
+impl<T: Interface> ComPtr<T> {
+    //Takes over the reference p holds, eg. one returned through an [out] parameter
+    pub unsafe fn from_raw(p: *mut T) -> ComPtr<T> {
+        ComPtr {
+            ptr: p
+        }
+    }
+
+    //Takes a reference of its own on a borrowed pointer
+    pub unsafe fn from_raw_add_ref(p: *mut T) -> ComPtr<T> {
+        if !p.is_null() {
+            (*(p as *mut IUnknown)).AddRef();
+        }
+        ComPtr::from_raw(p)
+    }
+
+    pub fn null() -> ComPtr<T> {
+        ComPtr {
+            ptr: ptr::null_mut()
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.ptr.is_null()
+    }
+
+    //Lends the pointer, the reference stays with the ComPtr
+    pub fn as_ptr(&self) -> *mut T {
+        self.ptr
+    }
+
+    pub fn as_unknown(&self) -> *mut IUnknown {
+        self.ptr as *mut IUnknown
+    }
+
+    //Gives up the reference without releasing it
+    pub fn into_raw(self) -> *mut T {
+        let p = self.ptr;
+        ::std::mem::forget(self);
+        p
+    }
+
+    //QueryInterface for U's IID. The result holds the reference QueryInterface took.
+    pub fn query_interface<U: Interface>(&self) -> Result<ComPtr<U>> {
+        unsafe {query_interface(self.ptr)}
+    }
+}
+
+//QueryInterface on a borrowed pointer to any interface, in place of transmuting it.
+//E_NOINTERFACE becomes ClrError::NoInterface, p must be a valid interface pointer or null.
+pub unsafe fn query_interface<T, U: Interface>(p: *mut T) -> Result<ComPtr<U>> {
+    if p.is_null() {
+        return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::IUnknown(line!())});
+    }
+    let iid = U::uuidof();
+    let mut pv: *mut c_void = ptr::null_mut();
+    let hr = (*(p as *mut IUnknown)).QueryInterface(&iid, &mut pv);
+    trace!("QueryInterface({:p}, {}) - hr = 0x{:x}", p, iid_string(&iid), hr);
+    if hr == E_NOINTERFACE {
+        debug!("QueryInterface({:p}, {}) - no such interface", p, iid_string(&iid));
+        return Err(ClrError::NoInterface{iid: iid_string(&iid)});
+    }
+    SUCCEEDED!(hr, ComPtr::from_raw(pv as *mut U), IUnknown)
+}
+
+//Registry format, eg. {BCA8B44D-AAD6-3A86-8AB7-03349F4F2DA2}
+fn iid_string(iid: &GUID) -> String {
+    format!("{{{:08X}-{:04X}-{:04X}-{:02X}{:02X}-{:02X}{:02X}{:02X}{:02X}{:02X}{:02X}}}", 
+        iid.Data1, iid.Data2, iid.Data3, 
+        iid.Data4[0], iid.Data4[1], iid.Data4[2], iid.Data4[3], 
+        iid.Data4[4], iid.Data4[5], iid.Data4[6], iid.Data4[7])
+}
+
+impl<T: Interface> Clone for ComPtr<T> {
+    fn clone(&self) -> ComPtr<T> {
+        unsafe {ComPtr::from_raw_add_ref(self.ptr)}
+    }
+}
+
+impl<T: Interface> Drop for ComPtr<T> {
+    fn drop(&mut self) {
+        if !self.ptr.is_null() {
+            unsafe {
+                (*self.as_unknown()).Release();
+            }
+        }
+    }
+}
+
+impl<T: Interface> Default for ComPtr<T> {
+    fn default() -> ComPtr<T> {
+        ComPtr::null()
+    }
+}
+
+impl<T: Interface> fmt::Debug for ComPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ComPtr({:p})", self.ptr)
+    }
+}
+
+impl<T: Interface> PartialEq for ComPtr<T> {
+    fn eq(&self, other: &ComPtr<T>) -> bool {
+        self.ptr == other.ptr
+    }
+}
+
+impl<T: Interface> PartialOrd for ComPtr<T> {
+    fn partial_cmp(&self, other: &ComPtr<T>) -> Option<Ordering> {
+        self.ptr.partial_cmp(&other.ptr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::cell::Cell;
+
+    use winapi::shared::guiddef::{IsEqualGUID, REFIID};
+    use winapi::shared::minwindef::ULONG;
+    use winapi::shared::winerror::{HRESULT, S_OK};
+    use winapi::shared::wtypes::{VT_I4, VT_UNKNOWN};
+    use winapi::um::oaidl::{IDispatch, LPSAFEARRAY};
+    use winapi::um::unknwnbase::IUnknownVtbl;
+
+    use mscorlib_sys::system::reflection::{_Assembly, _MemberInfo, _Type};
+
+    use new_safearray::{RSafeArray, RSafeArrayNd, SafeArrayDestroy};
+    use new_variant::Variant;
+    use owned_variant::OwnedVariant;
+    use wrappers::{ClrType, PtrContainer};
+
+    //The count lives outside the object, the vtable methods only get to see its vtable pointer
+    struct MockUnknown {
+        _vtbl: *const IUnknownVtbl
+    }
+
+    thread_local! {
+        static REFS: Cell<ULONG> = Cell::new(1);
+    }
+
+    fn refs() -> ULONG {
+        REFS.with(Cell::get)
+    }
+
+    //Answers for IUnknown, IDispatch, _Type and _MemberInfo, as a System.Type would
+    unsafe extern "system" fn mock_query_interface(this: *mut IUnknown, riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+        let known = [IUnknown::uuidof(), IDispatch::uuidof(), _Type::uuidof(), _MemberInfo::uuidof()];
+        if known.iter().any(|iid| IsEqualGUID(iid, &*riid)) {
+            mock_add_ref(this);
+            *ppv = this as *mut c_void;
+            S_OK
+        }
+        else {
+            *ppv = ptr::null_mut();
+            E_NOINTERFACE
+        }
+    }
+    unsafe extern "system" fn mock_add_ref(_: *mut IUnknown) -> ULONG {
+        REFS.with(|refs| {
+            refs.set(refs.get() + 1);
+            refs.get()
+        })
+    }
+    unsafe extern "system" fn mock_release(_: *mut IUnknown) -> ULONG {
+        REFS.with(|refs| {
+            refs.set(refs.get() - 1);
+            refs.get()
+        })
+    }
+
+    static VTBL: IUnknownVtbl = IUnknownVtbl {
+        QueryInterface: mock_query_interface,
+        AddRef: mock_add_ref,
+        Release: mock_release
+    };
+
+    fn extract<M: PtrContainer<_Type>>(psa: LPSAFEARRAY) -> Result<Vec<M>> {
+        Ok(EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Type, M})
+    }
+
+    fn extract_assemblies<M: PtrContainer<_Assembly>>(psa: LPSAFEARRAY) -> Result<Vec<M>> {
+        Ok(EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Assembly, M})
+    }
+
+    #[test]
+    fn clone_and_drop() {
+        let mut mock = MockUnknown {_vtbl: &VTBL};
+        let p = &mut mock as *mut MockUnknown as *mut _Type;
+        {
+            let t = unsafe {ComPtr::from_raw(p)};
+            assert_eq!(refs(), 1);
+            let t2 = t.clone();
+            assert_eq!(refs(), 2);
+            assert_eq!(t, t2);
+            drop(t2);
+            assert_eq!(refs(), 1);
+            let borrowed = unsafe {ComPtr::from_raw_add_ref(t.as_ptr())};
+            assert_eq!(refs(), 2);
+            assert_eq!(borrowed.into_raw(), p);
+            assert_eq!(refs(), 2);
+            unsafe {(*(p as *mut IUnknown)).Release();}
+        }
+        assert_eq!(refs(), 0);
+    }
+
+    #[test]
+    fn casts() {
+        let mut mock = MockUnknown {_vtbl: &VTBL};
+        let p = &mut mock as *mut MockUnknown as *mut _Type;
+        {
+            let t = unsafe {ComPtr::from_raw(p)};
+            let member: ComPtr<_MemberInfo> = t.query_interface().unwrap();
+            let disp: ComPtr<IDispatch> = member.query_interface().unwrap();
+            let unk: ComPtr<IUnknown> = disp.query_interface().unwrap();
+            assert_eq!(unk.as_ptr(), t.as_unknown());
+            assert_eq!(refs(), 4);
+            match t.query_interface::<_Assembly>() {
+                Err(ClrError::NoInterface{iid}) => assert_eq!(iid, iid_string(&_Assembly::uuidof())),
+                other => panic!("expected NoInterface, got {:?}", other)
+            }
+            assert_eq!(refs(), 4);
+        }
+        assert_eq!(refs(), 0);
+
+        let null: ComPtr<_Type> = ComPtr::null();
+        assert!(null.query_interface::<IUnknown>().is_err());
+        drop(null.clone());
+    }
+
+    #[test]
+    fn format_iid() {
+        assert_eq!(iid_string(&_Type::uuidof()), "{BCA8B44D-AAD6-3A86-8AB7-03349F4F2DA2}");
+    }
+
+    #[test]
+    fn containers() {
+        let mut mock = MockUnknown {_vtbl: &VTBL};
+        let p = &mut mock as *mut MockUnknown as *mut _Type;
+        let borrowed: ClrType = PtrContainer::from(p);
+        assert_eq!(refs(), 2);
+        drop(borrowed);
+        let t: ClrType = unsafe {PtrContainer::from_raw(p)};
+        match t.into_variant().unwrap() {
+            Variant::Unknown(punk) => assert_eq!(punk, p as *mut IUnknown),
+            other => panic!("expected an IUnknown, got {:?}", other)
+        }
+        assert_eq!(refs(), 1);
+
+        //the array keeps the references QueryInterface took after the ClrTypes are gone, 
+        //then hands them on to the SAFEARRAY
+        let rsa = RSafeArray::from_vec_dispatch(vec![t.clone(), t.clone()]).unwrap();
+        if let RSafeArray::Dispatchs(ref disps, _) = rsa {
+            assert!(disps.iter().all(|disp| disp.as_ptr() == p as *mut IDispatch));
+        }
+        assert_eq!(refs(), 3);
+        let psa = rsa.try_into_ptr().unwrap();
+        assert_eq!(refs(), 3);
+        assert_eq!(unsafe {SafeArrayDestroy(psa)}, S_OK);
+        assert_eq!(refs(), 1);
+        drop(t);
+        assert_eq!(refs(), 0);
+    }
+
+    #[test]
+    fn extract_vector() {
+        let mut mock = MockUnknown {_vtbl: &VTBL};
+        let punk = &mut mock as *mut MockUnknown as *mut IUnknown;
+        //Unknowns holds a reference per element
+        let owned = || unsafe {ComPtr::from_raw_add_ref(punk)};
+        let psa: LPSAFEARRAY = RSafeArray::<()>::Unknowns(vec![owned(), owned()], None).into();
+        assert_eq!(refs(), 3);
+        let types: Vec<ClrType> = extract(psa).unwrap();
+        assert_eq!(types.len(), 2);
+        assert_eq!(types[0].ptr(), punk as *const _Type);
+        assert_eq!(refs(), 3);
+        drop(types);
+        assert_eq!(refs(), 1);
+
+        //nothing is left holding a reference when the cast fails
+        let psa: LPSAFEARRAY = RSafeArray::<()>::Unknowns(vec![owned(), owned()], None).into();
+        match extract_assemblies::<MockAssembly>(psa) {
+            Err(ClrError::NoInterface{..}) => {},
+            Err(e) => panic!("expected NoInterface, got {}", e),
+            Ok(_) => panic!("expected NoInterface")
+        }
+        assert_eq!(refs(), 1);
+
+        //an array of the wrong vartype is an error, not an empty Vec
+        let psa: LPSAFEARRAY = RSafeArray::<()>::Longs(vec![1, 2]).into();
+        match extract::<ClrType>(psa) {
+            Err(ClrError::VartypeMismatch{expected, found}) => assert_eq!((expected, found), (VT_UNKNOWN as u16, VT_I4 as u16)),
+            Err(e) => panic!("expected VartypeMismatch, got {}", e),
+            Ok(_) => panic!("expected VartypeMismatch")
+        }
+    }
+
+    #[test]
+    fn array_elements() {
+        let mut mock = MockUnknown {_vtbl: &VTBL};
+        let punk = &mut mock as *mut MockUnknown as *mut IUnknown;
+        let owned = || unsafe {ComPtr::from_raw_add_ref(punk)};
+
+        //dropped without being converted
+        let rsa = RSafeArray::<()>::Unknowns(vec![owned(), owned()], None);
+        assert_eq!(refs(), 3);
+        drop(rsa);
+        assert_eq!(refs(), 1);
+
+        //a clone holds references of its own, converting it leaves the original's alone
+        let rsa = RSafeArray::<()>::Unknowns(vec![owned()], None);
+        let psa = rsa.clone().try_into_ptr().unwrap();
+        assert_eq!(refs(), 3);
+        assert_eq!(unsafe {SafeArrayDestroy(psa)}, S_OK);
+        assert_eq!(refs(), 2);
+
+        let v = Variant::Array(RSafeArrayNd::from(RSafeArray::Unknowns(vec![owned()], None)));
+        assert_eq!(refs(), 3);
+        drop(OwnedVariant::new(v.clone()));
+        assert_eq!(refs(), 3);
+        drop(v);
+        assert_eq!(refs(), 2);
+
+        //a Variant taken from an element holds a reference of its own
+        match rsa.get(0).map(|elem| elem.to_variant()) {
+            Some(Variant::Unknown(p)) => drop(unsafe {ComPtr::from_raw(p)}), 
+            other => panic!("expected an IUnknown, got {:?}", other)
+        }
+        assert_eq!(refs(), 2);
+        match rsa.into_variants().pop() {
+            Some(Variant::Unknown(p)) => drop(unsafe {ComPtr::from_raw(p)}), 
+            other => panic!("expected an IUnknown, got {:?}", other)
+        }
+        assert_eq!(refs(), 1);
+    }
+
+    struct MockAssembly {
+        ptr: ComPtr<_Assembly>
+    }
+
+    impl PtrContainer<_Assembly> for MockAssembly {
+        fn ptr(&self) -> *const _Assembly {
+            self.ptr.as_ptr()
+        }
+        fn ptr_mut(&self) -> *mut _Assembly {
+            self.ptr.as_ptr()
+        }
+        fn from(p: *mut _Assembly) -> MockAssembly {
+            MockAssembly {ptr: unsafe {ComPtr::from_raw_add_ref(p)}}
+        }
+        unsafe fn from_raw(p: *mut _Assembly) -> MockAssembly {
+            MockAssembly {ptr: ComPtr::from_raw(p)}
+        }
+        fn into_variant(&self) -> Result<Variant> {
+            let unk: ComPtr<IUnknown> = self.ptr.query_interface()?;
+            Ok(Variant::from(unk.as_ptr()))
+        }
+    }
+}
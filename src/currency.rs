@@ -0,0 +1,277 @@
+// currency.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Currency is OLE Automation's CY, a count of ten-thousandths held in an i64.
+//Arithmetic is checked and rounds half to even like VarCyMul/VarCyDiv do.
+//Conversions in from f64 or Decimal take a RoundingMode for digits past the fourth place.
+
+use std::fmt;
+use std::str::FromStr;
+
+use rust_decimal::Decimal;
+
+use winapi::shared::wtypes::{VARTYPE, VT_BSTR, VT_CY, VT_DECIMAL, VT_R8};
+
+use coerce::{Num, decimal_to_num, div_round_half_even, float_to_exact, format_exact, parse_number, pow10};
+use new_variant::Currency;
+use result::{ClrError, Result};
+
+const SCALE: u32 = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RoundingMode {
+    //Banker's rounding, what oleaut32 uses
+    HalfEven, 
+    HalfAwayFromZero, 
+    TowardZero, 
+    AwayFromZero, 
+    Floor, 
+    Ceiling,
+}
+
+impl Default for RoundingMode {
+    fn default() -> RoundingMode {
+        RoundingMode::HalfEven
+    }
+}
+
+//m / d rounded by mode, d > 0
+fn div_round(m: i128, d: i128, mode: RoundingMode) -> i128 {
+    let q = m / d;
+    let r = m % d;
+    if r == 0 {
+        return q;
+    }
+    let away = q + m.signum();
+    match mode {
+        RoundingMode::HalfEven => div_round_half_even(m, d), 
+        RoundingMode::HalfAwayFromZero => if r.abs() * 2 >= d {away} else {q}, 
+        RoundingMode::TowardZero => q, 
+        RoundingMode::AwayFromZero => away, 
+        RoundingMode::Floor => if m < 0 {away} else {q}, 
+        RoundingMode::Ceiling => if m > 0 {away} else {q},
+    }
+}
+
+fn to_currency(m: i128, scale: u32, mode: RoundingMode) -> Option<Currency> {
+    let raw = if scale <= SCALE {
+        m.checked_mul(pow10(SCALE - scale)?)?
+    } else {
+        match pow10(scale - SCALE) {
+            Some(d) => div_round(m, d, mode),
+            None => 0
+        }
+    };
+    if raw >= i64::min_value() as i128 && raw <= i64::max_value() as i128 {
+        Some(Currency(raw as i64))
+    } else {
+        None
+    }
+}
+
+fn fits(raw: i128) -> Option<Currency> {
+    to_currency(raw, SCALE, RoundingMode::HalfEven)
+}
+
+impl Currency {
+    pub const MAX: Currency = Currency(i64::max_value());
+    pub const MIN: Currency = Currency(i64::min_value());
+    pub const ZERO: Currency = Currency(0);
+
+    //n whole units, None if n * 10,000 doesn't fit
+    pub fn from_units(n: i64) -> Option<Currency> {
+        n.checked_mul(10_000).map(Currency)
+    }
+
+    //The whole units, truncated toward zero, and the ten-thousandths left over
+    pub fn units(&self) -> i64 {
+        self.0 / 10_000
+    }
+
+    pub fn fraction(&self) -> i64 {
+        self.0 % 10_000
+    }
+
+    pub fn checked_add(self, other: Currency) -> Option<Currency> {
+        self.0.checked_add(other.0).map(Currency)
+    }
+
+    pub fn checked_sub(self, other: Currency) -> Option<Currency> {
+        self.0.checked_sub(other.0).map(Currency)
+    }
+
+    pub fn checked_mul(self, other: Currency) -> Option<Currency> {
+        fits(div_round_half_even(self.0 as i128 * other.0 as i128, 10_000))
+    }
+
+    pub fn checked_div(self, other: Currency) -> Option<Currency> {
+        if other.0 == 0 {
+            return None;
+        }
+        let (n, d) = (self.0 as i128 * 10_000, other.0 as i128);
+        let (n, d) = if d < 0 {(-n, -d)} else {(n, d)};
+        fits(div_round_half_even(n, d))
+    }
+
+    pub fn checked_neg(self) -> Option<Currency> {
+        self.0.checked_neg().map(Currency)
+    }
+
+    //Scaling by a plain integer, e.g. a quantity
+    pub fn checked_mul_int(self, n: i64) -> Option<Currency> {
+        self.0.checked_mul(n).map(Currency)
+    }
+
+    pub fn to_decimal(&self) -> Decimal {
+        Decimal::new(self.0, SCALE)
+    }
+
+    pub fn from_decimal(d: Decimal, mode: RoundingMode) -> Result<Currency> {
+        match decimal_to_num(d) {
+            Num::Exact(m, scale) => to_currency(m, scale, mode), 
+            Num::Float(_) => None
+        }.ok_or(ClrError::Overflow{from: VT_DECIMAL as VARTYPE, to: VT_CY as VARTYPE})
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.0 as f64 / 10_000.0
+    }
+
+    //Goes through the double's shortest 15 digit decimal form, so 0.1 is 1000 ten-thousandths
+    //rather than whatever 0.1 * 10,000 comes out as
+    pub fn from_f64(f: f64, mode: RoundingMode) -> Result<Currency> {
+        float_to_exact(f)
+            .and_then(|(m, scale)| to_currency(m, scale, mode))
+            .ok_or(ClrError::Overflow{from: VT_R8 as VARTYPE, to: VT_CY as VARTYPE})
+    }
+}
+
+impl From<Currency> for Decimal {
+    fn from(c: Currency) -> Decimal {
+        c.to_decimal()
+    }
+}
+
+//Trailing zeros are trimmed unless a precision is given, so {} of 1.5 is "1.5" and {:.2} is "1.50".
+//Precisions past four places pad with zeros.
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match f.precision() {
+            None => format_exact(self.0 as i128, SCALE), 
+            Some(p) => {
+                let kept = p.min(SCALE as usize);
+                let m = div_round_half_even(self.0 as i128, 10i128.pow(SCALE - kept as u32));
+                let digits = format!("{:0width$}", m.abs(), width = kept + 1);
+                let (int_part, frac_part) = digits.split_at(digits.len() - kept);
+                let sign = if m < 0 {"-"} else {""};
+                if p == 0 {
+                    format!("{}{}", sign, int_part)
+                } else {
+                    format!("{}{}.{}{}", sign, int_part, frac_part, "0".repeat(p - kept))
+                }
+            }
+        };
+        f.pad_integral(!s.starts_with('-'), "", s.trim_start_matches('-'))
+    }
+}
+
+//Decimal notation, with an exponent if you like; digits past the fourth place round half to even
+impl FromStr for Currency {
+    type Err = ClrError;
+    fn from_str(s: &str) -> Result<Currency> {
+        match parse_number(s) {
+            Some(Num::Exact(m, scale)) => to_currency(m, scale, RoundingMode::HalfEven)
+                .ok_or(ClrError::Overflow{from: VT_BSTR as VARTYPE, to: VT_CY as VARTYPE}), 
+            Some(Num::Float(_)) => Err(ClrError::Overflow{from: VT_BSTR as VARTYPE, to: VT_CY as VARTYPE}), 
+            None => Err(ClrError::Coercion{from: VT_BSTR as VARTYPE, to: VT_CY as VARTYPE})
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let a = Currency::from_units(3).unwrap();
+        let b: Currency = "1.25".parse().unwrap();
+        assert_eq!(a, Currency(30000));
+        assert_eq!(a.checked_add(b), Some(Currency(42500)));
+        assert_eq!(a.checked_sub(b), Some(Currency(17500)));
+        assert_eq!(a.checked_mul(b), Some(Currency(37500)));
+        assert_eq!(a.checked_div(b), Some(Currency(24000)));
+        assert_eq!(a.checked_div(b.checked_neg().unwrap()), Some(Currency(-24000)));
+        //0.0001 * 0.5 is half a ten-thousandth, which rounds to even
+        assert_eq!(Currency(1).checked_mul(Currency(5000)), Some(Currency(0)));
+        assert_eq!(Currency(3).checked_mul(Currency(5000)), Some(Currency(2)));
+        assert_eq!(Currency(10000).checked_div(Currency(30000)), Some(Currency(3333)));
+        assert_eq!(a.checked_div(Currency::ZERO), None);
+        assert_eq!(Currency::MAX.checked_add(Currency(1)), None);
+        assert_eq!(Currency::MAX.checked_mul(Currency::from_units(2).unwrap()), None);
+        assert_eq!(Currency::from_units(i64::max_value()), None);
+        assert_eq!(Currency(-12345).units(), -1);
+        assert_eq!(Currency(-12345).fraction(), -2345);
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(Currency(15000).to_string(), "1.5");
+        assert_eq!(format!("{:.2}", Currency(15000)), "1.50");
+        assert_eq!(format!("{:.2}", Currency(-12350)), "-1.24");
+        assert_eq!(format!("{:.0}", Currency(25000)), "2");
+        assert_eq!(format!("{:.6}", Currency(1)), "0.000100");
+        assert_eq!(format!("{:>8}", Currency(-5000)), "    -0.5");
+        assert_eq!(Currency::MIN.to_string(), "-922337203685477.5808");
+        assert_eq!("-922337203685477.5808".parse::<Currency>().unwrap(), Currency::MIN);
+        assert_eq!("1.23455".parse::<Currency>().unwrap(), Currency(12346));
+        assert_eq!("2e3".parse::<Currency>().unwrap(), Currency(20_000_000));
+        match "922337203685477.5808".parse::<Currency>() {
+            Err(ClrError::Overflow{..}) => {},
+            other => panic!("Unexpected result: {:?}", other)
+        }
+        match "1,5".parse::<Currency>() {
+            Err(ClrError::Coercion{..}) => {},
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(Currency(12345).to_decimal(), Decimal::new(12345, 4));
+        let d: Decimal = From::from(Currency(-5));
+        assert_eq!(d, Decimal::new(-5, 4));
+        let d = Decimal::new(123455, 5);
+        assert_eq!(Currency::from_decimal(d, RoundingMode::HalfEven).unwrap(), Currency(12346));
+        assert_eq!(Currency::from_decimal(d, RoundingMode::TowardZero).unwrap(), Currency(12345));
+        assert_eq!(Currency::from_decimal(-d, RoundingMode::Floor).unwrap(), Currency(-12346));
+        assert_eq!(Currency::from_decimal(-d, RoundingMode::Ceiling).unwrap(), Currency(-12345));
+        assert_eq!(Currency::from_decimal(-d, RoundingMode::HalfAwayFromZero).unwrap(), Currency(-12346));
+        assert!(Currency::from_decimal(Decimal::from_str("1000000000000000").unwrap(), RoundingMode::HalfEven).is_err());
+        assert_eq!(Currency::from_f64(0.1, RoundingMode::HalfEven).unwrap(), Currency(1000));
+        assert_eq!(Currency::from_f64(1.00005, RoundingMode::HalfEven).unwrap(), Currency(10000));
+        assert_eq!(Currency::from_f64(1.00005, RoundingMode::HalfAwayFromZero).unwrap(), Currency(10001));
+        assert_eq!(Currency::from_f64(1.00001, RoundingMode::AwayFromZero).unwrap(), Currency(10001));
+        assert!(Currency::from_f64(1e300, RoundingMode::HalfEven).is_err());
+        assert!(Currency::from_f64(::std::f64::NAN, RoundingMode::HalfEven).is_err());
+        assert_eq!(Currency(25000).to_f64(), 2.5);
+    }
+}
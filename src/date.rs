@@ -0,0 +1,173 @@
+// date.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//Date to and from SystemTime, and chrono's NaiveDateTime with the chrono feature.
+//Both go through milliseconds since 1970-01-01, the precision .NET's DateTime.FromOADate keeps.
+//A negative DATE counts days back from 1899-12-30 but its fraction is still the time forward
+//from midnight, so -1.25 is 1899-12-29 06:00 and not 1899-12-28 18:00.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+
+use coerce::{MAX_DATE, MIN_DATE, UNIX_EPOCH_DATE};
+#[cfg(feature = "chrono")]
+use coerce::{civil_from_days, days_from_civil};
+use new_variant::Date;
+use result::{ClrError, Result};
+
+const MS_PER_DAY: i64 = 86_400_000;
+
+fn to_unix_millis(d: f64) -> Option<i64> {
+    if !(d >= MIN_DATE && d < MAX_DATE) {
+        return None;
+    }
+    let day = d.trunc();
+    let ms = ((d - day).abs() * MS_PER_DAY as f64).round() as i64;
+    //A fraction that rounds up to a whole day is midnight of the next calendar day, which is day + 1
+    //for negative DATEs too (-1.9999999999 is 1899-12-30 00:00), the same carry as coerce::split_date
+    let (day, ms) = if ms == MS_PER_DAY {(day as i64 + 1, 0)} else {(day as i64, ms)};
+    if day >= MAX_DATE as i64 {
+        return None;
+    }
+    Some((day - UNIX_EPOCH_DATE) * MS_PER_DAY + ms)
+}
+
+fn from_unix_millis(ms: i128) -> Option<f64> {
+    if ms.abs() > i64::max_value() as i128 {
+        return None;
+    }
+    let ms = ms as i64;
+    let day = ms.div_euclid(MS_PER_DAY) + UNIX_EPOCH_DATE;
+    let time = ms.rem_euclid(MS_PER_DAY) as f64 / MS_PER_DAY as f64;
+    let d = if day < 0 {day as f64 - time} else {day as f64 + time};
+    if d >= MIN_DATE && d < MAX_DATE {Some(d)} else {None}
+}
+
+fn duration_millis(d: Duration) -> i128 {
+    d.as_secs() as i128 * 1000 + ((d.subsec_nanos() + 500_000) / 1_000_000) as i128
+}
+
+fn out_of_range<T: ::std::fmt::Debug>(date: T) -> ClrError {
+    ClrError::DateRange{date: format!("{:?}", date)}
+}
+
+impl Date {
+    pub fn to_system_time(&self) -> Result<SystemTime> {
+        let ms = to_unix_millis(self.0).ok_or_else(|| out_of_range(self.0))?;
+        let t = if ms >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_millis(ms as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_millis(-ms as u64))
+        };
+        t.ok_or_else(|| out_of_range(self.0))
+    }
+
+    pub fn from_system_time(t: SystemTime) -> Result<Date> {
+        let ms = match t.duration_since(UNIX_EPOCH) {
+            Ok(d) => duration_millis(d),
+            Err(e) => -duration_millis(e.duration())
+        };
+        from_unix_millis(ms).map(Date).ok_or_else(|| out_of_range(t))
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn to_naive_date_time(&self) -> Result<NaiveDateTime> {
+        let ms = to_unix_millis(self.0).ok_or_else(|| out_of_range(self.0))?;
+        let (y, m, d) = civil_from_days(ms.div_euclid(MS_PER_DAY));
+        let rem = ms.rem_euclid(MS_PER_DAY) as u32;
+        NaiveDate::from_ymd_opt(y as i32, m, d)
+            .and_then(|date| date.and_hms_milli_opt(rem / 3_600_000, rem / 60_000 % 60, rem / 1000 % 60, rem % 1000))
+            .ok_or_else(|| out_of_range(self.0))
+    }
+
+    #[cfg(feature = "chrono")]
+    pub fn from_naive_date_time(dt: &NaiveDateTime) -> Result<Date> {
+        let days = days_from_civil(dt.year() as i64, dt.month(), dt.day()) as i128;
+        //a leap second's nanoseconds run past 1e9, fold it into the last millisecond of the minute
+        let ms = (dt.nanosecond().min(999_999_999) + 500_000) / 1_000_000;
+        let ms = days * MS_PER_DAY as i128 + dt.num_seconds_from_midnight() as i128 * 1000 + ms as i128;
+        from_unix_millis(ms).map(Date).ok_or_else(|| out_of_range(dt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn system_time() {
+        assert_eq!(Date(25569.0).to_system_time().unwrap(), UNIX_EPOCH);
+        let t = UNIX_EPOCH + Duration::from_secs(86400 + 6 * 3600);
+        assert_eq!(Date(25570.25).to_system_time().unwrap(), t);
+        assert_eq!(Date::from_system_time(t).unwrap(), Date(25570.25));
+        //1899-12-29 06:00
+        let t = UNIX_EPOCH - Duration::from_secs(25570 * 86400 - 6 * 3600);
+        assert_eq!(Date(-1.25).to_system_time().unwrap(), t);
+        assert_eq!(Date::from_system_time(t).unwrap(), Date(-1.25));
+        match Date(MAX_DATE).to_system_time() {
+            Err(ClrError::DateRange{..}) => {},
+            other => panic!("Unexpected result: {:?}", other)
+        }
+        assert!(Date(::std::f64::NAN).to_system_time().is_err());
+    }
+
+    #[test]
+    fn fraction_rounds_up_to_a_day() {
+        let day = Duration::from_secs(86400);
+        assert_eq!(Date(25569.9999999999).to_system_time().unwrap(), UNIX_EPOCH + day);
+        assert_eq!(Date(25568.9999999999).to_system_time().unwrap(), UNIX_EPOCH);
+        //1899-12-29 23:59:59.9999 rounds to 1899-12-30 00:00, and 1899-12-30 23:59:59.9999 to 1899-12-31 00:00
+        assert_eq!(Date(-1.9999999999).to_system_time().unwrap(), UNIX_EPOCH - day * 25569);
+        assert_eq!(Date(-0.9999999999).to_system_time().unwrap(), UNIX_EPOCH - day * 25568);
+        //short of rounding up, the time still runs forward from the truncated day, 1899-12-29 23:58:33.6
+        assert_eq!(Date(-1.999).to_system_time().unwrap(), UNIX_EPOCH - day * 25570 + Duration::from_millis(86_313_600));
+        //just past a whole negative day is just past its midnight, 1899-12-28 00:00
+        assert_eq!(Date(-2.0000000001).to_system_time().unwrap(), UNIX_EPOCH - day * 25571);
+        match Date(MAX_DATE - 1e-9).to_system_time() {
+            Err(ClrError::DateRange{..}) => {},
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn naive_date_time() {
+        let dt = NaiveDate::from_ymd_opt(2018, 1, 1).unwrap().and_hms_milli_opt(12, 0, 0, 500).unwrap();
+        let d = Date::from_naive_date_time(&dt).unwrap();
+        assert!((d.0 - (43101.5 + 0.5 / 86400.0)).abs() < 1e-9);
+        assert_eq!(d.to_naive_date_time().unwrap(), dt);
+        let dt = NaiveDate::from_ymd_opt(1899, 12, 29).unwrap().and_hms_opt(6, 0, 0).unwrap();
+        assert_eq!(Date::from_naive_date_time(&dt).unwrap(), Date(-1.25));
+        assert_eq!(Date(-1.25).to_naive_date_time().unwrap(), dt);
+        let first = NaiveDate::from_ymd_opt(100, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(Date::from_naive_date_time(&first).unwrap(), Date(MIN_DATE));
+        let before = NaiveDate::from_ymd_opt(99, 12, 31).unwrap().and_hms_opt(23, 59, 59).unwrap();
+        assert!(Date::from_naive_date_time(&before).is_err());
+        let after = NaiveDate::from_ymd_opt(10000, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert!(Date::from_naive_date_time(&after).is_err());
+        let midnight = NaiveDate::from_ymd_opt(1899, 12, 30).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(Date(-1.9999999999).to_naive_date_time().unwrap(), midnight);
+        let midnight = NaiveDate::from_ymd_opt(2018, 1, 2).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        assert_eq!(Date(43101.9999999999).to_naive_date_time().unwrap(), midnight);
+    }
+}
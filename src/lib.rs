@@ -30,18 +30,44 @@ extern crate mscorlib_sys;
 
 extern crate rust_decimal;
 
+#[macro_use] extern crate log;
+
+#[cfg(feature = "chrono")]
+extern crate chrono;
+
+#[cfg(feature = "serde")]
+extern crate serde;
+
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+mod bstr_alloc;
 mod bstring;
+mod coerce;
+mod com_ptr;
 mod collections;
+mod currency;
+mod date;
 mod result;
+mod safearray_descriptor;
+mod safearray_iter;
+#[cfg(feature = "serde")]
+mod serde_impls;
 mod struct_wrappers;
+mod typed_safearray;
 mod wrappers;
 
 pub mod new_variant;
 pub mod new_safearray;
+pub mod owned_variant;
+pub mod record;
 
 pub use collections::*;
+pub use com_ptr::*;
+pub use currency::*;
 pub use bstring::*;
 pub use result::*;
+pub use typed_safearray::*;
 pub use wrappers::*;
 
 use mscorlib_sys::system::reflection::{_Assembly, _AssemblyName, _Binder, _ConstructorInfo, _FieldInfo, _EventInfo, _MemberInfo, 
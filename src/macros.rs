@@ -53,7 +53,7 @@ macro_rules! SUCCEEDED {
         let hr = unsafe {
             (*p).get_DeclaringType(t)
         };
-        SUCCEEDED!(hr, T::from(unsafe{*t}), _Type)
+        SUCCEEDED!(hr, unsafe {T::from_raw(*t)}, _Type)
     }
 
     fn $prop_name<T>(&self) -> Result<T> 
@@ -64,7 +64,7 @@ macro_rules! SUCCEEDED {
         let hr = unsafe {
             (*p).$prop_type_$fn_name(t)
         };
-        SUCCEEDED!(hr, T::from(unsafe{*t}), $ptr_type)
+        SUCCEEDED!(hr, unsafe {T::from_raw(*t)}, $ptr_type)
     }
     PROPERTY!{get_IsNotPublic _Type{ get { not_public(VARIANT_BOOL) }}}
 */
@@ -91,24 +91,29 @@ macro_rules! PROPERTY {
             let hr = unsafe {
                 (*p).$fn_name(&mut t)
             };
-            SUCCEEDED!(hr, T::from(t), $err_type)
+            SUCCEEDED!(hr, unsafe {T::from_raw(t)}, $err_type)
         }
     };
 }
 
+//Each element holds a reference of its own, which is handed to a ComPtr and
+//QueryInterface'd for $target_type. A wrong interface returns ClrError::NoInterface.
 #[macro_export]
 macro_rules! EXTRACT_VECTOR_FROM_SAFEARRAY {
-    ($enum_name:ident, $psa_name:ident, $origin_type:ty, $transmuted_type:ty, $ctr_type:ident) => {
+    ($enum_name:ident, $psa_name:ident, $origin_type:ty, $target_type:ty, $ctr_type:ident) => {
         {
-            let rsa: RSafeArray<$ctr_type> = RSafeArray::from($psa_name);
+            let rsa: RSafeArray<$ctr_type> = unsafe {RSafeArray::try_from_ptr($psa_name)}?;
+            let found = rsa.vartype() as u16;
             if let RSafeArray::$enum_name(array, _) = rsa {
-                array.into_iter().map(|item| {
-                    let trans_item = unsafe { mem::transmute::<$origin_type, $transmuted_type>(item)};
-                    $ctr_type::from(trans_item)
-                }).collect()
+                let items: Vec<ComPtr<$origin_type>> = array;
+                items.into_iter().map(|item| {
+                    let p: ComPtr<$target_type> = item.query_interface()?;
+                    Ok(unsafe {$ctr_type::from_raw(p.into_raw())})
+                }).collect::<Result<Vec<$ctr_type>>>()?
             }
             else {
-                Vec::new()
+                let expected = RSafeArray::<$ctr_type>::$enum_name(Vec::new(), None).vartype() as u16;
+                return Err(ClrError::VartypeMismatch{expected: expected, found: found});
             }
         }
         
@@ -119,11 +124,13 @@ macro_rules! EXTRACT_VECTOR_FROM_SAFEARRAY {
 macro_rules! SIMPLE_EXTRACT {
     ($enum_name:ident, $psa_name:ident, $ptr_type:ty) => {
         {
-            let rsa: RSafeArray<$ptr_type> = RSafeArray::from($psa_name);
+            let rsa: RSafeArray<$ptr_type> = unsafe {RSafeArray::try_from_ptr($psa_name)}?;
+            let found = rsa.vartype() as u16;
             if let RSafeArray::$enum_name(inner) = rsa {
                 inner
             } else {
-                Vec::new()
+                let expected = RSafeArray::<$ptr_type>::$enum_name(Vec::new()).vartype() as u16;
+                return Err(ClrError::VartypeMismatch{expected: expected, found: found});
             }
         }
     };
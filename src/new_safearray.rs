@@ -19,51 +19,60 @@
 //  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 //  SOFTWARE.
 
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
 use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::Deref;
 use std::ptr;
+use std::slice;
 
 use winapi::ctypes::{c_long, c_void};
 
 use winapi::shared::minwindef::{UINT, ULONG};
-use winapi::shared::winerror::HRESULT;
+use winapi::shared::winerror::{DISP_E_BADINDEX, E_OUTOFMEMORY, E_POINTER, HRESULT};
 use winapi::shared::wtypes::{CY, VARENUM, VARTYPE, VT_BOOL,    VT_BSTR, 
                              VT_CY,   VT_DATE, VT_DECIMAL, VT_DISPATCH,                              
                              VT_INT,  VT_I1,  VT_I2,   VT_I4,   VT_R4,       
-                             VT_R8,   VT_UINT, VT_UNKNOWN, VT_UI1,  
-                             VT_UI2,  VT_UI4,  VT_VARIANT};
-use winapi::shared::wtypes::{BSTR, VARIANT_BOOL};
+                             VT_R8,   VT_RECORD, VT_UINT, VT_UNKNOWN, VT_UI1,  
+                             VT_UI2,  VT_UI4,  VT_VARIANT, VT_I8, 
+                             VT_UI8,  VT_BYREF, VT_EMPTY};
+use winapi::shared::wtypes::{BSTR, DATE, DECIMAL, VARIANT_BOOL};
 
-use winapi::um::oaidl::{IDispatch, LPSAFEARRAYBOUND, SAFEARRAY, SAFEARRAYBOUND, VARIANT};
+use winapi::um::oaidl::{IDispatch, IRecordInfo, SAFEARRAY, SAFEARRAYBOUND, VARIANT};
+use winapi::um::oaidl::LPSAFEARRAYBOUND;
+use winapi::um::oleauto::VariantClear;
 use winapi::um::unknwnbase::IUnknown;
 use rust_decimal::Decimal;
 
-use new_variant::{Currency, Date, Int, UInt, Variant, build_c_decimal};
+#[cfg(feature = "chrono")]
+use chrono::NaiveDateTime;
 
+use new_variant::{Currency, Date, Int, UInt, Variant, build_c_decimal, build_rust_decimal};
+use owned_variant::OwnedVariant;
+use record::Record;
+
+use result::{ClrError, SourceLocation, Result};
 use wrappers::PtrContainer;
 
-use bstring;
-
-/*{ 
-if let RSafeArray::Unknown(array) = RSafeArray::from(pmodules) {
-    array.into_iter().map(|item| {
-        let trans_item = unsafe { mem::transmute::<*mut IUnknown, *mut _Module>(item)};
-        M::from(trans_item)
-    }).collect()
-}
-else {
-    Vec::new()
-}*/
+use bstring::{BString, StringMode, SysString};
+use com_ptr::{ComPtr, query_interface};
 
 extern "system" {
     pub fn SafeArrayCreate(vt: VARTYPE, cDims: UINT, rgsabound: LPSAFEARRAYBOUND) -> LPSAFEARRAY;
+    pub fn SafeArrayCreateEx(vt: VARTYPE, cDims: UINT, rgsabound: LPSAFEARRAYBOUND, pvExtra: *mut c_void) -> LPSAFEARRAY;
 	pub fn SafeArrayDestroy(safe: LPSAFEARRAY)->HRESULT;
+    pub fn SafeArrayCopy(psa: LPSAFEARRAY, ppsaOut: *mut LPSAFEARRAY) -> HRESULT;
     
-    pub fn SafeArrayGetDim(psa: LPSAFEARRAY) -> UINT;
-	
+    pub fn SafeArrayPutElement(psa: LPSAFEARRAY, rgIndices: *const c_long, pv: *mut c_void) -> HRESULT;
+
+    pub fn SafeArrayGetRecordInfo(psa: LPSAFEARRAY, prinfo: *mut *mut IRecordInfo) -> HRESULT;
+
     pub fn SafeArrayGetElement(psa: LPSAFEARRAY, rgIndices: *const c_long, pv: *mut c_void) -> HRESULT;
+
+    pub fn SafeArrayGetDim(psa: LPSAFEARRAY) -> UINT;
     pub fn SafeArrayGetElemSize(psa: LPSAFEARRAY) -> UINT;
     
     pub fn SafeArrayGetLBound(psa: LPSAFEARRAY, nDim: UINT, plLbound: *mut c_long)->HRESULT;
@@ -73,10 +82,15 @@ extern "system" {
 
     pub fn SafeArrayLock(psa: LPSAFEARRAY) -> HRESULT;
 	pub fn SafeArrayUnlock(psa: LPSAFEARRAY) -> HRESULT;
-    
-    pub fn SafeArrayPutElement(psa: LPSAFEARRAY, rgIndices: *const c_long, pv: *mut c_void) -> HRESULT;
+
+    pub fn SafeArrayAccessData(psa: LPSAFEARRAY, ppvData: *mut *mut c_void) -> HRESULT;
+    pub fn SafeArrayUnaccessData(psa: LPSAFEARRAY) -> HRESULT;
 }
 
+pub use safearray_descriptor::StandInSafeArray;
+use safearray_descriptor::{self, check_bounds};
+pub use safearray_iter::{ElementRef, Iter};
+
 pub use winapi::um::oaidl::LPSAFEARRAY;
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -88,18 +102,19 @@ pub enum RSafeArray<P=i32> {
     Currencies(Vec<Currency>), //VT_CY, 
     Dates(Vec<Date>),//VT_DATE,
     BStrings(Vec<String>), //VT_BSTR,
-    Dispatchs(Vec<*mut IDispatch>, Option<P>), //VT_DISPATCH, 
+    Dispatchs(Vec<ComPtr<IDispatch>>, Option<P>), //VT_DISPATCH
     Bools(Vec<bool>), //VT_BOOL, need to translate between rust bool and VARIANT_BOOL,
-    Variants(Vec<Variant>), //VT_VARIANT,
-    Unknowns(Vec<*mut IUnknown>, Option<P>), //VT_UNKNOWN, 
+    Variants(Vec<Variant>), //VT_VARIANT, by-reference Variants can't be put in a SAFEARRAY
+    Unknowns(Vec<ComPtr<IUnknown>>, Option<P>), //VT_UNKNOWN
     Decimals(Vec<Decimal>),//VT_DECIMAL,
-    //VT_RECORD,
+    Records(Vec<Record>), //VT_RECORD, every element's IRecordInfo has to match the first's
     Chars(Vec<i8>), //VT_I1, 
     UChars(Vec<u8>), //VT_UI1, 
     UShorts(Vec<u16>), //VT_UI2, 
     ULongs(Vec<u32>), //VT_UI4,
     Ints(Vec<Int>), //VT_INT, 
     UInts(Vec<UInt>), //VT_UINT,
+    WStrings(Vec<BString>), //VT_BSTR, read with StringMode::Wide
 }
 
 impl<T> RSafeArray<T> {
@@ -117,14 +132,19 @@ impl<T> RSafeArray<T> {
             RSafeArray::Variants(inner) => inner.len(), 
             RSafeArray::Unknowns(inner, _) => inner.len(), 
             RSafeArray::Decimals(inner) => inner.len(),
+            RSafeArray::Records(inner) => inner.len(),
             RSafeArray::Chars(inner) => inner.len(), 
             RSafeArray::UChars(inner) => inner.len(),
             RSafeArray::UShorts(inner) => inner.len(), 
             RSafeArray::ULongs(inner) => inner.len(), 
             RSafeArray::Ints(inner) => inner.len(), 
             RSafeArray::UInts(inner) => inner.len(),
+            RSafeArray::WStrings(inner) => inner.len(),
         }
     }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
     pub fn vartype(&self) -> VARENUM {
         match self { 
             RSafeArray::Shorts(_) => VT_I2, 
@@ -139,35 +159,53 @@ impl<T> RSafeArray<T> {
             RSafeArray::Variants(_) => VT_VARIANT, 
             RSafeArray::Unknowns(_, _) => VT_UNKNOWN, 
             RSafeArray::Decimals(_) => VT_DECIMAL,
+            RSafeArray::Records(_) => VT_RECORD,
             RSafeArray::Chars(_) => VT_I1, 
             RSafeArray::UChars(_) => VT_UI1, 
             RSafeArray::UShorts(_) => VT_UI2, 
             RSafeArray::ULongs(_) => VT_UI4, 
             RSafeArray::Ints(_) => VT_INT, 
             RSafeArray::UInts(_) => VT_UINT,
+            RSafeArray::WStrings(_) => VT_BSTR,
         }
     }
 
-    pub fn from_vec_dispatch<TOut: Deref<Target=IDispatch>>(vec: Vec<T>) -> RSafeArray<T> 
+    pub fn with_lower_bound(self, lower_bound: c_long) -> Result<RSafeArrayNd<T>> {
+        RSafeArrayNd::with_lower_bound(self, lower_bound)
+    }
+
+    //Each element is QueryInterface'd for IDispatch, the array holds the references that took
+    pub fn from_vec_dispatch<TOut>(vec: Vec<T>) -> Result<RSafeArray<T>> 
         where T: PtrContainer<TOut>
     {
-        let stripped_vec: Vec<*mut IDispatch> = vec.into_iter().map(|item|{
-            let ptr = item.ptr_mut();
-            let id = unsafe { mem::transmute::<*mut TOut, *mut IDispatch>(ptr)};
-            id
+        let stripped_vec: Result<Vec<ComPtr<IDispatch>>> = vec.into_iter().map(|item|{
+            unsafe {query_interface(item.ptr_mut())}
         }).collect();
-        RSafeArray::Dispatchs(stripped_vec, None)
+        Ok(RSafeArray::Dispatchs(stripped_vec?, None))
     }
 
-    pub fn from_vec_unknown<TOut: Deref<Target=IUnknown>>(vec: Vec<T>) -> RSafeArray<T>
+    pub fn from_vec_unknown<TOut>(vec: Vec<T>) -> Result<RSafeArray<T>>
         where T: PtrContainer<TOut> 
     {
-        let stripped_vec: Vec<*mut IUnknown> = vec.into_iter().map(|item|{
-            let ptr = item.ptr_mut();
-            let id = unsafe { mem::transmute::<*mut TOut, *mut IUnknown>(ptr)};
-            id
+        let stripped_vec: Result<Vec<ComPtr<IUnknown>>> = vec.into_iter().map(|item|{
+            unsafe {query_interface(item.ptr_mut())}
         }).collect();
-        RSafeArray::Unknowns(stripped_vec, None)
+        Ok(RSafeArray::Unknowns(stripped_vec?, None))
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<T> RSafeArray<T> {
+    pub fn to_naive_date_times(&self) -> Result<Vec<NaiveDateTime>> {
+        match self {
+            RSafeArray::Dates(dates) => dates.iter().map(Date::to_naive_date_time).collect(), 
+            _ => Err(ClrError::VartypeMismatch{expected: VT_DATE as VARTYPE, found: self.vartype() as VARTYPE})
+        }
+    }
+
+    pub fn from_naive_date_times(dts: &[NaiveDateTime]) -> Result<RSafeArray<T>> {
+        let dates = dts.iter().map(Date::from_naive_date_time).collect::<Result<Vec<Date>>>()?;
+        Ok(RSafeArray::Dates(dates))
     }
 }
 
@@ -198,17 +236,331 @@ FROM_IMPLS!{
     {Longs, i32}
     {Floats, f32}
     {Doubles, f64}
+    {Currencies, Currency}
+    {Dates, Date}
     {BStrings, String}
-    {Dispatchs, *mut IDispatch}
+    {Dispatchs, ComPtr<IDispatch>}
     {Bools, bool}
     {Variants, Variant}
-    {Unknowns, *mut IUnknown}
+    {Unknowns, ComPtr<IUnknown>}
+    {Decimals, Decimal}
     {Chars, i8}
     {UChars, u8}
     {UShorts, u16}
     {ULongs, u32}
     {Ints, Int}
     {UInts, UInt}
+    {Records, Record}
+    {WStrings, BString}
+}
+
+macro_rules! REORDER_MATCH {
+    ($match_name:ident, $order:ident, {$($enum_name:ident,)*}, {$($ptr_enum_name:ident,)*}) => {
+        match $match_name {
+            $(
+                RSafeArray::$enum_name(array) => RSafeArray::$enum_name(reorder_vec(array, $order)),
+            )*
+            $(
+                RSafeArray::$ptr_enum_name(array, p) => RSafeArray::$ptr_enum_name(reorder_vec(array, $order), p),
+            )*
+        }
+    };
+}
+
+fn reorder_vec<E>(vc: Vec<E>, order: &[usize]) -> Vec<E> {
+    let mut slots: Vec<Option<E>> = vc.into_iter().map(Some).collect();
+    order.iter().map(|&ix| slots[ix].take().expect("Index used twice in reorder")).collect()
+}
+
+impl<T> RSafeArray<T> {
+    //new[ix] = old[order[ix]]
+    fn reorder(self, order: &[usize]) -> RSafeArray<T> {
+        assert_eq!(order.len(), self.len());
+        REORDER_MATCH!{self, order, 
+            {Shorts, Longs, Floats, Doubles, Currencies, Dates, BStrings, Bools, 
+             Variants, Decimals, Records, Chars, UChars, UShorts, ULongs, Ints, UInts, WStrings,}, 
+            {Dispatchs, Unknowns,}
+        }
+    }
+}
+
+//Multi-dimensional counterpart to RSafeArray. 
+//Elements are kept in SAFEARRAY memory order (column-major, leftmost dimension varies fastest), 
+//and bounds are kept in SafeArrayCreate order (leftmost dimension first). 
+#[derive(Clone)]
+pub struct RSafeArrayNd<P=i32> {
+    bounds: Vec<SAFEARRAYBOUND>, 
+    data: RSafeArray<P>,
+}
+
+impl<T> RSafeArrayNd<T> {
+    //SafeArrayDimensions for empty bounds, SafeArrayLength if they don't hold data.len() elements
+    pub fn new(bounds: Vec<SAFEARRAYBOUND>, data: RSafeArray<T>) -> Result<RSafeArrayNd<T>> {
+        check_bounds(&bounds, data.len())?;
+        Ok(RSafeArrayNd {
+            bounds: bounds, 
+            data: data
+        })
+    }
+
+    pub fn from_row_major(bounds: Vec<SAFEARRAYBOUND>, data: RSafeArray<T>) -> Result<RSafeArrayNd<T>> {
+        let rsa = RSafeArrayNd::new(bounds, data)?;
+        let order = rsa.row_major_permutation();
+        let RSafeArrayNd {bounds, data} = rsa;
+        Ok(RSafeArrayNd {
+            bounds: bounds, 
+            data: data.reorder(&order)
+        })
+    }
+
+    //Fails as safearray_descriptor::single_bound does
+    pub fn with_lower_bound(data: RSafeArray<T>, lower_bound: c_long) -> Result<RSafeArrayNd<T>> {
+        let bounds = safearray_descriptor::single_bound(data.len(), lower_bound)?;
+        Ok(RSafeArrayNd {
+            bounds: bounds, 
+            data: data
+        })
+    }
+
+    pub fn dims(&self) -> usize {
+        self.bounds.len()
+    }
+
+    //Lower bound of the leftmost dimension
+    pub fn lower_bound(&self) -> c_long {
+        self.bounds[0].lLbound
+    }
+
+    pub fn lower_bounds(&self) -> Vec<c_long> {
+        self.bounds.iter().map(|b| b.lLbound).collect()
+    }
+
+    //Upper bound of the leftmost dimension, inclusive like SafeArrayGetUBound. 
+    //None for an empty dimension whose lower bound is c_long::min_value().
+    pub fn upper_bound(&self) -> Option<c_long> {
+        safearray_descriptor::upper_bound(&self.bounds[0])
+    }
+
+    pub fn bounds(&self) -> &[SAFEARRAYBOUND] {
+        &self.bounds
+    }
+
+    pub fn shape(&self) -> Vec<usize> {
+        self.bounds.iter().map(|b| b.cElements as usize).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn vartype(&self) -> VARENUM {
+        self.data.vartype()
+    }
+
+    pub fn column_major(&self) -> &RSafeArray<T> {
+        &self.data
+    }
+
+    pub fn into_column_major(self) -> RSafeArray<T> {
+        self.data
+    }
+
+    pub fn into_row_major(self) -> RSafeArray<T> {
+        let order = self.row_major_permutation();
+        let mut inverse = vec![0usize; order.len()];
+        for (col, row) in order.into_iter().enumerate() {
+            inverse[row] = col;
+        }
+        self.data.reorder(&inverse)
+    }
+
+    //Offset into column_major() for the given indices, which include lower bounds. 
+    pub fn column_major_offset(&self, indices: &[c_long]) -> Option<usize> {
+        self.offset(indices, self.bounds.iter().enumerate().collect())
+    }
+
+    //Offset into into_row_major() for the given indices, which include lower bounds. 
+    pub fn row_major_offset(&self, indices: &[c_long]) -> Option<usize> {
+        self.offset(indices, self.bounds.iter().enumerate().rev().collect())
+    }
+
+    //dims lists the dimensions from fastest varying to slowest
+    fn offset(&self, indices: &[c_long], dims: Vec<(usize, &SAFEARRAYBOUND)>) -> Option<usize> {
+        if indices.len() != self.bounds.len() {
+            return None;
+        }
+        let mut offset = 0usize;
+        let mut stride = 1usize;
+        for (dim, bound) in dims {
+            let rel = indices[dim] as i64 - bound.lLbound as i64;
+            if rel < 0 || rel >= bound.cElements as i64 {
+                return None;
+            }
+            offset += rel as usize * stride;
+            stride *= bound.cElements as usize;
+        }
+        Some(offset)
+    }
+
+    //row-major offset of each element, in column-major order
+    fn row_major_permutation(&self) -> Vec<usize> {
+        let mut indices = ColumnMajorIndices::new(&self.bounds);
+        let mut order = Vec::with_capacity(indices.len());
+        while let Some(ix) = indices.next_index() {
+            order.push(self.row_major_offset(ix).expect("Index generated out of bounds"));
+        }
+        order
+    }
+}
+
+impl<T> From<RSafeArray<T>> for RSafeArrayNd<T> {
+    fn from(rsa: RSafeArray<T>) -> RSafeArrayNd<T> {
+        match RSafeArrayNd::with_lower_bound(rsa, 0) {
+            Ok(rsa) => rsa, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+impl<T: Debug> Debug for RSafeArrayNd<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bounds: Vec<(c_long, ULONG)> = self.bounds.iter().map(|b| (b.lLbound, b.cElements)).collect();
+        f.debug_struct("RSafeArrayNd")
+            .field("bounds", &bounds)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+impl<T> RSafeArrayNd<T> {
+    fn same_bounds(&self, other: &RSafeArrayNd<T>) -> bool {
+        self.bounds.len() == other.bounds.len() && 
+        self.bounds.iter().zip(other.bounds.iter()).all(|(l, r)| {
+            l.lLbound == r.lLbound && l.cElements == r.cElements
+        })
+    }
+}
+
+impl<T: PartialEq> PartialEq for RSafeArrayNd<T> {
+    fn eq(&self, other: &RSafeArrayNd<T>) -> bool {
+        self.same_bounds(other) && self.data == other.data
+    }
+}
+
+//Arrays with different shapes are unordered
+impl<T: PartialOrd> PartialOrd for RSafeArrayNd<T> {
+    fn partial_cmp(&self, other: &RSafeArrayNd<T>) -> Option<Ordering> {
+        if self.same_bounds(other) {
+            self.data.partial_cmp(&other.data)
+        } else {
+            None
+        }
+    }
+}
+
+//Element types that can be copied straight out of SAFEARRAY memory. 
+//Implementors must have the same size and layout as the C element type for vartype().
+pub unsafe trait PodElement: Copy {
+    fn vartype() -> VARENUM;
+    fn accepts(vt: VARTYPE) -> bool {
+        vt as u32 == Self::vartype()
+    }
+}
+
+macro_rules! POD_IMPLS {
+    ($({$elem_type:ty, $vt:ident $(| $alt_vt:ident)*})*) => {
+        $(
+            unsafe impl PodElement for $elem_type {
+                fn vartype() -> VARENUM {
+                    $vt
+                }
+                fn accepts(vt: VARTYPE) -> bool {
+                    let vt = vt as u32;
+                    vt == $vt $(|| vt == $alt_vt)*
+                }
+            }
+        )*
+    };
+}
+
+POD_IMPLS!{
+    {i8, VT_I1}
+    {u8, VT_UI1}
+    {i16, VT_I2}
+    {u16, VT_UI2}
+    {i32, VT_I4 | VT_INT}
+    {u32, VT_UI4 | VT_UINT}
+    {i64, VT_I8}
+    {u64, VT_UI8}
+    {f32, VT_R4}
+    {f64, VT_R8}
+}
+
+//Borrowed view of a SAFEARRAY's elements, in memory (column-major) order. 
+//Holds a SafeArrayAccessData lock which is released on drop. 
+pub struct SafeArrayData<'a, E: PodElement + 'a> {
+    psa: *mut SAFEARRAY, 
+    data: *const E, 
+    len: usize, 
+    _marker: PhantomData<&'a [E]>
+}
+
+impl<'a, E: PodElement> SafeArrayData<'a, E> {
+    //psa must stay alive for 'a and isn't destroyed by the guard. 
+    pub unsafe fn new(psa: *mut SAFEARRAY) -> Result<SafeArrayData<'a, E>> {
+        if psa.is_null() {
+            return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::SafeArray(line!())});
+        }
+        let vt = read_vartype(psa)?;
+        if !E::accepts(vt) || SafeArrayGetElemSize(psa) as usize != mem::size_of::<E>() {
+            return Err(ClrError::VartypeMismatch{expected: E::vartype() as VARTYPE, found: vt});
+        }
+        let sa_dims = SafeArrayGetDim(psa);
+        if sa_dims == 0 {
+            return Err(ClrError::SafeArrayDimensions{dims: sa_dims, source: SourceLocation::SafeArray(line!())});
+        }
+        let len: usize = read_bounds(psa, sa_dims)?.iter().map(|b| b.cElements as usize).product();
+        let mut pv: *mut c_void = ptr::null_mut();
+        let hr = SafeArrayAccessData(psa, &mut pv);
+        SUCCEEDED!(hr, SafeArrayData {
+            psa: psa, 
+            data: pv as *const E, 
+            len: len, 
+            _marker: PhantomData
+        }, SafeArray)
+    }
+
+    pub fn as_slice(&self) -> &[E] {
+        if self.len == 0 || self.data.is_null() {
+            return &[];
+        }
+        unsafe {
+            slice::from_raw_parts(self.data, self.len)
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<E> {
+        self.as_slice().to_vec()
+    }
+}
+
+impl<'a, E: PodElement> Deref for SafeArrayData<'a, E> {
+    type Target = [E];
+    fn deref(&self) -> &[E] {
+        self.as_slice()
+    }
+}
+
+impl<'a, E: PodElement> Drop for SafeArrayData<'a, E> {
+    fn drop(&mut self) {
+        unsafe {
+            SafeArrayUnaccessData(self.psa)
+        };
+    }
 }
 
 struct SafeArrayDestructor {
@@ -217,8 +569,8 @@ struct SafeArrayDestructor {
 }
 
 impl SafeArrayDestructor {
+    //p may be null, in which case there is nothing to destroy
     fn new(p: *mut SAFEARRAY) -> SafeArrayDestructor {
-        assert!(!p.is_null());
         SafeArrayDestructor{
             inner: p, 
             _marker: PhantomData
@@ -238,291 +590,624 @@ impl Drop for SafeArrayDestructor {
     }
 }
 
-impl<T> From<*mut SAFEARRAY> for RSafeArray<T> {
-    fn from(psa: *mut SAFEARRAY) -> RSafeArray<T> {
-        let _sad = SafeArrayDestructor::new(psa);
-        let sa_dims = unsafe {SafeArrayGetDim(psa)};
-        assert!(sa_dims > 0); //ensure we aren't dealing with a dimensionless safearr
-        let vt = unsafe {
-            let mut vt: VARTYPE = 0;
-            let hr = SafeArrayGetVartype(psa, &mut vt);
-            println!("hr = 0x{:x}", hr);
-            vt
-        };
-        if sa_dims == 1 {
-            let (lower_bound, upper_bound) = unsafe {
-                let mut lower_bound: c_long = 0;
-                let mut upper_bound: c_long = 0;
-                let hr = SafeArrayGetLBound(psa, 1, &mut lower_bound);
-                println!("L - hr = 0x{:x}", hr);
-                let hr = SafeArrayGetUBound(psa, 1, &mut upper_bound);
-                println!("U - hr = 0x{:x}", hr);
-                (lower_bound, upper_bound)
-            };
-            match vt as u32 {
-                VT_I2 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0i16;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::Shorts(vc)
-                },
-                VT_I4 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0i32;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::Longs(vc)
-                },
-                VT_R4 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i:f32 = 0.0;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::Floats(vc)
-                },
-                VT_R8 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i:f64 = 0.0;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::Doubles(vc)
-                },
-                VT_BSTR => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut bs: BSTR = ptr::null_mut();
-                        let hr = SafeArrayGetElement(psa, &ix, &mut bs as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        let s = bstring::BString::from_ptr_safe(bs).to_string();
-                        vc.push(s)
-                    }
-                    RSafeArray::BStrings(vc)
-                },
-                VT_DISPATCH => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut p: *mut IDispatch = ptr::null_mut();
-                        let hr = SafeArrayGetElement(psa, &ix, &mut p as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(p);
-                    }
-                    RSafeArray::Dispatchs(vc, None)
-                },
-                VT_BOOL => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut vb: VARIANT_BOOL = 0;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut vb as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(vb == -1);
-                    }
-                    RSafeArray::Bools(vc)
-                },
-                VT_VARIANT => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut vt: *mut VARIANT = ptr::null_mut();
-                        let hr = SafeArrayGetElement(psa, &ix, &mut vt as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        let vr = Variant::from_c_variant(*vt);
-                        vc.push(vr);
-                    }
-                    RSafeArray::Variants(vc)
-                },
-                VT_UNKNOWN => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut pu: *mut IUnknown = ptr::null_mut();
-                        let hr = SafeArrayGetElement(psa, &ix, &mut pu as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(pu);
-                    }
-                    RSafeArray::Unknowns(vc, None)
-                },
-                VT_I1 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0i8;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::Chars(vc)
-                },
-                VT_UI1 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0u8;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::UChars(vc)
-                },
-                VT_UI2 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0u16;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::UShorts(vc)
-                },
-                VT_UI4 => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0u32;
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(i);
-                    }
-                    RSafeArray::ULongs(vc)
-                },
-                VT_INT => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0i32;
-
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(Int(i));
-                    }
-                    RSafeArray::Ints(vc)
-                },
-                VT_UINT => unsafe {
-                    let mut vc = Vec::new();
-                    for ix in lower_bound..upper_bound {
-                        let mut i = 0u32;
-
-                        let hr = SafeArrayGetElement(psa, &ix, &mut i as *mut _ as *mut c_void);
-                        println!("loop {} - hr = 0x{:x}", ix, hr);
-                        vc.push(UInt(i));
-                    }
-                    RSafeArray::UInts(vc)
-                },
-                _ => panic!("Not supported!")
+//Walks every index of an array with the given bounds, leftmost dimension varying fastest. 
+//This is the order SAFEARRAY lays its elements out in memory. 
+//Each index is written into the same buffer, so only one is live at a time.
+struct ColumnMajorIndices<'a> {
+    bounds: &'a [SAFEARRAYBOUND], 
+    current: Vec<c_long>, 
+    total: usize, 
+    visited: usize
+}
+
+impl<'a> ColumnMajorIndices<'a> {
+    fn new(bounds: &'a [SAFEARRAYBOUND]) -> ColumnMajorIndices<'a> {
+        let total = if bounds.is_empty() {0} else {bounds.iter().map(|b| b.cElements as usize).product()};
+        ColumnMajorIndices {
+            bounds: bounds, 
+            current: bounds.iter().map(|b| b.lLbound).collect(), 
+            total: total, 
+            visited: 0
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.total
+    }
+
+    fn next_index(&mut self) -> Option<&[c_long]> {
+        if self.visited == self.total {
+            return None;
+        }
+        if self.visited > 0 {
+            //compared as i64, the last index of a dimension can be c_long::max_value()
+            for (dim, bound) in self.bounds.iter().enumerate() {
+                if (self.current[dim] as i64) + 1 < bound.lLbound as i64 + bound.cElements as i64 {
+                    self.current[dim] += 1;
+                    break;
+                }
+                self.current[dim] = bound.lLbound;
             }
         }
-        else {
-            panic!("Nested safearrays not yet supported.");
+        self.visited += 1;
+        Some(&self.current)
+    }
+}
+
+unsafe fn read_vartype(psa: *mut SAFEARRAY) -> Result<VARTYPE> {
+    let mut vt: VARTYPE = 0;
+    let hr = SafeArrayGetVartype(psa, &mut vt);
+    trace!("SafeArrayGetVartype({:p}) - hr = 0x{:x}, vt = 0x{:x}", psa, hr, vt);
+    SUCCEEDED!(hr, vt, SafeArray)
+}
+
+//Bounds are returned in SafeArrayCreate order, ie leftmost dimension first. 
+unsafe fn read_bounds(psa: *mut SAFEARRAY, dims: UINT) -> Result<Vec<SAFEARRAYBOUND>> {
+    (1..dims + 1).map(|dim| {
+        let mut lower_bound: c_long = 0;
+        let mut upper_bound: c_long = 0;
+        let hr = SafeArrayGetLBound(psa, dim, &mut lower_bound);
+        trace!("SafeArrayGetLBound({:p}, {}) - hr = 0x{:x}, lbound = {}", psa, dim, hr, lower_bound);
+        if hr != 0 {
+            return Err(ClrError::InnerCall{hr: hr, source: SourceLocation::SafeArray(line!())});
+        }
+        let hr = SafeArrayGetUBound(psa, dim, &mut upper_bound);
+        trace!("SafeArrayGetUBound({:p}, {}) - hr = 0x{:x}, ubound = {}", psa, dim, hr, upper_bound);
+        if hr != 0 {
+            return Err(ClrError::InnerCall{hr: hr, source: SourceLocation::SafeArray(line!())});
         }
+        //an empty dimension has its upper bound one below the lower
+        let count = upper_bound as i64 - lower_bound as i64 + 1;
+        match ULONG::try_from(count) {
+            Ok(count) => Ok(SAFEARRAYBOUND {cElements: count, lLbound: lower_bound}), 
+            Err(_) => Err(ClrError::SafeArrayBound{lower: lower_bound as i64, upper: upper_bound as i64, source: SourceLocation::SafeArray(line!())})
+        }
+    }).collect()
+}
+
+unsafe fn get_elements<E, R, F>(psa: *mut SAFEARRAY, bounds: &[SAFEARRAYBOUND], init: E, conv: F) -> Result<Vec<R>> 
+    where E: Copy, 
+          F: Fn(E) -> Result<R>
+{
+    let mut indices = ColumnMajorIndices::new(bounds);
+    let mut vc = Vec::with_capacity(indices.len());
+    while let Some(ix) = indices.next_index() {
+        let mut elem = init;
+        let hr = SafeArrayGetElement(psa, ix.as_ptr(), &mut elem as *mut _ as *mut c_void);
+        trace!("SafeArrayGetElement({:p}, {:?}) - hr = 0x{:x}", psa, ix, hr);
+        if hr != 0 {
+            debug!("SafeArrayGetElement({:p}, {:?}) failed - hr = 0x{:x}", psa, ix, hr);
+            return Err(ClrError::SafeArrayElement{hr: hr, index: ix.to_vec()});
+        }
+        vc.push(conv(elem)?);
     }
+    Ok(vc)
 }
 
-macro_rules! FROM_RUST_MATCH {
-    ($match_name:ident, $psa:ident, {$($enum_name:ident,)*}) => {
-        match $match_name {
-            $(
-                RSafeArray::$enum_name(array) => {
-                    for (ix, mut elem) in array.into_iter().enumerate() {
-                        let _hr = unsafe {
-                            SafeArrayPutElement($psa, &(ix as i32), &mut elem as *mut _ as *mut c_void)
-                        };
-                    }
-                }
-            ),*
-            _ => panic!("Unsupported variants")
+unsafe fn copy_elements<E: PodElement>(psa: *mut SAFEARRAY) -> Result<Vec<E>> {
+    let data: SafeArrayData<E> = SafeArrayData::new(psa)?;
+    Ok(data.to_vec())
+}
+
+//Single memcpy for the numeric vartypes, None for anything that has to go element by element
+unsafe fn bulk_read_elements<T>(psa: *mut SAFEARRAY, vt: VARTYPE) -> Result<Option<RSafeArray<T>>> {
+    let rsa = match vt as u32 {
+        VT_I2 => RSafeArray::Shorts(copy_elements(psa)?),
+        VT_I4 => RSafeArray::Longs(copy_elements(psa)?),
+        VT_R4 => RSafeArray::Floats(copy_elements(psa)?),
+        VT_R8 => RSafeArray::Doubles(copy_elements(psa)?),
+        VT_I1 => RSafeArray::Chars(copy_elements(psa)?),
+        VT_UI1 => RSafeArray::UChars(copy_elements(psa)?),
+        VT_UI2 => RSafeArray::UShorts(copy_elements(psa)?),
+        VT_UI4 => RSafeArray::ULongs(copy_elements(psa)?),
+        VT_INT => RSafeArray::Ints(copy_elements::<i32>(psa)?.into_iter().map(Int).collect()),
+        VT_UINT => RSafeArray::UInts(copy_elements::<u32>(psa)?.into_iter().map(UInt).collect()),
+        _ => return Ok(None)
+    };
+    Ok(Some(rsa))
+}
+
+//Records are deep copied out of the array memory, which is in the same order as ColumnMajorIndices
+unsafe fn read_records(psa: *mut SAFEARRAY, len: usize) -> Result<Vec<Record>> {
+    let mut info: *mut IRecordInfo = ptr::null_mut();
+    let hr = SafeArrayGetRecordInfo(psa, &mut info);
+    trace!("SafeArrayGetRecordInfo({:p}) - hr = 0x{:x}", psa, hr);
+    SUCCEEDED!(hr, (), SafeArray)?;
+    //the Records take references of their own, this one is released on return
+    let info = ComPtr::from_raw(info);
+    let elem_size = SafeArrayGetElemSize(psa) as usize;
+    let mut pv: *mut c_void = ptr::null_mut();
+    let hr = SafeArrayAccessData(psa, &mut pv);
+    SUCCEEDED!(hr, (), SafeArray)?;
+    let records = (0..len).map(|ix| {
+        Record::from_raw(info.as_ptr(), (pv as *const u8).offset((ix * elem_size) as isize) as *const c_void)
+    }).collect();
+    SafeArrayUnaccessData(psa);
+    records
+}
+
+//Each element is copied into a zeroed VARIANT by SafeArrayGetElement, read in place and then cleared. 
+//Interface pointers directly in an element keep the reference the copy took, as Dispatchs and Unknowns do. 
+unsafe fn read_variants(psa: *mut SAFEARRAY, bounds: &[SAFEARRAYBOUND], mode: StringMode) -> Result<Vec<Variant>> {
+    let mut indices = ColumnMajorIndices::new(bounds);
+    let mut vc = Vec::with_capacity(indices.len());
+    while let Some(ix) = indices.next_index() {
+        let mut elem: VARIANT = mem::zeroed();
+        let hr = SafeArrayGetElement(psa, ix.as_ptr(), &mut elem as *mut _ as *mut c_void);
+        trace!("SafeArrayGetElement({:p}, {:?}) - hr = 0x{:x}", psa, ix, hr);
+        if hr != 0 {
+            debug!("SafeArrayGetElement({:p}, {:?}) failed - hr = 0x{:x}", psa, ix, hr);
+            return Err(ClrError::SafeArrayElement{hr: hr, index: ix.to_vec()});
+        }
+        let v = Variant::try_from_c_variant_ref_as(&elem, mode);
+        let n2 = elem.n1.n2_mut();
+        match n2.vt as u32 {
+            VT_UNKNOWN | VT_DISPATCH if v.is_ok() => n2.vt = VT_EMPTY as VARTYPE, 
+            _ => {}
+        }
+        let hr = VariantClear(&mut elem);
+        if hr != 0 {
+            debug!("VariantClear(vt = 0x{:x}) failed - hr = 0x{:x}", elem.n1.n2().vt, hr);
+        }
+        vc.push(v?);
+    }
+    Ok(vc)
+}
+
+unsafe fn read_elements<T>(psa: *mut SAFEARRAY, vt: VARTYPE, bounds: &[SAFEARRAYBOUND], mode: StringMode) -> Result<RSafeArray<T>> {
+    let rsa = match vt as u32 {
+        VT_BSTR if mode == StringMode::Wide => RSafeArray::WStrings(get_elements(psa, bounds, ptr::null_mut() as BSTR, |bs| {
+            Ok(SysString::from_raw(bs).to_bstring())
+        })?),
+        VT_I2 => RSafeArray::Shorts(get_elements(psa, bounds, 0i16, Ok)?),
+        VT_I4 => RSafeArray::Longs(get_elements(psa, bounds, 0i32, Ok)?),
+        VT_R4 => RSafeArray::Floats(get_elements(psa, bounds, 0.0f32, Ok)?),
+        VT_R8 => RSafeArray::Doubles(get_elements(psa, bounds, 0.0f64, Ok)?),
+        VT_CY => RSafeArray::Currencies(get_elements(psa, bounds, CY {int64: 0}, |cy| Ok(Currency::from(cy)))?),
+        VT_DATE => RSafeArray::Dates(get_elements(psa, bounds, 0.0 as DATE, |dt| Ok(Date(dt)))?),
+        //SafeArrayGetElement hands back a copy, which SysString frees
+        VT_BSTR => RSafeArray::BStrings(get_elements(psa, bounds, ptr::null_mut() as BSTR, |bs| {
+            let bs = SysString::from_raw(bs);
+            if mode == StringMode::Strict {bs.to_string_strict()} else {Ok(bs.to_string_lossy())}
+        })?),
+        VT_DISPATCH => RSafeArray::Dispatchs(get_elements(psa, bounds, ptr::null_mut() as *mut IDispatch, |p| Ok(ComPtr::from_raw(p)))?, None),
+        VT_BOOL => RSafeArray::Bools(get_elements(psa, bounds, 0 as VARIANT_BOOL, |vb| Ok(vb == -1))?),
+        VT_VARIANT => RSafeArray::Variants(read_variants(psa, bounds, mode)?),
+        VT_UNKNOWN => RSafeArray::Unknowns(get_elements(psa, bounds, ptr::null_mut() as *mut IUnknown, |p| Ok(ComPtr::from_raw(p)))?, None),
+        VT_DECIMAL => RSafeArray::Decimals(get_elements(psa, bounds, mem::zeroed::<DECIMAL>(), |dec| Ok(build_rust_decimal(dec)))?),
+        VT_I1 => RSafeArray::Chars(get_elements(psa, bounds, 0i8, Ok)?),
+        VT_UI1 => RSafeArray::UChars(get_elements(psa, bounds, 0u8, Ok)?),
+        VT_UI2 => RSafeArray::UShorts(get_elements(psa, bounds, 0u16, Ok)?),
+        VT_UI4 => RSafeArray::ULongs(get_elements(psa, bounds, 0u32, Ok)?),
+        VT_INT => RSafeArray::Ints(get_elements(psa, bounds, 0i32, |i| Ok(Int(i)))?),
+        VT_UINT => RSafeArray::UInts(get_elements(psa, bounds, 0u32, |u| Ok(UInt(u)))?),
+        VT_RECORD => RSafeArray::Records(read_records(psa, ColumnMajorIndices::new(bounds).len())?),
+        _ => {
+            debug!("unsupported SAFEARRAY vartype 0x{:x}", vt);
+            return Err(ClrError::UnsupportedVartype{vt: vt, source: SourceLocation::SafeArray(line!())})
         }
     };
+    Ok(rsa)
 }
 
-impl<T> From<RSafeArray<T>> for *mut SAFEARRAY
-    where T: Debug
-{
-    //consumes underlying object, is this memory safe? 
-    fn from(rsa: RSafeArray<T>) -> *mut SAFEARRAY {
-        let c_elements: ULONG = rsa.len() as u32;
-        let vartype = rsa.vartype();
-        let mut sab = SAFEARRAYBOUND {cElements: c_elements, lLbound: 0i32};
-        let psa = unsafe {
-            SafeArrayCreate(vartype as u16, 1, &mut sab)
-        };
+//Takes ownership of psa, which is destroyed whether or not the conversion succeeds. 
+unsafe fn read_safearray<T>(psa: *mut SAFEARRAY, max_dims: Option<UINT>, mode: StringMode) -> Result<RSafeArrayNd<T>> {
+    if psa.is_null() {
+        return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::SafeArray(line!())});
+    }
+    let _sad = SafeArrayDestructor::new(psa);
+    read_borrowed_safearray(psa, max_dims, mode)
+}
 
-        //Allocate struct to safely destroy SAFEARRAY if there is a panic or crash during this process.
-        let mut sad = SafeArrayDestructor::new(psa);
-        if let RSafeArray::Bools(array) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
+//Copies the elements out and leaves psa as it was
+unsafe fn read_borrowed_safearray<T>(psa: *mut SAFEARRAY, max_dims: Option<UINT>, mode: StringMode) -> Result<RSafeArrayNd<T>> {
+    if psa.is_null() {
+        return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::SafeArray(line!())});
+    }
+    let sa_dims = SafeArrayGetDim(psa);
+    //ensure we aren't dealing with a dimensionless safearr
+    if sa_dims == 0 || max_dims.map_or(false, |max| sa_dims > max) {
+        return Err(ClrError::SafeArrayDimensions{dims: sa_dims, source: SourceLocation::SafeArray(line!())});
+    }
+    let vt = read_vartype(psa)?;
+    let bounds = read_bounds(psa, sa_dims)?;
+    let data = match bulk_read_elements(psa, vt)? {
+        Some(data) => data, 
+        None => read_elements(psa, vt, &bounds, mode)?
+    };
+    Ok(RSafeArrayNd {bounds: bounds, data: data})
+}
+
+impl<T> RSafeArray<T> {
+    //Fallible counterpart to From<*mut SAFEARRAY>. 
+    //psa must be a SAFEARRAY the caller owns, or null. It is destroyed either way.
+    pub unsafe fn try_from_ptr(psa: *mut SAFEARRAY) -> Result<RSafeArray<T>> {
+        RSafeArray::try_from_ptr_as(psa, StringMode::Lossy)
+    }
+
+    pub unsafe fn try_from_ptr_as(psa: *mut SAFEARRAY, mode: StringMode) -> Result<RSafeArray<T>> {
+        read_safearray(psa, Some(1), mode).map(RSafeArrayNd::into_column_major)
+    }
+}
+
+impl<T> RSafeArrayNd<T> {
+    //Fallible counterpart to From<*mut SAFEARRAY>. 
+    //psa must be a SAFEARRAY the caller owns, or null. It is destroyed either way.
+    pub unsafe fn try_from_ptr(psa: *mut SAFEARRAY) -> Result<RSafeArrayNd<T>> {
+        RSafeArrayNd::try_from_ptr_as(psa, StringMode::Lossy)
+    }
+
+    pub unsafe fn try_from_ptr_as(psa: *mut SAFEARRAY, mode: StringMode) -> Result<RSafeArrayNd<T>> {
+        read_safearray(psa, None, mode)
+    }
+
+    //Like try_from_ptr, but psa is left for the caller to destroy. psa must be a valid SAFEARRAY or null.
+    pub unsafe fn try_from_borrowed_ptr(psa: *mut SAFEARRAY) -> Result<RSafeArrayNd<T>> {
+        read_borrowed_safearray(psa, None, StringMode::Lossy)
+    }
+
+    pub unsafe fn try_from_borrowed_ptr_as(psa: *mut SAFEARRAY, mode: StringMode) -> Result<RSafeArrayNd<T>> {
+        read_borrowed_safearray(psa, None, mode)
+    }
+}
+
+//Writes pv at the next index. SafeArrayPutElement copies what pv points at, the caller keeps its own.
+unsafe fn put_element(psa: *mut SAFEARRAY, indices: &mut ColumnMajorIndices, pv: *mut c_void) -> Result<()> {
+    let ix = match indices.next_index() {
+        Some(ix) => ix, 
+        None => return Err(ClrError::InnerCall{hr: DISP_E_BADINDEX, source: SourceLocation::SafeArray(line!())})
+    };
+    let hr = SafeArrayPutElement(psa, ix.as_ptr(), pv);
+    trace!("SafeArrayPutElement({:p}, {:?}) - hr = 0x{:x}", psa, ix, hr);
+    if hr != 0 {
+        debug!("SafeArrayPutElement({:p}, {:?}) failed - hr = 0x{:x}", psa, ix, hr);
+        return Err(ClrError::SafeArrayElement{hr: hr, index: ix.to_vec()});
+    }
+    Ok(())
+}
+
+//Elements whose Rust layout is what the SAFEARRAY stores
+unsafe fn put_elements<E>(psa: *mut SAFEARRAY, indices: &mut ColumnMajorIndices, array: Vec<E>) -> Result<()> {
+    for mut elem in array {
+        put_element(psa, indices, &mut elem as *mut _ as *mut c_void)?;
+    }
+    Ok(())
+}
+
+unsafe fn fill_safearray<T>(psa: *mut SAFEARRAY, bounds: &[SAFEARRAYBOUND], rsa: RSafeArray<T>) -> Result<()> {
+    let mut indices = ColumnMajorIndices::new(bounds);
+    let indices = &mut indices;
+    match rsa {
+        RSafeArray::Bools(array) => {
+            for elem in array {
                 let mut vb_elem: VARIANT_BOOL = if elem {-1} else {0};
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut vb_elem as *mut _ as *mut c_void)
-                };
+                put_element(psa, indices, &mut vb_elem as *mut _ as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::Variants(array) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
-                let mut var_elem = elem.into_c_variant();
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut var_elem as *mut _ as *mut c_void)
-                };
+        }, 
+        RSafeArray::Variants(array) => {
+            for elem in array {
+                //the array's copy would point at targets boxed up for our copy, which is gone once it's put
+                let vt = elem.vartype();
+                if vt as u32 & VT_BYREF != 0 {
+                    debug!("by-reference VARIANT 0x{:x} can't go in a SAFEARRAY", vt);
+                    return Err(ClrError::UnsupportedVartype{vt: vt, source: SourceLocation::SafeArray(line!())});
+                }
+                //SafeArrayPutElement copies the VARIANT, ours is cleared on drop
+                let mut var_elem = OwnedVariant::try_new(elem)?;
+                put_element(psa, indices, var_elem.as_mut_ptr() as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::BStrings(array) = rsa {
-            for(ix, mut elem) in array.into_iter().enumerate() {
-                let mut bs: bstring::BString = From::from(elem);
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut bs.as_sys() as *mut _ as *mut c_void)
-                };
+        }, 
+        RSafeArray::BStrings(array) => {
+            for elem in array {
+                //SafeArrayPutElement copies the string, ours is freed at the end of the iteration
+                let sys: SysString = From::from(elem);
+                put_element(psa, indices, sys.as_in() as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::Dispatchs(array, _) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut elem as *mut _ as *mut c_void)
-                };
+        }, 
+        RSafeArray::WStrings(array) => {
+            for elem in array {
+                let sys = elem.to_sys();
+                put_element(psa, indices, sys.as_in() as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::Unknowns(array, _) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut elem as *mut _ as *mut c_void)
-                };
+        }, 
+        RSafeArray::Dispatchs(array, _) => {
+            //SafeArrayPutElement AddRefs its own reference, ours is released on drop
+            for elem in array.iter() {
+                put_element(psa, indices, elem.as_ptr() as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::Currencies(array) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
+        }, 
+        RSafeArray::Unknowns(array, _) => {
+            for elem in array.iter() {
+                put_element(psa, indices, elem.as_ptr() as *mut c_void)?;
+            }
+        }, 
+        RSafeArray::Records(array) => {
+            //SafeArrayPutElement copies the record with IRecordInfo::RecordCopy, ours is cleared on drop
+            for elem in array {
+                put_element(psa, indices, elem.as_ptr() as *mut c_void)?;
+            }
+        }, 
+        RSafeArray::Currencies(array) => {
+            for elem in array {
                 let mut cy = CY::from(elem);
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut cy as *mut _ as *mut c_void)
-                };
+                put_element(psa, indices, &mut cy as *mut _ as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::Dates(array) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
+        }, 
+        RSafeArray::Dates(array) => {
+            for elem in array {
                 let mut dt = elem.0;
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut dt as *mut _ as *mut c_void)
-                };
+                put_element(psa, indices, &mut dt as *mut _ as *mut c_void)?;
             }
-        }
-        else if let RSafeArray::Decimals(array) = rsa {
-            for (ix, mut elem) in array.into_iter().enumerate() {
+        }, 
+        RSafeArray::Decimals(array) => {
+            for elem in array {
                 let mut cdec = build_c_decimal(elem);
-                let _hr = unsafe {
-                    SafeArrayPutElement(psa, &(ix as i32), &mut cdec as *mut _ as *mut c_void)
-                };
+                put_element(psa, indices, &mut cdec as *mut _ as *mut c_void)?;
             }
+        }, 
+        RSafeArray::Shorts(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::Longs(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::Floats(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::Doubles(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::Chars(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::UChars(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::UShorts(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::ULongs(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::Ints(array) => put_elements(psa, indices, array)?, 
+        RSafeArray::UInts(array) => put_elements(psa, indices, array)?, 
+    }
+    Ok(())
+}
+
+//SafeArrayCreateEx takes one IRecordInfo for the whole array, so there has to be a record to take it from
+//and the rest have to match it
+unsafe fn shared_record_info(records: &[Record]) -> Result<*mut IRecordInfo> {
+    let info = match records.first() {
+        Some(first) => first.info(), 
+        None => return Err(ClrError::RecordInfo{source: SourceLocation::SafeArray(line!())})
+    };
+    for record in &records[1..] {
+        if record.info() != info && (*info).IsMatchingType(record.info()) == 0 {
+            debug!("IRecordInfo {:p} doesn't match {:p}", record.info(), info);
+            return Err(ClrError::RecordInfo{source: SourceLocation::SafeArray(line!())});
+        }
+    }
+    Ok(info)
+}
+
+//Consumes rsa. On failure the half built SAFEARRAY is destroyed and nothing is returned.
+fn create_safearray<T>(rsa: RSafeArray<T>, bounds: &[SAFEARRAYBOUND]) -> Result<*mut SAFEARRAY> {
+    check_bounds(bounds, rsa.len())?;
+    let vartype = rsa.vartype();
+    let mut sabs = bounds.to_vec();
+    let psa = unsafe {
+        match rsa {
+            RSafeArray::Records(ref array) => {
+                let info = shared_record_info(array)?;
+                SafeArrayCreateEx(vartype as u16, sabs.len() as UINT, sabs.as_mut_ptr(), info as *mut c_void)
+            }, 
+            _ => SafeArrayCreate(vartype as u16, sabs.len() as UINT, sabs.as_mut_ptr())
+        }
+    };
+    trace!("SafeArrayCreate(0x{:x}, {}) - psa = {:p}", vartype, sabs.len(), psa);
+    if psa.is_null() {
+        debug!("SafeArrayCreate(0x{:x}, {}) failed", vartype, sabs.len());
+        return Err(ClrError::InnerCall{hr: E_OUTOFMEMORY, source: SourceLocation::SafeArray(line!())});
+    }
+
+    //Destroys the SAFEARRAY if an element can't be put, or there is a panic along the way
+    let mut sad = SafeArrayDestructor::new(psa);
+    unsafe {
+        fill_safearray(psa, bounds, rsa)?;
+    }
+    sad.inner = ptr::null_mut(); //ensure struct doesn't destroy the safearray
+    Ok(psa)
+}
+
+impl<T> RSafeArray<T> {
+    //Fallible counterpart to From<RSafeArray<T>> for *mut SAFEARRAY. The caller owns the SAFEARRAY.
+    pub fn try_into_ptr(self) -> Result<*mut SAFEARRAY> {
+        let c_elements: ULONG = self.len() as u32;
+        create_safearray(self, &[SAFEARRAYBOUND {cElements: c_elements, lLbound: 0i32}])
+    }
+}
+
+impl<T> RSafeArrayNd<T> {
+    //Fallible counterpart to From<RSafeArrayNd<T>> for *mut SAFEARRAY. The caller owns the SAFEARRAY.
+    pub fn try_into_ptr(self) -> Result<*mut SAFEARRAY> {
+        let RSafeArrayNd {bounds, data} = self;
+        create_safearray(data, &bounds)
+    }
+}
+
+impl<T> From<RSafeArray<T>> for *mut SAFEARRAY
+    where T: Debug
+{
+    fn from(rsa: RSafeArray<T>) -> *mut SAFEARRAY {
+        match rsa.try_into_ptr() {
+            Ok(psa) => psa, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+impl<T> From<RSafeArrayNd<T>> for *mut SAFEARRAY
+    where T: Debug
+{
+    fn from(rsa: RSafeArrayNd<T>) -> *mut SAFEARRAY {
+        match rsa.try_into_ptr() {
+            Ok(psa) => psa, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::sync::Once;
+    use std::sync::atomic::{AtomicBool, Ordering as AtomicOrdering};
+
+    #[test]
+    fn bulk_data() {
+        let mut sa = StandInSafeArray::new(vec![1.0f64, 2.5, -3.0]);
+        {
+            let data: SafeArrayData<f64> = unsafe {SafeArrayData::new(sa.as_ptr()).unwrap()};
+            assert_eq!(&*data, &[1.0f64, 2.5, -3.0]);
+            assert_eq!(unsafe {(*sa.as_ptr()).cLocks}, 1);
+        }
+        assert_eq!(unsafe {(*sa.as_ptr()).cLocks}, 0);
+    }
+
+    #[test]
+    fn bulk_data_nd() {
+        let bounds = [SAFEARRAYBOUND {cElements: 2, lLbound: 1}, SAFEARRAYBOUND {cElements: 3, lLbound: -1}];
+        let mut sa = StandInSafeArray::with_bounds((0..6).collect::<Vec<i32>>(), &bounds);
+        let data: SafeArrayData<i32> = unsafe {SafeArrayData::new(sa.as_ptr()).unwrap()};
+        assert_eq!(data.to_vec(), sa.data().to_vec());
+    }
+
+    #[test]
+    fn bulk_data_mismatch() {
+        let mut sa = StandInSafeArray::new(vec![1i16, 2, 3]);
+        match unsafe {SafeArrayData::<i32>::new(sa.as_ptr())} {
+            Err(ClrError::VartypeMismatch{expected, found}) => {
+                assert_eq!(expected, VT_I4 as VARTYPE);
+                assert_eq!(found, VT_I2 as VARTYPE);
+            }, 
+            _ => panic!("expected a vartype mismatch")
         }
-        else {
-            FROM_RUST_MATCH!{rsa, psa, 
-                {Shorts,   Longs,    Floats,   Doubles, Chars, 
-                 UChars, UShorts, ULongs, Ints, UInts,}
-            };
+        assert_eq!(unsafe {(*sa.as_ptr()).cLocks}, 0);
+    }
+
+    #[test]
+    fn bstr_elements() {
+        let words: Vec<SysString> = ["one", "", "three"].iter().map(|&w| From::from(w)).collect();
+        let mut sa = StandInSafeArray::bstrs(words.iter().map(SysString::as_in).collect());
+        let rsa = unsafe {RSafeArrayNd::<()>::try_from_borrowed_ptr(sa.as_ptr()).unwrap()};
+        assert_eq!(rsa.into_column_major(), RSafeArray::BStrings(vec![String::from("one"), String::new(), String::from("three")]));
+    }
+
+    #[test]
+    fn wide_elements() {
+        let wide = vec![BString::from_vec(vec![0xDFFF, 0x41]), BString::from_str("ok")];
+        let words: Vec<SysString> = wide.iter().map(|w| w.to_sys()).collect();
+        let mut sa = StandInSafeArray::bstrs(words.iter().map(SysString::as_in).collect());
+        let rsa = unsafe {RSafeArrayNd::<()>::try_from_borrowed_ptr_as(sa.as_ptr(), StringMode::Wide).unwrap()};
+        assert_eq!(rsa.into_column_major(), RSafeArray::WStrings(wide));
+        match unsafe {RSafeArrayNd::<()>::try_from_borrowed_ptr_as(sa.as_ptr(), StringMode::Strict)} {
+            Err(ClrError::InvalidUtf16{unit, ..}) => assert_eq!(unit, 0xDFFF), 
+            _ => panic!("expected an invalid UTF-16 error")
         }
-        sad.inner = ptr::null_mut(); //ensure struct doesn't destroy the safearray
-        psa
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn naive_date_times() {
+        use chrono::NaiveDate;
+        let dts = vec![NaiveDate::from_ymd_opt(2018, 1, 1).unwrap().and_hms_opt(12, 0, 0).unwrap(), 
+                       NaiveDate::from_ymd_opt(1899, 12, 29).unwrap().and_hms_opt(6, 0, 0).unwrap()];
+        let rsa = RSafeArray::<()>::from_naive_date_times(&dts).unwrap();
+        assert_eq!(rsa, RSafeArray::Dates(vec![Date(43101.5), Date(-1.25)]));
+        assert_eq!(rsa.to_naive_date_times().unwrap(), dts);
+        match RSafeArray::<()>::Doubles(vec![1.0]).to_naive_date_times() {
+            Err(ClrError::VartypeMismatch{expected, ..}) => assert_eq!(expected, VT_DATE as VARTYPE), 
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Id(i32);
+
+    unsafe impl ::record::RecordPayload for Id {}
+
+    #[test]
+    fn records_need_one_info() {
+        use record::mock::MockRecordInfo;
+        let info = MockRecordInfo::new(4);
+        let other = MockRecordInfo::new(4);
+        let record = |info: &MockRecordInfo, id: i32| unsafe {Record::from_struct(info.as_ptr(), Id(id))}.unwrap();
+
+        match RSafeArray::<()>::Records(Vec::new()).try_into_ptr() {
+            Err(ClrError::RecordInfo{..}) => {}, 
+            other => panic!("Unexpected result: {:?}", other)
+        }
+        match RSafeArray::<()>::Records(vec![record(&info, 1), record(&other, 2)]).try_into_ptr() {
+            Err(ClrError::RecordInfo{..}) => {}, 
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn records_round_trip() {
+        use record::mock::MockRecordInfo;
+        let info = MockRecordInfo::new(4);
+        let record = |id: i32| unsafe {Record::from_struct(info.as_ptr(), Id(id))}.unwrap();
+        let psa = RSafeArray::<()>::Records(vec![record(1), record(2)]).try_into_ptr().unwrap();
+        //the records were dropped once copied in, the array keeps a reference of its own
+        assert_eq!(info.refs(), 1);
+        let rsa: RSafeArray<()> = unsafe {RSafeArray::try_from_ptr(psa)}.unwrap();
+        match rsa {
+            RSafeArray::Records(ref records) => {
+                let ids: Vec<Id> = records.iter().map(|r| r.view_struct().unwrap()).collect();
+                assert_eq!(ids, vec![Id(1), Id(2)]);
+                assert!(records.iter().all(|r| r.as_ptr() as usize % 8 == 0));
+            }, 
+            ref other => panic!("Unexpected array: {:?}", other)
+        }
+        assert_eq!(info.refs(), 2);
+        drop(rsa);
+        assert_eq!(info.refs(), 0);
+    }
+
+    //Keeps what is logged on threads that asked for it, every other thread is ignored
+    struct Capture;
+
+    thread_local! {
+        static CAPTURED: RefCell<Option<Vec<String>>> = RefCell::new(None);
+    }
+
+    impl ::log::Log for Capture {
+        fn enabled(&self, _: &::log::Metadata) -> bool {
+            true
+        }
+        fn log(&self, record: &::log::Record) {
+            CAPTURED.with(|captured| {
+                if let Some(lines) = captured.borrow_mut().as_mut() {
+                    lines.push(format!("{} {}", record.level(), record.args()));
+                }
+            });
+        }
+        fn flush(&self) {}
+    }
+
+    //The logger is global to the test binary, so it is installed once and left in place. 
+    //False if some other logger got there first.
+    fn capture_logs() -> bool {
+        static LOGGER: Capture = Capture;
+        static INSTALL: Once = Once::new();
+        static INSTALLED: AtomicBool = AtomicBool::new(false);
+        INSTALL.call_once(|| {
+            if ::log::set_logger(&LOGGER).is_ok() {
+                ::log::set_max_level(::log::LevelFilter::Trace);
+                INSTALLED.store(true, AtomicOrdering::SeqCst);
+            }
+        });
+        CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+        INSTALLED.load(AtomicOrdering::SeqCst)
+    }
+
+    #[test]
+    fn diagnostics_routed_through_log() {
+        if !capture_logs() {
+            return;
+        }
+        let mut sa = StandInSafeArray::new(vec![1u16, 2]);
+        let vt = unsafe {read_vartype(sa.as_ptr()).unwrap()};
+        assert_eq!(vt, VT_UI2 as VARTYPE);
+        let captured = CAPTURED.with(|captured| captured.borrow_mut().take().unwrap());
+        assert!(captured.iter().any(|line| line.starts_with("TRACE SafeArrayGetVartype") && line.ends_with("vt = 0x12")));
+    }
+}
@@ -20,6 +20,7 @@
 //  SOFTWARE.
 
 use std::mem;
+use std::ptr;
 
 use rust_decimal::Decimal;
 
@@ -30,14 +31,21 @@ use winapi::shared::wtypes::{BSTR,     CY,     DATE, DECIMAL,     DECIMAL_NEG,
                              VT_DATE,  VT_DECIMAL,   VT_DISPATCH, VT_EMPTY, 
                              VT_ERROR, VT_INT,      VT_I1,        VT_I2,       
                              VT_I4,    VT_I8,       VT_NULL,      VT_R4,    
-                             VT_R8,    VT_UINT,     VT_UI1,       VT_UI2,      
-                             VT_UI4,   VT_UI8,      VT_UNKNOWN,   VT_VARIANT, };
+                             VT_R8,    VT_RECORD,   VT_UINT,      VT_UI1,       
+                             VT_UI2,   VT_UI4,      VT_UI8,       VT_UNKNOWN,   
+                             VT_VARIANT, };
+use winapi::shared::winerror::E_POINTER;
 use winapi::shared::wtypesbase::SCODE;
 use winapi::um::unknwnbase::IUnknown;
-use winapi::um::oaidl::{IDispatch, SAFEARRAY, VARIANT, VARIANT_n1, __tagVARIANT, VARIANT_n3};
+use winapi::um::oaidl::{IDispatch, SAFEARRAY, VARIANT};
 
 use bstring;
-use new_safearray::{LPSAFEARRAY, RSafeArray};
+use bstring::StringMode;
+use com_ptr::ComPtr;
+use result::{ClrError, SourceLocation, Result};
+use new_safearray::{RSafeArray, RSafeArrayNd, SafeArrayDestroy};
+use owned_variant::{OleAut32, OwnedVariant, VariantAllocator};
+use record::Record;
 
 const VT_PBYTE: VARENUM = VT_BYREF|VT_UI1;
 const VT_PSHORT: VARENUM = VT_BYREF|VT_I2;
@@ -62,7 +70,8 @@ const VT_PULONGLONG: VARENUM = VT_BYREF|VT_UI8;
 const VT_PINT: VARENUM = VT_BYREF|VT_INT; 
 const VT_PUINT: VARENUM = VT_BYREF|VT_UINT;
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+//Fixed point, the value times 10,000. See currency.rs for arithmetic and conversions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Currency(pub i64);
 
 impl From<i64> for Currency {
@@ -174,6 +183,163 @@ mod tests {
        // assert_eq!(new_d.is_sign_positive(), true);
         assert_eq!(format!("{}", new_d), "1208925819333149903028225"  );
     }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Pair {
+        key: i32, 
+        value: i32
+    }
+
+    unsafe impl ::record::RecordPayload for Pair {}
+
+    #[test]
+    fn record_round_trip() {
+        use record::mock::MockRecordInfo;
+        let info = MockRecordInfo::new(8);
+        let record = unsafe {Record::from_struct(info.as_ptr(), Pair {key: 3, value: -4})}.unwrap();
+        let mut v = Variant::from(record.clone());
+        v.as_variant_ref_in(::owned_variant::mock::CountingAllocator::default()).finish().unwrap();
+        match v {
+            Variant::Record(back) => {
+                assert_eq!(back, record);
+                assert_eq!(back.view_struct::<Pair>().unwrap(), Pair {key: 3, value: -4});
+            },
+            other => panic!("Unexpected variant: {:?}", other)
+        }
+    }
+
+    //Stands in for a COM method taking an [in, out] VARIANT*
+    unsafe fn callee(pvar: *mut VARIANT) {
+        let n2 = (*pvar).n1.n2_mut();
+        let n3 = &mut n2.n3;
+        match n2.vt as u32 {
+            VT_PBOOL => **n3.pboolVal_mut() = -1, 
+            VT_PLONG => **n3.plVal_mut() += 1, 
+            VT_PCY => (**n3.pcyVal_mut()).int64 *= 2, 
+            VT_PDATE => **n3.pdate_mut() += 1.5, 
+            VT_PINT => **n3.pintVal_mut() = -7, 
+            VT_PDECIMAL => (**n3.pdecVal_mut()).sign = DECIMAL_NEG, 
+            VT_PVARIANT => callee(*n3.pvarVal_mut()), 
+            VT_I4 => *n3.lVal_mut() = 42, 
+            VT_BSTR | VT_PBSTR => {
+                let pbstr = if n2.vt as u32 == VT_BSTR {n3.bstrVal_mut() as *mut BSTR} else {*n3.pbstrVal_mut()};
+                let reply: Vec<u16> = "pong".encode_utf16().collect();
+                assert!(::winapi::um::oleauto::SysReAllocStringLen(pbstr, reply.as_ptr(), reply.len() as u32) != 0);
+            },
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn by_ref_write_back() {
+        let cases = vec![
+            (Variant::PBool(Box::new(false)), Variant::PBool(Box::new(true))), 
+            (Variant::PLong(Box::new(9)), Variant::PLong(Box::new(10))), 
+            (Variant::PCurrency(Box::new(Currency(25000))), Variant::PCurrency(Box::new(Currency(50000)))), 
+            (Variant::PDate(Box::new(Date(43000.25))), Variant::PDate(Box::new(Date(43001.75)))), 
+            (Variant::PInt(Box::new(Int(3))), Variant::PInt(Box::new(Int(-7)))), 
+            (Variant::PDecimal(Box::new(Decimal::new(125, 2))), Variant::PDecimal(Box::new(Decimal::new(-125, 2)))), 
+            (Variant::PVariant(Box::new(Variant::PLong(Box::new(1)))), Variant::PVariant(Box::new(Variant::PLong(Box::new(2))))), 
+            (Variant::Long(0), Variant::Long(42)), 
+            (Variant::Decimal(Decimal::new(-31415, 4)), Variant::Decimal(Decimal::new(-31415, 4))), 
+        ];
+        for (mut v, expected) in cases {
+            {
+                let mut vref = v.as_variant_ref();
+                unsafe {
+                    callee(vref.as_mut_ptr());
+                }
+                vref.finish().unwrap();
+            }
+            assert_eq!(v, expected);
+        }
+    }
+
+    #[test]
+    fn bstr_write_back() {
+        use owned_variant::mock::CountingAllocator;
+        let cases = vec![
+            (Variant::BString(String::from("ping")), Variant::BString(String::from("pong"))), 
+            (Variant::PBString(Box::new(String::from("ping"))), Variant::PBString(Box::new(String::from("pong")))), 
+        ];
+        for (mut v, expected) in cases {
+            let alloc = CountingAllocator::default();
+            {
+                let mut vref = v.as_variant_ref_in(alloc.clone());
+                unsafe {
+                    callee(vref.as_mut_ptr());
+                }
+                vref.finish().unwrap();
+            }
+            assert_eq!(v, expected);
+            assert_eq!(alloc.clears.get(), 1);
+        }
+    }
+
+    #[test]
+    fn unpaired_surrogates() {
+        use owned_variant::mock::CountingAllocator;
+        let wide = bstring::BString::from_vec(vec![0x61, 0xD800, 0x62]);
+        let owned = OwnedVariant::new_in(Variant::WString(wide.clone()), CountingAllocator::default());
+        assert_eq!(owned.to_variant_as(StringMode::Wide).unwrap(), Variant::WString(wide.clone()));
+        assert_eq!(owned.to_variant().unwrap(), Variant::BString(String::from("a\u{FFFD}b")));
+        match owned.to_variant_as(StringMode::Strict) {
+            Err(ClrError::InvalidUtf16{unit, index}) => {
+                assert_eq!(unit, 0xD800);
+                assert_eq!(index, 1);
+            },
+            other => panic!("Unexpected result: {:?}", other)
+        }
+
+        let mut v = Variant::PWString(Box::new(wide.clone()));
+        v.as_variant_ref_in(CountingAllocator::default()).finish().unwrap();
+        assert_eq!(v, Variant::PWString(Box::new(wide)));
+    }
+
+    #[test]
+    fn by_ref_write_back_on_drop() {
+        let mut v = Variant::PBool(Box::new(false));
+        {
+            let mut vref = v.as_variant_ref();
+            unsafe {
+                callee(vref.as_mut_ptr());
+            }
+        }
+        assert_eq!(v, Variant::PBool(Box::new(true)));
+    }
+
+    #[test]
+    fn unknown_vartype() {
+        let mut vt: VARIANT = unsafe {mem::zeroed()};
+        unsafe {
+            let n2 = vt.n1.n2_mut();
+            n2.vt = 0x0FFF;
+        }
+        match Variant::try_from_c_variant(vt) {
+            Err(ClrError::UnsupportedVartype{vt, ..}) => assert_eq!(vt, 0x0FFF), 
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn null_by_ref() {
+        for &vt in &[VT_PLONG, VT_PBSTR, VT_PVARIANT, VT_PARRAY | VT_I4] {
+            let mut raw: VARIANT = unsafe {mem::zeroed()};
+            unsafe {
+                raw.n1.n2_mut().vt = vt as VARTYPE;
+            }
+            match Variant::try_from_c_variant_ref(&raw) {
+                Err(ClrError::InnerCall{hr, ..}) => assert_eq!(hr, E_POINTER), 
+                other => panic!("Unexpected result for 0x{:x}: {:?}", vt, other)
+            }
+        }
+        let mut raw: VARIANT = unsafe {mem::zeroed()};
+        unsafe {
+            raw.n1.n2_mut().vt = VT_BYREF as VARTYPE;
+        }
+        assert_eq!(Variant::try_from_c_variant_ref(&raw).unwrap(), Variant::ByRef(ptr::null_mut()));
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -191,8 +357,8 @@ pub enum Variant {
     BString(String),
     Unknown(*mut IUnknown), 
     Dispatch(*mut IDispatch), 
-    Array(RSafeArray), 
-    //BRecord needs to be handled eventually
+    Array(RSafeArrayNd), 
+    Record(Record), 
     PByte(Box<u8>), 
     PShort(Box<i16>), 
     PLong(Box<i32>), 
@@ -206,7 +372,7 @@ pub enum Variant {
     PBString(Box<String>), 
     PUnknown(Box<*mut IUnknown>), 
     PDispatch(Box<*mut IDispatch>), 
-    PArray(Box<RSafeArray>), 
+    PArray(Box<RSafeArrayNd>), 
     PVariant(Box<Variant>), 
     ByRef(*mut c_void), 
     Char(i8), 
@@ -222,6 +388,8 @@ pub enum Variant {
     PULongLong(Box<u64>), 
     PInt(Box<Int>), 
     PUInt(Box<UInt>),
+    WString(bstring::BString), 
+    PWString(Box<bstring::BString>), 
     //non n3 variants 
     Decimal(Decimal),
     Empty(()), 
@@ -256,6 +424,17 @@ macro_rules! BRANCH_FROM_RAW {
     };
 }
 
+unsafe fn read_bstr(p: BSTR, mode: StringMode, by_ref: bool) -> Result<Variant> {
+    let bs = bstring::BStr::from_raw(p);
+    let s = match mode {
+        StringMode::Wide if by_ref => return Ok(Variant::PWString(Box::new(bs.to_bstring()))), 
+        StringMode::Wide => return Ok(Variant::WString(bs.to_bstring())), 
+        StringMode::Strict => bs.to_string_strict()?, 
+        StringMode::Lossy => bs.to_string_lossy()
+    };
+    Ok(if by_ref {Variant::PBString(Box::new(s))} else {Variant::BString(s)})
+}
+
 impl Variant {
     pub fn vartype(&self) -> VARTYPE {
         let vt = match self {
@@ -272,7 +451,8 @@ impl Variant {
             Variant::BString(_) => VT_BSTR, 
             Variant::Unknown(_) => VT_UNKNOWN, 
             Variant::Dispatch(_) => VT_DISPATCH,
-            Variant::Array(_) => VT_ARRAY, 
+            Variant::Array(array) => VT_ARRAY | array.vartype(), 
+            Variant::Record(_) => VT_RECORD, 
             Variant::PByte(_) => VT_PBYTE, 
             Variant::PShort(_) => VT_PSHORT, 
             Variant::PLong(_) => VT_PLONG, 
@@ -286,7 +466,7 @@ impl Variant {
             Variant::PBString(_) => VT_PBSTR,
             Variant::PUnknown(_) => VT_PUNKNOWN, 
             Variant::PDispatch(_) => VT_PDISPATCH, 
-            Variant::PArray(_) => VT_PARRAY, 
+            Variant::PArray(array) => VT_PARRAY | array.vartype(), 
             Variant::PVariant(_) => VT_PVARIANT,
             Variant::ByRef(_) => VT_BYREF, 
             Variant::Char(_) => VT_I1, 
@@ -302,6 +482,8 @@ impl Variant {
             Variant::PULongLong(_) => VT_PULONGLONG,
             Variant::PInt(_) => VT_PINT, 
             Variant::PUInt(_) => VT_PUINT,
+            Variant::WString(_) => VT_BSTR, 
+            Variant::PWString(_) => VT_PBSTR,
             Variant::Decimal(_) => VT_DECIMAL,
             Variant::Empty(_) => VT_EMPTY,
             Variant::Null(_) => VT_NULL
@@ -309,19 +491,57 @@ impl Variant {
         vt as u16
     }
 
+    #[deprecated(since = "0.2.0", note = "panics on a VARIANT it can't read, use try_from_c_variant")]
     pub fn from_c_variant(vt: VARIANT) -> Variant {
-        let mut n1 = vt.n1;
-        
-        let vt: VARTYPE = unsafe {
-            let n2_mut = n1.n2_mut();
-            n2_mut.vt
-        };
-        let mut n3 = unsafe {
-            let n2_mut = n1.n2_mut();   
-            n2_mut.n3
+        match Variant::try_from_c_variant(vt) {
+            Ok(v) => v, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    //Safearrays in vt are destroyed once read, nothing else in it is released. 
+    pub fn try_from_c_variant(vt: VARIANT) -> Result<Variant> {
+        Variant::try_from_c_variant_as(vt, StringMode::Lossy)
+    }
+
+    pub fn try_from_c_variant_as(vt: VARIANT, mode: StringMode) -> Result<Variant> {
+        let result = Variant::try_from_c_variant_ref_as(&vt, mode);
+        unsafe {
+            let n2 = vt.n1.n2();
+            let psa = match n2.vt as u32 & (VT_BYREF | VT_ARRAY) {
+                VT_ARRAY => *n2.n3.parray(), 
+                VT_PARRAY if !(*n2.n3.pparray()).is_null() => **n2.n3.pparray(), 
+                _ => ptr::null_mut()
+            };
+            if !psa.is_null() {
+                SafeArrayDestroy(psa);
+            }
+        }
+        result
+    }
+
+    //Reads in place and leaves pvar as it was. 
+    //Reading in place also means pointers in the union are never copied out as integers. 
+    pub fn try_from_c_variant_ref(pvar: &VARIANT) -> Result<Variant> {
+        Variant::try_from_c_variant_ref_as(pvar, StringMode::Lossy)
+    }
+
+    //mode picks how BSTRs come out, in this VARIANT and any array or VARIANT it points to
+    pub fn try_from_c_variant_ref_as(pvar: &VARIANT, mode: StringMode) -> Result<Variant> {
+        let n1 = &pvar.n1;
+        let n2 = unsafe {
+            n1.n2()
         };
+        let vt: VARTYPE = n2.vt;
+        trace!("VARIANT vt = 0x{:x}", vt);
+        let n3 = &n2.n3;
+        //every by-reference arm below reads through this pointer, a bare VT_BYREF is only passed along
+        if vt as u32 & VT_BYREF != 0 && vt as u32 != VT_BYREF && unsafe {n3.byref()}.is_null() {
+            debug!("null by-reference VARIANT, vt = 0x{:x}", vt);
+            return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::Variant(line!())});
+        }
         //have to special case bool/pbool conversions because of VARIANT_BOOL
-        match vt as u32 {
+        let v = match vt as u32 {
             VT_I8 => BRANCH_FROM_RAW!{S, LongLong(n3, llVal)},
             VT_I4 => BRANCH_FROM_RAW!{S, Long(n3, lVal)}, 
             VT_UI1 => BRANCH_FROM_RAW!{S, Byte(n3, bVal)},
@@ -341,15 +561,19 @@ impl Variant {
                 Variant::Date(Date(val))
             }; (n3, date)},
             VT_BSTR => BRANCH_FROM_RAW!{C, |val| {
-                let bs = bstring::BString::from_ptr_safe(val);
-                Variant::BString(bs.to_string())
-            }; (n3, bstrVal)},
+                unsafe {read_bstr(val, mode, false)}
+            }; (n3, bstrVal)}?,
             VT_UNKNOWN => BRANCH_FROM_RAW!{S, Unknown(n3, punkVal)}, 
             VT_DISPATCH => BRANCH_FROM_RAW!{S, Dispatch(n3, pdispVal)}, 
-            VT_ARRAY => BRANCH_FROM_RAW!{C, |val| {
-                let rsa: RSafeArray<i32> = RSafeArray::from(val);
-                Variant::Array(rsa)
-            }; (n3, parray)}, 
+            //arrays carry their element vartype, eg. VT_ARRAY | VT_VARIANT for object[]
+            vt if vt & (VT_BYREF | VT_ARRAY) == VT_ARRAY => {
+                let val = unsafe {*n3.parray()};
+                Variant::Array(unsafe {RSafeArrayNd::try_from_borrowed_ptr_as(val, mode)}?)
+            }, 
+            VT_RECORD => {
+                let brecord = unsafe {*n3.n4()};
+                Variant::Record(unsafe {Record::from_raw(brecord.pRecInfo, brecord.pvRecord)}?)
+            }, 
             VT_PBYTE => BRANCH_FROM_RAW!{C, |val: *mut u8| {
                 Variant::PByte(Box::new(unsafe{*val}))
             }; (n3, pbVal) },
@@ -381,28 +605,22 @@ impl Variant {
                 Variant::PDate(Box::new(Date(unsafe{*val})))
             }; (n3, pdate)},
             VT_PBSTR => BRANCH_FROM_RAW!{C, |val: *mut BSTR| {
-                Variant::PBString(
-                    Box::new(
-                        bstring::BString::from_ptr_safe(
-                            unsafe{
-                                *val
-                            }
-                        ).to_string()
-                    )
-                )
-            }; (n3, pbstrVal )},
+                unsafe {read_bstr(*val, mode, true)}
+            }; (n3, pbstrVal )}?,
             VT_PUNKNOWN => BRANCH_FROM_RAW!{C, |val: *mut *mut IUnknown| {
                 Variant::PUnknown(Box::new(unsafe{*val}))
-                }; (n3, ppunkVal_mut)},
+                }; (n3, ppunkVal)},
             VT_PDISPATCH => BRANCH_FROM_RAW!{C, |val: *mut *mut IDispatch| {
                 Variant::PDispatch(Box::new(unsafe{*val}))
-                }; (n3, ppdispVal_mut)},
-            VT_PARRAY => BRANCH_FROM_RAW!{C, |val: *mut *mut SAFEARRAY| {
-                Variant::PArray(Box::new(RSafeArray::from(unsafe{*val})))
-                }; (n3, pparray)},
-            VT_PVARIANT => BRANCH_FROM_RAW!{C, |val: *mut VARIANT | {
-                Variant::PVariant(Box::new(Variant::from_c_variant(unsafe{*val})))
-                }; (n3, pvarVal)},
+                }; (n3, ppdispVal)},
+            vt if vt & (VT_BYREF | VT_ARRAY) == VT_PARRAY => {
+                let val: *mut *mut SAFEARRAY = unsafe {*n3.pparray()};
+                Variant::PArray(Box::new(unsafe {RSafeArrayNd::try_from_borrowed_ptr_as(*val, mode)}?))
+            },
+            VT_PVARIANT => {
+                let val: *mut VARIANT = unsafe {*n3.pvarVal()};
+                Variant::PVariant(Box::new(Variant::try_from_c_variant_ref_as(unsafe{&*val}, mode)?))
+            },
             VT_BYREF => BRANCH_FROM_RAW!{C, |val: *mut c_void| {
                 Variant::ByRef(val)}; (n3, byref)
                 },
@@ -447,14 +665,42 @@ impl Variant {
             VT_NULL => {
                 Variant::Null(())
             },
-            _ => panic!("Unknown vartype: {}", vt)
-        }
+            _ => {
+                debug!("unsupported VARIANT vartype 0x{:x}", vt);
+                return Err(ClrError::UnsupportedVartype{vt: vt, source: SourceLocation::Variant(line!())})
+            }
+        };
+        Ok(v)
     }
 
+    //By-reference targets are moved into Box allocations that the VARIANT points at. 
+    //Nothing frees them on its own, see OwnedVariant and as_variant_ref. 
     pub fn into_c_variant(self) -> VARIANT {
+        match self.try_into_c_variant() {
+            Ok(variant) => variant, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    //Fails when an array or record can't be built, eg. a SAFEARRAY element is rejected
+    pub fn try_into_c_variant(self) -> Result<VARIANT> {
+        let mut variant: VARIANT = unsafe { mem::zeroed() };
+        self.try_write_c_variant(&mut variant)?;
+        Ok(variant)
+    }
+
+    //Writes in place, whatever variant held before is overwritten without being cleared. 
+    //By-reference targets are boxed up the same way as into_c_variant. 
+    pub fn write_c_variant(self, variant: &mut VARIANT) {
+        if let Err(e) = self.try_write_c_variant(variant) {
+            panic!("{}", e);
+        }
+    }
+
+    //On failure variant is left as it was
+    pub fn try_write_c_variant(self, variant: &mut VARIANT) -> Result<()> {
         let vt = self.vartype();
-        let mut n3: VARIANT_n3 = unsafe { mem::zeroed()};
-        let mut n1: VARIANT_n1 = unsafe { mem::zeroed()};
+        let n3 = unsafe { &mut variant.n1.n2_mut().n3 };
         
         match self {
             Variant::LongLong(val) => unsafe {
@@ -503,7 +749,7 @@ impl Variant {
             Variant::BString(inner) => unsafe {
                 let mut n_ptr = n3.bstrVal_mut();
                 let bs: bstring::BString = From::from(inner);
-                *n_ptr = bs.as_sys();
+                *n_ptr = bs.to_sys().into_raw();
             },
             Variant::Unknown(ptr) => unsafe {
                 let mut n_ptr = n3.punkVal_mut();
@@ -515,7 +761,15 @@ impl Variant {
             }, 
             Variant::Array(array) => unsafe {
                 let mut n_ptr = n3.parray_mut();
-                *n_ptr = LPSAFEARRAY::from(array);
+                *n_ptr = array.try_into_ptr()?;
+            }, 
+            Variant::Record(record) => unsafe {
+                //VariantClear releases pRecInfo, so the VARIANT gets a reference of its own
+                let info = ComPtr::from_raw_add_ref(record.info());
+                let pv = record.into_raw()?;
+                let n_ptr = n3.n4_mut();
+                n_ptr.pRecInfo = info.into_raw();
+                n_ptr.pvRecord = pv;
             }, 
             Variant::PByte(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pbVal_mut();
@@ -547,27 +801,27 @@ impl Variant {
                 // -1 = true, 0 = false
                 // wtf
                 let b_val = *boxed_ptr;
-                let mut vb_val: VARIANT_BOOL = if b_val {-1} else {0};
-                *n_ptr = &mut vb_val;
+                let vb_val: VARIANT_BOOL = if b_val {-1} else {0};
+                *n_ptr = Box::into_raw(Box::new(vb_val));
             }, 
-            Variant::PErrorCode(mut boxed_ptr) => unsafe {
+            Variant::PErrorCode(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pscode_mut();
-                *n_ptr = &mut (*boxed_ptr).0 as *mut SCODE; 
+                let sc_val: SCODE = (*boxed_ptr).0;
+                *n_ptr = Box::into_raw(Box::new(sc_val));
             },
             Variant::PCurrency(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pcyVal_mut();
-                let cy = *boxed_ptr;
-                *n_ptr = &mut CY::from(cy) as *mut CY;
+                *n_ptr = Box::into_raw(Box::new(CY::from(*boxed_ptr)));
             },
             Variant::PDate(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pdate_mut();
-                let mut dt = (*boxed_ptr).0;
-                *n_ptr = &mut dt as *mut DATE;
+                let dt: DATE = (*boxed_ptr).0;
+                *n_ptr = Box::into_raw(Box::new(dt));
             },
             Variant::PBString(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pbstrVal_mut();
-                let mut bs: bstring::BString = From::from(*boxed_ptr);
-                *n_ptr = &mut bs.as_sys() as *mut BSTR;
+                let bs: bstring::BString = From::from(*boxed_ptr);
+                *n_ptr = Box::into_raw(Box::new(bs.to_sys().into_raw()));
             }, 
             Variant::PUnknown(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.ppunkVal_mut();
@@ -579,13 +833,14 @@ impl Variant {
             }, 
             Variant::PArray(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pparray_mut();
-                let mut psa: *mut SAFEARRAY = <*mut SAFEARRAY>::from(*boxed_ptr);
-                *n_ptr = &mut psa;
+                let psa: *mut SAFEARRAY = boxed_ptr.try_into_ptr()?;
+                *n_ptr = Box::into_raw(Box::new(psa));
             },
             Variant::PVariant(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pvarVal_mut();
-                let mut vt = Variant::into_c_variant(*boxed_ptr);
-                *n_ptr = &mut vt;
+                let mut boxed_vt: Box<VARIANT> = Box::new(mem::zeroed());
+                (*boxed_ptr).try_write_c_variant(&mut boxed_vt)?;
+                *n_ptr = Box::into_raw(boxed_vt);
             }
             Variant::ByRef(ptr) => unsafe {
                 let mut n_ptr = n3.byref_mut();
@@ -638,33 +893,89 @@ impl Variant {
                 let mut n_ptr = n3.pullVal_mut();
                 *n_ptr = Box::into_raw(boxed_ptr);
             }, 
-            Variant::PInt(mut boxed_ptr) => unsafe {
+            Variant::PInt(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.pintVal_mut();
-                *n_ptr = &mut (*boxed_ptr).0;
+                *n_ptr = Box::into_raw(Box::new((*boxed_ptr).0));
             },
-            Variant::PUInt(mut boxed_ptr) => unsafe {
+            Variant::PUInt(boxed_ptr) => unsafe {
                 let mut n_ptr = n3.puintVal_mut();
-                *n_ptr = &mut (*boxed_ptr).0;
+                *n_ptr = Box::into_raw(Box::new((*boxed_ptr).0));
+            },
+            Variant::WString(inner) => unsafe {
+                let n_ptr = n3.bstrVal_mut();
+                *n_ptr = inner.to_sys().into_raw();
             },
+            Variant::PWString(boxed_ptr) => unsafe {
+                let n_ptr = n3.pbstrVal_mut();
+                *n_ptr = Box::into_raw(Box::new(boxed_ptr.to_sys().into_raw()));
+            },
+            //DECIMAL spans the whole union, its wReserved field is where vt goes
             Variant::Decimal(dec) => unsafe {
-                let mut n_ptr = n1.decVal_mut();
+                let n_ptr = variant.n1.decVal_mut();
                 *n_ptr = build_c_decimal(dec);
             },
             Variant::Empty(_) => {}, 
             Variant::Null(_) => {},
         };
 
-        let tv = __tagVARIANT { vt: vt, 
-                                wReserved1: 0, 
-                                wReserved2: 0, 
-                                wReserved3: 0,
-                                n3: n3 };
         unsafe {
-            let n_ptr = n1.n2_mut();
-            *n_ptr = tv;
+            variant.n1.n2_mut().vt = vt;
+        };
+        Ok(())
+    }
+
+    pub fn as_variant_ref(&mut self) -> VariantRef<'_> {
+        self.as_variant_ref_in(OleAut32)
+    }
+
+    pub fn as_variant_ref_in<A: VariantAllocator>(&mut self, alloc: A) -> VariantRef<'_, A> {
+        let owned = OwnedVariant::new_in(self.clone(), alloc);
+        VariantRef {
+            target: self, 
+            owned: Some(owned)
+        }
+    }
+}
+
+//A VARIANT lent out to an [in, out] call. 
+//By-reference targets are allocations owned here, so they stay valid until the VariantRef is finished or dropped, 
+//at which point whatever the callee left in them is written back to the Variant. 
+pub struct VariantRef<'a, A: VariantAllocator = OleAut32> {
+    target: &'a mut Variant, 
+    owned: Option<OwnedVariant<A>>
+}
+
+impl<'a, A: VariantAllocator> VariantRef<'a, A> {
+    pub fn as_mut_ptr(&mut self) -> *mut VARIANT {
+        self.owned.as_mut().expect("VariantRef already written back").as_mut_ptr()
+    }
+
+    //Drop does the same, but can only log a failed conversion
+    pub fn finish(mut self) -> Result<()> {
+        self.write_back()
+    }
+
+    fn write_back(&mut self) -> Result<()> {
+        let owned = match self.owned.take() {
+            Some(owned) => owned, 
+            None => return Ok(())
         };
+        //strings read back the way they went out
+        let mode = match *self.target {
+            Variant::WString(_) | Variant::PWString(_) => StringMode::Wide, 
+            _ => StringMode::Lossy
+        };
+        let v = owned.to_variant_as(mode)?;
+        *self.target = v;
+        Ok(())
+    }
+}
 
-        VARIANT {n1: n1}
+impl<'a, A: VariantAllocator> Drop for VariantRef<'a, A> {
+    fn drop(&mut self) {
+        if let Err(e) = self.write_back() {
+            debug!("VariantRef write back failed: {}", e);
+        }
     }
 }
 
@@ -703,7 +1014,8 @@ FROM_IMPLS!{
     (String, BString),
     (*mut IUnknown, Unknown),
     (*mut IDispatch, Dispatch),
-    (RSafeArray, Array),
+    (RSafeArrayNd, Array),
+    (Record, Record),
     (Box<u8>, PByte),
     (Box<i16>, PShort),
     (Box<i32>, PLong),
@@ -717,7 +1029,7 @@ FROM_IMPLS!{
     (Box<String>, PBString), 
     (Box<*mut IUnknown>, PUnknown), 
     (Box<*mut IDispatch>, PDispatch), 
-    (Box<RSafeArray>, PArray), 
+    (Box<RSafeArrayNd>, PArray), 
     (Box<Variant>, PVariant), 
     (*mut c_void, ByRef), 
     (i8, Char), 
@@ -733,9 +1045,23 @@ FROM_IMPLS!{
     (Box<u64>, PULongLong),
     (Box<Int>, PInt), 
     (Box<UInt>, PUInt),
+    (bstring::BString, WString), 
+    (Box<bstring::BString>, PWString), 
     (Decimal, Decimal)
 }
 
+impl From<RSafeArray> for Variant {
+    fn from(rsa: RSafeArray) -> Variant {
+        Variant::Array(RSafeArrayNd::from(rsa))
+    }
+}
+
+impl From<Box<RSafeArray>> for Variant {
+    fn from(boxed_rsa: Box<RSafeArray>) -> Variant {
+        Variant::PArray(Box::new(RSafeArrayNd::from(*boxed_rsa)))
+    }
+}
+
 impl From<Box<IUnknown>> for Variant {
     fn from(boxed_ptr: Box<IUnknown>) -> Variant {
         Variant::Unknown(Box::into_raw(boxed_ptr))
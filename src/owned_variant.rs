@@ -0,0 +1,518 @@
+// owned_variant.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+use std::fmt;
+use std::mem;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::winerror::HRESULT;
+use winapi::shared::wtypes::{DECIMAL, VARTYPE, VT_ARRAY, VT_BOOL, VT_BSTR, VT_BYREF,
+                             VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH, VT_EMPTY, VT_ERROR,
+                             VT_INT, VT_I1, VT_I2, VT_I4, VT_I8, VT_R4, VT_R8, VT_RECORD,
+                             VT_UINT, VT_UI1, VT_UI2, VT_UI4, VT_UI8, VT_UNKNOWN, VT_VARIANT};
+use winapi::um::oaidl::VARIANT;
+use winapi::um::oleauto::{VariantClear, VariantCopy};
+
+use bstring::StringMode;
+use new_variant::Variant;
+use result::{ClrError, SourceLocation, Result};
+
+//The oleaut32 calls OwnedVariant needs, so they can be swapped out (eg. for leak tests).
+pub trait VariantAllocator {
+    unsafe fn clear(&self, pvar: *mut VARIANT) -> HRESULT;
+    unsafe fn copy(&self, dest: *mut VARIANT, src: *const VARIANT) -> HRESULT;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OleAut32;
+
+impl VariantAllocator for OleAut32 {
+    unsafe fn clear(&self, pvar: *mut VARIANT) -> HRESULT {
+        VariantClear(pvar)
+    }
+    unsafe fn copy(&self, dest: *mut VARIANT, src: *const VARIANT) -> HRESULT {
+        VariantCopy(dest, src)
+    }
+}
+
+//A VARIANT that is cleared on drop.
+//Built from a Variant it also owns the by-reference targets Variant::write_c_variant boxed up,
+//built from a raw VARIANT the by-reference targets stay with whoever lent them, as with VariantClear.
+pub struct OwnedVariant<A: VariantAllocator = OleAut32> {
+    raw: Box<VARIANT>,
+    owns_by_ref: bool,
+    alloc: A
+}
+
+impl OwnedVariant {
+    pub fn new(v: Variant) -> OwnedVariant {
+        OwnedVariant::new_in(v, OleAut32)
+    }
+
+    pub fn try_new(v: Variant) -> Result<OwnedVariant> {
+        OwnedVariant::try_new_in(v, OleAut32)
+    }
+
+    //Takes over a VARIANT, eg. an [out] parameter, which must not be cleared elsewhere
+    pub unsafe fn from_raw(raw: VARIANT) -> OwnedVariant {
+        OwnedVariant::from_raw_in(raw, OleAut32)
+    }
+}
+
+impl<A: VariantAllocator> OwnedVariant<A> {
+    pub fn new_in(v: Variant, alloc: A) -> OwnedVariant<A> {
+        match OwnedVariant::try_new_in(v, alloc) {
+            Ok(owned) => owned, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+
+    //Fails where Variant::try_write_c_variant does
+    pub fn try_new_in(v: Variant, alloc: A) -> Result<OwnedVariant<A>> {
+        let mut raw: Box<VARIANT> = Box::new(unsafe { mem::zeroed() });
+        v.try_write_c_variant(&mut raw)?;
+        unsafe {
+            add_refs(&raw);
+        }
+        Ok(OwnedVariant {
+            raw: raw,
+            owns_by_ref: true,
+            alloc: alloc
+        })
+    }
+
+    pub unsafe fn from_raw_in(raw: VARIANT, alloc: A) -> OwnedVariant<A> {
+        OwnedVariant {
+            raw: Box::new(raw),
+            owns_by_ref: false,
+            alloc: alloc
+        }
+    }
+
+    //Gives up ownership without clearing. Boxed by-reference targets are leaked along with it.
+    pub fn into_raw(self) -> VARIANT {
+        let mut this = self;
+        let raw = unsafe { ptr::read(&*this.raw) };
+        unsafe {
+            this.raw.n1.n2_mut().vt = VT_EMPTY as VARTYPE;
+        }
+        raw
+    }
+
+    pub fn as_ptr(&self) -> *const VARIANT {
+        &*self.raw as *const VARIANT
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut VARIANT {
+        &mut *self.raw as *mut VARIANT
+    }
+
+    pub fn vartype(&self) -> VARTYPE {
+        unsafe {
+            self.raw.n1.n2().vt
+        }
+    }
+
+    //Copies the contents out, the VARIANT is left as it was
+    pub fn to_variant(&self) -> Result<Variant> {
+        Variant::try_from_c_variant_ref(&self.raw)
+    }
+
+    pub fn to_variant_as(&self, mode: StringMode) -> Result<Variant> {
+        Variant::try_from_c_variant_ref_as(&self.raw, mode)
+    }
+
+    //Reads the contents and clears the VARIANT. Interface pointers directly in it keep its reference, 
+    //as they do in arrays read with RSafeArray::try_from_ptr, everything else is copied out first.
+    pub fn into_variant(self) -> Result<Variant> {
+        let mut this = self;
+        let v = this.to_variant()?;
+        let n2 = unsafe {this.raw.n1.n2_mut()};
+        match n2.vt as u32 {
+            VT_UNKNOWN | VT_DISPATCH => n2.vt = VT_EMPTY as VARTYPE, 
+            _ => {}
+        }
+        Ok(v)
+    }
+}
+
+impl<A: VariantAllocator> Drop for OwnedVariant<A> {
+    fn drop(&mut self) {
+        unsafe {
+            release(&mut *self.raw, self.owns_by_ref, &self.alloc);
+        }
+    }
+}
+
+impl<A: VariantAllocator + Clone> OwnedVariant<A> {
+    //Deep copy, boxed by-reference targets are copied into new boxes rather than shared
+    pub fn try_clone(&self) -> Result<OwnedVariant<A>> {
+        let mut raw: Box<VARIANT> = Box::new(unsafe { mem::zeroed() });
+        let hr = unsafe {
+            deep_copy(&self.raw, &mut raw, self.owns_by_ref, &self.alloc)
+        };
+        if hr != 0 {
+            debug!("deep copy of VARIANT 0x{:x} failed - hr = 0x{:x}", self.vartype(), hr);
+            return Err(ClrError::InnerCall{hr: hr, source: SourceLocation::Variant(line!())});
+        }
+        Ok(OwnedVariant {
+            raw: raw,
+            owns_by_ref: self.owns_by_ref,
+            alloc: self.alloc.clone()
+        })
+    }
+}
+
+impl<A: VariantAllocator + Clone> Clone for OwnedVariant<A> {
+    fn clone(&self) -> OwnedVariant<A> {
+        match self.try_clone() {
+            Ok(owned) => owned, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+impl<A: VariantAllocator> fmt::Debug for OwnedVariant<A> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("OwnedVariant")
+            .field("vt", &self.vartype())
+            .field("owns_by_ref", &self.owns_by_ref)
+            .finish()
+    }
+}
+
+impl From<Variant> for OwnedVariant {
+    fn from(v: Variant) -> OwnedVariant {
+        OwnedVariant::new(v)
+    }
+}
+
+//Whether VariantClear has anything to release for a by-value vt
+fn needs_clear(vt: u32) -> bool {
+    vt & VT_ARRAY != 0 || match vt {
+        VT_BSTR | VT_UNKNOWN | VT_DISPATCH | VT_RECORD | VT_VARIANT => true,
+        _ => false
+    }
+}
+
+//write_c_variant copies interface pointers without taking a reference, VariantClear will release one
+unsafe fn add_refs(pvar: &VARIANT) {
+    let n2 = pvar.n1.n2();
+    match n2.vt as u32 {
+        VT_UNKNOWN => {
+            let p = *n2.n3.punkVal();
+            if !p.is_null() {
+                (*p).AddRef();
+            }
+        },
+        VT_DISPATCH => {
+            let p = *n2.n3.pdispVal();
+            if !p.is_null() {
+                (*p).AddRef();
+            }
+        },
+        VT_RECORD => {
+            let info = n2.n3.n4().pRecInfo;
+            if !info.is_null() {
+                (*info).AddRef();
+            }
+        },
+        vt if vt == VT_BYREF | VT_VARIANT => {
+            let p = *n2.n3.pvarVal();
+            if !p.is_null() {
+                add_refs(&*p);
+            }
+        },
+        _ => {}
+    }
+}
+
+//Runs $func::<T> for the type write_c_variant boxes up for a by-reference base vartype
+macro_rules! BY_REF_TARGET {
+    ($base:expr, $func:ident($($args:expr),*)) => {
+        match $base {
+            VT_I1 | VT_UI1 => Some($func::<u8>($($args),*)),
+            VT_I2 | VT_UI2 | VT_BOOL => Some($func::<u16>($($args),*)),
+            VT_I4 | VT_UI4 | VT_INT | VT_UINT | VT_ERROR | VT_R4 => Some($func::<u32>($($args),*)),
+            VT_I8 | VT_UI8 | VT_R8 | VT_CY | VT_DATE => Some($func::<u64>($($args),*)),
+            VT_DECIMAL => Some($func::<DECIMAL>($($args),*)),
+            VT_UNKNOWN | VT_DISPATCH => Some($func::<*mut c_void>($($args),*)),
+            _ => None
+        }
+    };
+}
+
+unsafe fn free_box<T>(p: *mut c_void) {
+    drop(Box::from_raw(p as *mut T));
+}
+
+unsafe fn copy_box<T>(p: *mut c_void) -> *mut c_void {
+    Box::into_raw(Box::new(ptr::read(p as *const T))) as *mut c_void
+}
+
+//Fills tmp as a by-value VARIANT over the pointer in a BSTR or SAFEARRAY by-reference target.
+//Written in place, moving a VARIANT around would drop the pointer's provenance under Miri.
+unsafe fn pointer_variant(tmp: &mut VARIANT, vt: u32, p: *mut c_void) {
+    let n2 = tmp.n1.n2_mut();
+    n2.vt = vt as VARTYPE;
+    *n2.n3.byref_mut() = p;
+}
+
+unsafe fn release<A: VariantAllocator>(pvar: *mut VARIANT, owns_by_ref: bool, alloc: &A) {
+    let vt = (*pvar).n1.n2().vt as u32;
+    if vt & VT_BYREF != 0 {
+        let target = *(*pvar).n1.n2().n3.byref();
+        let base = vt & !VT_BYREF;
+        if owns_by_ref && base != VT_EMPTY && !target.is_null() {
+            release_target(base, target, alloc);
+        }
+    }
+    else if needs_clear(vt) {
+        let hr = alloc.clear(pvar);
+        trace!("VariantClear(vt = 0x{:x}) - hr = 0x{:x}", vt, hr);
+        if hr != 0 {
+            debug!("VariantClear(vt = 0x{:x}) failed - hr = 0x{:x}", vt, hr);
+        }
+    }
+    (*pvar).n1.n2_mut().vt = VT_EMPTY as VARTYPE;
+}
+
+unsafe fn release_target<A: VariantAllocator>(base: u32, target: *mut c_void, alloc: &A) {
+    if base == VT_VARIANT {
+        //built by write_c_variant as well, so its own targets are ours too
+        release(target as *mut VARIANT, true, alloc);
+        free_box::<VARIANT>(target);
+    }
+    else if base == VT_BSTR || base & VT_ARRAY != 0 {
+        let mut tmp: VARIANT = mem::zeroed();
+        pointer_variant(&mut tmp, base, *(target as *mut *mut c_void));
+        alloc.clear(&mut tmp);
+        free_box::<*mut c_void>(target);
+    }
+    else if BY_REF_TARGET!(base, free_box(target)).is_none() {
+        debug!("Leaking by-reference target of unknown vartype 0x{:x}", base);
+    }
+}
+
+unsafe fn deep_copy<A: VariantAllocator>(src: &VARIANT, dest: &mut VARIANT, owns_by_ref: bool, alloc: &A) -> HRESULT {
+    let vt = src.n1.n2().vt as u32;
+    let base = vt & !VT_BYREF;
+    if vt & VT_BYREF == 0 || !owns_by_ref || base == VT_EMPTY {
+        if needs_clear(vt) {
+            return alloc.copy(dest, src);
+        }
+        //nothing to duplicate, plain copy keeps any by-reference pointer as it was
+        ptr::copy_nonoverlapping(src, dest, 1);
+        return 0;
+    }
+    let target = *src.n1.n2().n3.byref();
+    let new_target = if target.is_null() {
+        ptr::null_mut()
+    }
+    else if base == VT_VARIANT {
+        let mut inner: Box<VARIANT> = Box::new(mem::zeroed());
+        let hr = deep_copy(&*(target as *const VARIANT), &mut inner, true, alloc);
+        if hr != 0 {
+            return hr;
+        }
+        Box::into_raw(inner) as *mut c_void
+    }
+    else if base == VT_BSTR || base & VT_ARRAY != 0 {
+        let mut tmp_src: VARIANT = mem::zeroed();
+        pointer_variant(&mut tmp_src, base, *(target as *mut *mut c_void));
+        let mut tmp_dest: VARIANT = mem::zeroed();
+        let hr = alloc.copy(&mut tmp_dest, &tmp_src);
+        if hr != 0 {
+            return hr;
+        }
+        Box::into_raw(Box::new(*tmp_dest.n1.n2().n3.byref())) as *mut c_void
+    }
+    else {
+        match BY_REF_TARGET!(base, copy_box(target)) {
+            Some(p) => p,
+            None => return ::winapi::shared::winerror::DISP_E_BADVARTYPE
+        }
+    };
+    let n2 = dest.n1.n2_mut();
+    n2.vt = vt as VARTYPE;
+    *n2.n3.byref_mut() = new_target;
+    0
+}
+
+#[cfg(test)]
+pub mod mock {
+    //Counts what it is asked to clear or copy. Frees BSTRs through oleaut32 and records through
+    //their IRecordInfo, everything else is ignored.
+    use std::cell::Cell;
+    use std::ptr;
+    use std::rc::Rc;
+
+    use winapi::shared::winerror::HRESULT;
+    use winapi::shared::wtypes::{VT_BSTR, VT_EMPTY, VT_RECORD};
+    use winapi::um::oaidl::VARIANT;
+
+    use bstr_alloc::{SysAllocStringLen, SysFreeString, SysStringLen};
+    use super::VariantAllocator;
+
+    #[derive(Debug, Clone, Default)]
+    pub struct CountingAllocator {
+        pub clears: Rc<Cell<usize>>,
+        pub copies: Rc<Cell<usize>>
+    }
+
+    impl VariantAllocator for CountingAllocator {
+        unsafe fn clear(&self, pvar: *mut VARIANT) -> HRESULT {
+            self.clears.set(self.clears.get() + 1);
+            let n2 = (*pvar).n1.n2_mut();
+            if n2.vt as u32 == VT_RECORD {
+                let brecord = *n2.n3.n4();
+                (*brecord.pRecInfo).RecordDestroy(brecord.pvRecord);
+                (*brecord.pRecInfo).Release();
+            }
+            else if n2.vt as u32 == VT_BSTR {
+                SysFreeString(*n2.n3.bstrVal());
+            }
+            n2.vt = VT_EMPTY as u16;
+            0
+        }
+        unsafe fn copy(&self, dest: *mut VARIANT, src: *const VARIANT) -> HRESULT {
+            self.copies.set(self.copies.get() + 1);
+            ptr::copy_nonoverlapping(src, dest, 1);
+            let n2 = (*dest).n1.n2_mut();
+            if n2.vt as u32 == VT_BSTR {
+                let bs = *n2.n3.bstrVal();
+                *n2.n3.bstrVal_mut() = SysAllocStringLen(bs, SysStringLen(bs));
+            }
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::mock::CountingAllocator;
+    use new_variant::{Currency, Int};
+
+    #[test]
+    fn scalars_skip_the_allocator() {
+        let alloc = CountingAllocator::default();
+        {
+            let owned = OwnedVariant::new_in(Variant::Long(5), alloc.clone());
+            let copy = owned.clone();
+            assert_eq!(copy.to_variant().unwrap(), Variant::Long(5));
+        }
+        assert_eq!(alloc.clears.get(), 0);
+        assert_eq!(alloc.copies.get(), 0);
+    }
+
+    #[test]
+    fn by_ref_targets_released() {
+        let alloc = CountingAllocator::default();
+        let values = vec![
+            Variant::PLong(Box::new(3)),
+            Variant::PBool(Box::new(true)),
+            Variant::PCurrency(Box::new(Currency(12))),
+            Variant::PInt(Box::new(Int(-1))),
+            Variant::PVariant(Box::new(Variant::PDouble(Box::new(0.5)))),
+        ];
+        for v in values {
+            let owned = OwnedVariant::new_in(v.clone(), alloc.clone());
+            let copy = owned.clone();
+            drop(owned);
+            assert_eq!(copy.to_variant().unwrap(), v);
+        }
+        assert_eq!(alloc.clears.get(), 0);
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let alloc = CountingAllocator::default();
+        let owned = OwnedVariant::new_in(Variant::Short(-2), alloc.clone());
+        let raw = owned.into_raw();
+        let owned = unsafe { OwnedVariant::from_raw_in(raw, alloc.clone()) };
+        assert_eq!(owned.vartype(), VT_I2 as VARTYPE);
+        assert_eq!(owned.to_variant().unwrap(), Variant::Short(-2));
+    }
+
+    #[test]
+    fn into_variant() {
+        let alloc = CountingAllocator::default();
+        let owned = OwnedVariant::new_in(Variant::BString(String::from("abc")), alloc.clone());
+        assert_eq!(owned.into_variant().unwrap(), Variant::BString(String::from("abc")));
+        assert_eq!(alloc.clears.get(), 1);
+
+        //the interface pointer keeps the VARIANT's reference, so nothing is cleared
+        let raw = OwnedVariant::new_in(Variant::Unknown(ptr::null_mut()), alloc.clone()).into_raw();
+        let owned = unsafe { OwnedVariant::from_raw_in(raw, alloc.clone()) };
+        assert_eq!(owned.into_variant().unwrap(), Variant::Unknown(ptr::null_mut()));
+        assert_eq!(alloc.clears.get(), 1);
+    }
+
+    //Clears like CountingAllocator but every copy fails
+    #[derive(Clone, Default)]
+    struct FailingCopy(CountingAllocator);
+
+    impl VariantAllocator for FailingCopy {
+        unsafe fn clear(&self, pvar: *mut VARIANT) -> HRESULT {
+            self.0.clear(pvar)
+        }
+        unsafe fn copy(&self, _dest: *mut VARIANT, _src: *const VARIANT) -> HRESULT {
+            ::winapi::shared::winerror::E_OUTOFMEMORY
+        }
+    }
+
+    #[test]
+    fn failed_clone() {
+        let alloc = FailingCopy::default();
+        let owned = OwnedVariant::new_in(Variant::BString(String::from("abc")), alloc.clone());
+        match owned.try_clone() {
+            Err(ClrError::InnerCall{hr, ..}) => assert_eq!(hr, ::winapi::shared::winerror::E_OUTOFMEMORY),
+            other => panic!("expected InnerCall, got {:?}", other.map(|_| ()))
+        }
+        drop(owned);
+        assert_eq!((alloc.0).clears.get(), 1);
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy)]
+    struct Entry {
+        key: u16,
+        value: u16
+    }
+
+    unsafe impl ::record::RecordPayload for Entry {}
+
+    #[test]
+    fn record_cleared() {
+        use record::Record;
+        use record::mock::MockRecordInfo;
+        let info = MockRecordInfo::new(4);
+        let alloc = CountingAllocator::default();
+        let record = unsafe {Record::from_struct(info.as_ptr(), Entry {key: 1, value: 2})}.unwrap();
+        {
+            let owned = OwnedVariant::new_in(Variant::Record(record.clone()), alloc.clone());
+            assert_eq!(owned.to_variant().unwrap(), Variant::Record(record));
+        }
+        assert_eq!(alloc.clears.get(), 1);
+    }
+}
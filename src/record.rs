@@ -0,0 +1,435 @@
+// record.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//VT_RECORD payloads, ie .NET value types passed by value (DictionaryEntry, InterfaceMapping, etc).
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use winapi::ctypes::c_void;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_OUTOFMEMORY, E_POINTER};
+use winapi::um::oaidl::IRecordInfo;
+
+use com_ptr::ComPtr;
+use result::{ClrError, SourceLocation, Result};
+
+//Marker for #[repr(C)] structs that mirror a record's layout field for field.
+//Implementing it promises the struct has no padding the record doesn't, and that its fields
+//are valid for any bytes the record can hold (BSTR, *mut IUnknown, VARIANT, etc for reference fields).
+pub unsafe trait RecordPayload: Copy {}
+
+//One record and the IRecordInfo describing it, which the Record holds a reference to.
+//The bytes own whatever the record references (BSTRs, interfaces), and are released with
+//IRecordInfo::RecordClear on drop.
+pub struct Record {
+    info: ComPtr<IRecordInfo>,
+    data: RecordData
+}
+
+//Automation records are at most 8 byte aligned (doubles, CY, DECIMAL, VARIANT),
+//so the bytes are kept in u64s for RecordCopy and RecordClear to work on.
+struct RecordData {
+    words: Vec<u64>,
+    len: usize
+}
+
+impl RecordData {
+    fn zeroed(len: usize) -> RecordData {
+        RecordData {words: vec![0u64; (len + 7) / 8], len: len}
+    }
+
+    fn as_bytes(&self) -> &[u8] {
+        unsafe {
+            slice::from_raw_parts(self.words.as_ptr() as *const u8, self.len)
+        }
+    }
+
+    fn as_mut_ptr(&mut self) -> *mut c_void {
+        self.words.as_mut_ptr() as *mut c_void
+    }
+}
+
+//Takes a reference on info, which must be a valid IRecordInfo or null, and asks it for the record size
+unsafe fn record_info(info: *mut IRecordInfo) -> Result<(ComPtr<IRecordInfo>, usize)> {
+    if info.is_null() {
+        return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::Record(line!())});
+    }
+    let mut size: ULONG = 0;
+    let hr = (*info).GetSize(&mut size);
+    SUCCEEDED!(hr, (ComPtr::from_raw_add_ref(info), size as usize), Record)
+}
+
+impl Record {
+    //info must be a valid IRecordInfo or null, the Record takes a reference of its own on it.
+    //data must be one record laid out as info describes, ownership of its fields moves into the Record
+    pub unsafe fn from_bytes(info: *mut IRecordInfo, data: Vec<u8>) -> Result<Record> {
+        let (info, size) = record_info(info)?;
+        if size != data.len() {
+            return Err(ClrError::RecordSize{expected: size, found: data.len()});
+        }
+        let mut bytes = RecordData::zeroed(size);
+        ptr::copy_nonoverlapping(data.as_ptr(), bytes.as_mut_ptr() as *mut u8, size);
+        Ok(Record {info: info, data: bytes})
+    }
+
+    pub unsafe fn from_struct<S: RecordPayload>(info: *mut IRecordInfo, value: S) -> Result<Record> {
+        let (info, size) = record_info(info)?;
+        if size != mem::size_of::<S>() {
+            return Err(ClrError::RecordSize{expected: size, found: mem::size_of::<S>()});
+        }
+        let mut data = RecordData::zeroed(size);
+        ptr::write_unaligned(data.as_mut_ptr() as *mut S, value);
+        Ok(Record {info: info, data: data})
+    }
+
+    //Deep copy of the record at pv, via IRecordInfo::RecordCopy.
+    pub unsafe fn from_raw(info: *mut IRecordInfo, pv: *const c_void) -> Result<Record> {
+        if pv.is_null() {
+            return Err(ClrError::InnerCall{hr: E_POINTER, source: SourceLocation::Record(line!())});
+        }
+        let (info, size) = record_info(info)?;
+        let mut data = RecordData::zeroed(size);
+        if size == 0 {
+            return Ok(Record {info: info, data: data});
+        }
+        let hr = (*info.as_ptr()).RecordCopy(pv as *mut c_void, data.as_mut_ptr());
+        trace!("IRecordInfo::RecordCopy({:p}) - hr = 0x{:x}", pv, hr);
+        SUCCEEDED!(hr, Record {info: info, data: data}, Record)
+    }
+
+    //Moves the record into a block allocated by IRecordInfo::RecordCreate,
+    //which the caller releases with IRecordInfo::RecordDestroy (or VariantClear/SafeArrayDestroy).
+    pub fn into_raw(self) -> Result<*mut c_void> {
+        //info is never null, every constructor checks it
+        let pv = unsafe {
+            (*self.info.as_ptr()).RecordCreate()
+        };
+        if pv.is_null() {
+            return Err(ClrError::InnerCall{hr: E_OUTOFMEMORY, source: SourceLocation::Record(line!())});
+        }
+        let (_, data) = self.into_parts();
+        unsafe {
+            ptr::copy_nonoverlapping(data.as_ptr(), pv as *mut u8, data.len());
+        }
+        Ok(pv)
+    }
+
+    //Lends the IRecordInfo, the reference stays with the Record
+    pub fn info(&self) -> *mut IRecordInfo {
+        self.info.as_ptr()
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_bytes()
+    }
+
+    pub fn as_ptr(&self) -> *const c_void {
+        self.data.words.as_ptr() as *const c_void
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.len == 0
+    }
+
+    //Copies the fields out without transferring ownership, reference fields still belong to the Record.
+    pub fn view_struct<S: RecordPayload>(&self) -> Result<S> {
+        if self.len() != mem::size_of::<S>() {
+            return Err(ClrError::RecordSize{expected: self.len(), found: mem::size_of::<S>()});
+        }
+        Ok(unsafe {
+            ptr::read_unaligned(self.as_ptr() as *const S)
+        })
+    }
+
+    //Moves the fields out, the caller takes over releasing any reference fields.
+    pub fn into_struct<S: RecordPayload>(self) -> Result<S> {
+        let value = self.view_struct()?;
+        self.into_parts();
+        Ok(value)
+    }
+
+    //Gives up the bytes without clearing them
+    pub fn into_parts(self) -> (ComPtr<IRecordInfo>, Vec<u8>) {
+        let mut this = self;
+        let data = mem::replace(&mut this.data, RecordData::zeroed(0));
+        let info = mem::replace(&mut this.info, ComPtr::null());
+        (info, data.as_bytes().to_vec())
+    }
+}
+
+impl Drop for Record {
+    fn drop(&mut self) {
+        if self.info.is_null() || self.is_empty() {
+            return;
+        }
+        unsafe {
+            (*self.info.as_ptr()).RecordClear(self.data.as_mut_ptr())
+        };
+    }
+}
+
+impl Clone for Record {
+    fn clone(&self) -> Record {
+        if self.is_empty() {
+            return Record {info: self.info.clone(), data: RecordData::zeroed(0)};
+        }
+        match unsafe { Record::from_raw(self.info(), self.as_ptr()) } {
+            Ok(record) => record,
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+impl fmt::Debug for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Record")
+            .field("info", &self.info())
+            .field("data", &self.as_bytes())
+            .finish()
+    }
+}
+
+impl PartialEq for Record {
+    fn eq(&self, other: &Record) -> bool {
+        self.info == other.info && self.as_bytes() == other.as_bytes()
+    }
+}
+
+//Records of different types aren't comparable
+impl PartialOrd for Record {
+    fn partial_cmp(&self, other: &Record) -> Option<Ordering> {
+        if self.info != other.info {
+            return None;
+        }
+        self.as_bytes().partial_cmp(other.as_bytes())
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    //Minimal IRecordInfo for a blittable record, enough for the marshaling paths.
+    //The object is only the vtable pointer, with the mock's state stored after the vtable,
+    //since calls through winapi only get a pointer to the interface itself.
+    use std::ptr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use winapi::ctypes::c_void;
+    use winapi::shared::guiddef::{GUID, REFIID};
+    use winapi::shared::minwindef::{BOOL, ULONG};
+    use winapi::shared::winerror::{E_NOTIMPL, HRESULT, S_OK};
+    use winapi::shared::wtypes::BSTR;
+    use winapi::um::oaidl::{IRecordInfo, IRecordInfoVtbl, ITypeInfo, VARIANT};
+    use winapi::um::unknwnbase::IUnknownVtbl;
+    use winapi::um::winnt::LPCWSTR;
+
+    #[repr(C)]
+    struct MockState {
+        vtbl: IRecordInfoVtbl,
+        size: ULONG,
+        clears: AtomicUsize,
+        refs: AtomicUsize
+    }
+
+    pub struct MockRecordInfo {
+        state: *mut MockState,
+        object: *mut IRecordInfo
+    }
+
+    impl MockRecordInfo {
+        pub fn new(size: ULONG) -> MockRecordInfo {
+            let state = Box::into_raw(Box::new(MockState {vtbl: VTBL, size: size, clears: AtomicUsize::new(0), refs: AtomicUsize::new(0)}));
+            let object = Box::into_raw(Box::new(state as *const IRecordInfoVtbl)) as *mut IRecordInfo;
+            MockRecordInfo {state: state, object: object}
+        }
+
+        pub fn as_ptr(&self) -> *mut IRecordInfo {
+            self.object
+        }
+
+        pub fn clears(&self) -> usize {
+            unsafe {
+                (*self.state).clears.load(Ordering::SeqCst)
+            }
+        }
+
+        //References taken on top of the one the mock keeps for itself
+        pub fn refs(&self) -> usize {
+            unsafe {
+                (*self.state).refs.load(Ordering::SeqCst)
+            }
+        }
+    }
+
+    impl Drop for MockRecordInfo {
+        fn drop(&mut self) {
+            unsafe {
+                drop(Box::from_raw(self.object as *mut *const IRecordInfoVtbl));
+                drop(Box::from_raw(self.state));
+            }
+        }
+    }
+
+    unsafe fn this<'a>(p: *mut IRecordInfo) -> &'a MockState {
+        &*(*(p as *const *const MockState))
+    }
+
+    unsafe extern "system" fn query_interface(_: *mut ::winapi::um::unknwnbase::IUnknown, _: REFIID, _: *mut *mut c_void) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn add_ref(p: *mut ::winapi::um::unknwnbase::IUnknown) -> ULONG {
+        this(p as *mut IRecordInfo).refs.fetch_add(1, Ordering::SeqCst) as ULONG + 2
+    }
+    unsafe extern "system" fn release(p: *mut ::winapi::um::unknwnbase::IUnknown) -> ULONG {
+        this(p as *mut IRecordInfo).refs.fetch_sub(1, Ordering::SeqCst) as ULONG
+    }
+    unsafe extern "system" fn record_init(p: *mut IRecordInfo, pv: *mut c_void) -> HRESULT {
+        ptr::write_bytes(pv as *mut u8, 0, this(p).size as usize);
+        S_OK
+    }
+    unsafe extern "system" fn record_clear(p: *mut IRecordInfo, _: *mut c_void) -> HRESULT {
+        this(p).clears.fetch_add(1, Ordering::SeqCst);
+        S_OK
+    }
+    unsafe extern "system" fn record_copy(p: *mut IRecordInfo, src: *mut c_void, dst: *mut c_void) -> HRESULT {
+        ptr::copy_nonoverlapping(src as *const u8, dst as *mut u8, this(p).size as usize);
+        S_OK
+    }
+    unsafe extern "system" fn get_guid(_: *mut IRecordInfo, _: *mut GUID) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn get_name(_: *mut IRecordInfo, _: *mut BSTR) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn get_size(p: *mut IRecordInfo, size: *mut ULONG) -> HRESULT {
+        *size = this(p).size;
+        S_OK
+    }
+    unsafe extern "system" fn get_type_info(_: *mut IRecordInfo, _: *mut *mut ITypeInfo) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn get_field(_: *mut IRecordInfo, _: *mut c_void, _: LPCWSTR, _: *mut VARIANT) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn get_field_no_copy(_: *mut IRecordInfo, _: *mut c_void, _: LPCWSTR, _: *mut VARIANT, _: *mut *mut c_void) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn put_field(_: *mut IRecordInfo, _: ULONG, _: *mut c_void, _: LPCWSTR, _: *mut VARIANT) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn get_field_names(_: *mut IRecordInfo, _: *mut ULONG, _: *mut BSTR) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn is_matching_type(_: *mut IRecordInfo, _: *mut IRecordInfo) -> BOOL { 0 }
+    unsafe extern "system" fn record_create(p: *mut IRecordInfo) -> *mut c_void {
+        let block = vec![0u8; this(p).size as usize].into_boxed_slice();
+        Box::into_raw(block) as *mut c_void
+    }
+    unsafe extern "system" fn record_create_copy(_: *mut IRecordInfo, _: *mut c_void, _: *mut *mut c_void) -> HRESULT { E_NOTIMPL }
+    unsafe extern "system" fn record_destroy(p: *mut IRecordInfo, pv: *mut c_void) -> HRESULT {
+        let size = this(p).size as usize;
+        drop(Box::from_raw(::std::slice::from_raw_parts_mut(pv as *mut u8, size)));
+        S_OK
+    }
+
+    const VTBL: IRecordInfoVtbl = IRecordInfoVtbl {
+        parent: IUnknownVtbl {
+            QueryInterface: query_interface,
+            AddRef: add_ref,
+            Release: release
+        },
+        RecordInit: record_init,
+        RecordClear: record_clear,
+        RecordCopy: record_copy,
+        GetGuid: get_guid,
+        GetName: get_name,
+        GetSize: get_size,
+        GetTypeInfo: get_type_info,
+        GetField: get_field,
+        GetFieldNoCopy: get_field_no_copy,
+        PutField: put_field,
+        PutFieldNoCopy: put_field,
+        GetFieldNames: get_field_names,
+        IsMatchingType: is_matching_type,
+        RecordCreate: record_create,
+        RecordCreateCopy: record_create_copy,
+        RecordDestroy: record_destroy
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::mock::MockRecordInfo;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    struct Point {
+        x: i32,
+        y: i32,
+        z: f64
+    }
+
+    unsafe impl RecordPayload for Point {}
+
+    #[test]
+    fn struct_round_trip() {
+        let info = MockRecordInfo::new(16);
+        let p = Point {x: 1, y: -2, z: 3.5};
+        let record = unsafe {Record::from_struct(info.as_ptr(), p)}.unwrap();
+        assert_eq!(record.len(), 16);
+        assert_eq!(record.view_struct::<Point>().unwrap(), p);
+        let copy = record.clone();
+        assert_eq!(copy, record);
+        assert_eq!(copy.into_struct::<Point>().unwrap(), p);
+        assert_eq!(info.clears(), 0);
+        assert_eq!(info.refs(), 1);
+        drop(record);
+        assert_eq!(info.clears(), 1);
+        assert_eq!(info.refs(), 0);
+    }
+
+    #[test]
+    fn size_mismatch() {
+        let info = MockRecordInfo::new(12);
+        match unsafe {Record::from_struct(info.as_ptr(), Point {x: 0, y: 0, z: 0.0})} {
+            Err(ClrError::RecordSize{expected, found}) => {
+                assert_eq!(expected, 12);
+                assert_eq!(found, 16);
+            },
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let info = MockRecordInfo::new(16);
+        let p = Point {x: 7, y: 8, z: -1.25};
+        let pv = unsafe {Record::from_struct(info.as_ptr(), p)}.unwrap().into_raw().unwrap();
+        let record = unsafe { Record::from_raw(info.as_ptr(), pv).unwrap() };
+        assert_eq!(record.view_struct::<Point>().unwrap(), p);
+        assert_eq!(record.as_ptr() as usize % mem::align_of::<f64>(), 0);
+        unsafe {
+            (*info.as_ptr()).RecordDestroy(pv);
+        }
+        drop(record);
+        assert_eq!(info.refs(), 0);
+    }
+
+    #[test]
+    fn null_info() {
+        match unsafe {Record::from_bytes(ptr::null_mut(), vec![0u8; 4])} {
+            Err(ClrError::InnerCall{hr, ..}) => assert_eq!(hr, E_POINTER),
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+}
@@ -22,6 +22,7 @@
 use std;
 //use failure::Error;
 use winapi::shared::winerror::HRESULT;
+use winapi::shared::wtypes::VARTYPE;
 
 #[derive(Debug, Fail)]
 pub enum CommonHresultValues {
@@ -114,6 +115,14 @@ pub enum SourceLocation {
     _PropertyInfo(u32),
     #[fail(display = "_EventInfo(line: {})", _0)]
     _EventInfo(u32),
+    #[fail(display = "SafeArray(line: {})", _0)]
+    SafeArray(u32),
+    #[fail(display = "Variant(line: {})", _0)]
+    Variant(u32),
+    #[fail(display = "Record(line: {})", _0)]
+    Record(u32),
+    #[fail(display = "IUnknown(line: {})", _0)]
+    IUnknown(u32),
 }
 
 #[derive(Debug, Fail)]
@@ -127,6 +136,69 @@ pub enum ClrError {
     Conversion{
         source: SourceLocation
     }, 
+    #[fail(display = "Unsupported vartype 0x{:x} at: {:?}", vt, source)]
+    UnsupportedVartype{
+        vt: VARTYPE, 
+        source: SourceLocation
+    }, 
+    #[fail(display = "Unsupported safearray dimension count {} at: {:?}", dims, source)]
+    SafeArrayDimensions{
+        dims: u32, 
+        source: SourceLocation
+    }, 
+    #[fail(display = "Expected vartype 0x{:x} but found 0x{:x}", expected, found)]
+    VartypeMismatch{
+        expected: VARTYPE, 
+        found: VARTYPE
+    }, 
+    #[fail(display = "Record is {} bytes but {} bytes were given", expected, found)]
+    RecordSize{
+        expected: usize, 
+        found: usize
+    }, 
+    #[fail(display = "Safearray bounds hold {} elements but {} were given", expected, found)]
+    SafeArrayLength{
+        expected: usize, 
+        found: usize
+    }, 
+    #[fail(display = "Safearray dimension {}..={} is out of range at: {:?}", lower, upper, source)]
+    SafeArrayBound{
+        lower: i64, 
+        upper: i64, 
+        source: SourceLocation
+    }, 
+    #[fail(display = "Records in a safearray need one IRecordInfo they all match, at: {:?}", source)]
+    RecordInfo{
+        source: SourceLocation
+    }, 
+    #[fail(display = "Safearray element {:?} could not be read or written, HRESULT: 0x{:x}", index, hr)]
+    SafeArrayElement{
+        hr: HRESULT, 
+        index: Vec<i32>
+    }, 
+    #[fail(display = "Unpaired UTF-16 surrogate 0x{:x} at code unit {}", unit, index)]
+    InvalidUtf16{
+        unit: u16, 
+        index: usize
+    }, 
+    #[fail(display = "Vartype 0x{:x} cannot be coerced to 0x{:x}", from, to)]
+    Coercion{
+        from: VARTYPE, 
+        to: VARTYPE
+    }, 
+    #[fail(display = "Value of vartype 0x{:x} is out of range for 0x{:x}", from, to)]
+    Overflow{
+        from: VARTYPE, 
+        to: VARTYPE
+    }, 
+    #[fail(display = "{} is outside the range of an OLE Automation date", date)]
+    DateRange{
+        date: String
+    }, 
+    #[fail(display = "Interface {} is not supported", iid)]
+    NoInterface{
+        iid: String
+    }, 
 }
 
 pub type Result<T> = std::result::Result<T, ClrError>;
\ No newline at end of file
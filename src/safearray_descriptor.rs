@@ -0,0 +1,144 @@
+// safearray_descriptor.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//SAFEARRAY descriptors built in Rust memory, and the bounds checks done before asking oleaut32 for one.
+//Layout follows oleaut32: the VARTYPE sits in the DWORD before the descriptor when
+//FADF_HAVEVARTYPE is set, and rgsabound is stored rightmost dimension first.
+
+use std::convert::TryFrom;
+use std::mem;
+
+use winapi::ctypes::{c_long, c_void};
+use winapi::shared::minwindef::{ULONG, USHORT};
+use winapi::shared::wtypes::{BSTR, VARTYPE, VT_BSTR};
+use winapi::um::oaidl::{FADF_BSTR, FADF_FIXEDSIZE, FADF_HAVEVARTYPE, FADF_STATIC, SAFEARRAY, SAFEARRAYBOUND};
+
+use new_safearray::PodElement;
+use result::{ClrError, Result, SourceLocation};
+
+//Words before the descriptor, enough to keep it 8 byte aligned with the VARTYPE right in front of it
+const HEADER_WORDS: usize = 1;
+
+//rgsabound is declared [SAFEARRAYBOUND; 1] but really runs cDims long, 
+//so address it from the descriptor pointer rather than through the field. 
+unsafe fn rgsabound(psa: *mut SAFEARRAY) -> *mut SAFEARRAYBOUND {
+    let offset = mem::size_of::<SAFEARRAY>() - mem::size_of::<SAFEARRAYBOUND>();
+    (psa as *mut u8).offset(offset as isize) as *mut SAFEARRAYBOUND
+}
+
+//Inclusive upper bound of a dimension, None when it doesn't fit a c_long (eg. an empty one at c_long::min_value())
+pub fn upper_bound(bound: &SAFEARRAYBOUND) -> Option<c_long> {
+    c_long::try_from(bound.lLbound as i64 + bound.cElements as i64 - 1).ok()
+}
+
+//Bounds have to name at least one dimension, with every index a c_long, and hold exactly len elements
+pub fn check_bounds(bounds: &[SAFEARRAYBOUND], len: usize) -> Result<()> {
+    if bounds.is_empty() {
+        return Err(ClrError::SafeArrayDimensions{dims: 0, source: SourceLocation::SafeArray(line!())});
+    }
+    if let Some(b) = bounds.iter().find(|b| b.cElements > 0 && upper_bound(b).is_none()) {
+        return Err(ClrError::SafeArrayBound{lower: b.lLbound as i64, upper: b.lLbound as i64 + b.cElements as i64 - 1, source: SourceLocation::SafeArray(line!())});
+    }
+    let total = bounds.iter().fold(Some(1usize), |acc, b| acc.and_then(|n| n.checked_mul(b.cElements as usize)));
+    match total {
+        Some(total) if total == len => Ok(()), 
+        _ => Err(ClrError::SafeArrayLength{expected: total.unwrap_or(usize::max_value()), found: len})
+    }
+}
+
+//One dimension of len elements from lower_bound. SafeArrayLength if len doesn't fit in cElements, 
+//SafeArrayBound if the last index doesn't fit in a c_long.
+pub fn single_bound(len: usize, lower_bound: c_long) -> Result<Vec<SAFEARRAYBOUND>> {
+    let c_elements = match ULONG::try_from(len) {
+        Ok(c_elements) => c_elements, 
+        Err(_) => return Err(ClrError::SafeArrayLength{expected: ULONG::max_value() as usize, found: len})
+    };
+    let bounds = vec![SAFEARRAYBOUND {cElements: c_elements, lLbound: lower_bound}];
+    check_bounds(&bounds, len)?;
+    Ok(bounds)
+}
+
+//A SAFEARRAY that borrows its data from a Vec owned on the Rust side.
+//Useful for tests and benchmarks of the read paths, it is marked FADF_STATIC | FADF_FIXEDSIZE
+//and must never be handed to SafeArrayDestroy or the owning conversions.
+pub struct StandInSafeArray<E: Copy> {
+    buffer: Vec<u64>,
+    data: Vec<E>
+}
+
+impl<E: PodElement> StandInSafeArray<E> {
+    pub fn new(data: Vec<E>) -> StandInSafeArray<E> {
+        let c_elements = data.len() as u32;
+        StandInSafeArray::with_bounds(data, &[SAFEARRAYBOUND {cElements: c_elements, lLbound: 0}])
+    }
+
+    //bounds are in SafeArrayCreate order, leftmost dimension first
+    pub fn with_bounds(data: Vec<E>, bounds: &[SAFEARRAYBOUND]) -> StandInSafeArray<E> {
+        StandInSafeArray::build(data, E::vartype() as VARTYPE, 0, bounds)
+    }
+}
+
+impl StandInSafeArray<BSTR> {
+    //The strings stay owned by the caller, same as the Vec
+    pub fn bstrs(data: Vec<BSTR>) -> StandInSafeArray<BSTR> {
+        let c_elements = data.len() as u32;
+        StandInSafeArray::build(data, VT_BSTR as VARTYPE, FADF_BSTR, &[SAFEARRAYBOUND {cElements: c_elements, lLbound: 0}])
+    }
+}
+
+impl<E: Copy> StandInSafeArray<E> {
+    fn build(mut data: Vec<E>, vt: VARTYPE, features: u32, bounds: &[SAFEARRAYBOUND]) -> StandInSafeArray<E> {
+        assert!(!bounds.is_empty());
+        let total: usize = bounds.iter().map(|b| b.cElements as usize).product();
+        assert_eq!(total, data.len());
+
+        let desc_size = mem::size_of::<SAFEARRAY>() + (bounds.len() - 1) * mem::size_of::<SAFEARRAYBOUND>();
+        let words = HEADER_WORDS + (desc_size + 7) / 8;
+        let mut buffer = vec![0u64; words];
+        unsafe {
+            let psa = buffer.as_mut_ptr().offset(HEADER_WORDS as isize) as *mut SAFEARRAY;
+            *(psa as *mut u32).offset(-1) = vt as u32;
+            (*psa).cDims = bounds.len() as USHORT;
+            (*psa).fFeatures = (FADF_STATIC | FADF_FIXEDSIZE | FADF_HAVEVARTYPE | features) as USHORT;
+            (*psa).cbElements = mem::size_of::<E>() as u32;
+            (*psa).cLocks = 0;
+            (*psa).pvData = data.as_mut_ptr() as *mut c_void;
+            let rgsabound = rgsabound(psa);
+            for (ix, bound) in bounds.iter().rev().enumerate() {
+                *rgsabound.offset(ix as isize) = *bound;
+            }
+        }
+        StandInSafeArray {
+            buffer: buffer,
+            data: data
+        }
+    }
+
+    pub fn as_ptr(&mut self) -> *mut SAFEARRAY {
+        unsafe {
+            self.buffer.as_mut_ptr().offset(HEADER_WORDS as isize) as *mut SAFEARRAY
+        }
+    }
+
+    pub fn data(&self) -> &[E] {
+        &self.data
+    }
+}
@@ -0,0 +1,246 @@
+// safearray_iter.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+
+//Element access on RSafeArray without matching on the arm first.
+
+use std::iter::FromIterator;
+
+use winapi::um::oaidl::IDispatch;
+use winapi::um::unknwnbase::IUnknown;
+
+use rust_decimal::Decimal;
+
+use bstring::BString;
+use com_ptr::ComPtr;
+use new_safearray::RSafeArray;
+use new_variant::{Currency, Date, Int, UInt, Variant};
+use record::Record;
+use result::Result;
+use typed_safearray::SafeArrayElement;
+
+macro_rules! ELEMENT_REFS {
+    (values {$($enum_name:ident => $arm:ident($ty:ty),)*} ptrs {$($ptr_enum_name:ident => $ptr_arm:ident($ptr_ty:ty),)*}) => {
+        //Borrowed element of an RSafeArray, named after the Variant arm it turns into. 
+        //An interface pointer turns into a Variant holding a reference of its own, as one read from a VARIANT does.
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        pub enum ElementRef<'a> {
+            $($arm(&'a $ty),)*
+            $($ptr_arm(&'a ComPtr<$ptr_ty>),)*
+            Variant(&'a Variant),
+        }
+
+        impl<'a> ElementRef<'a> {
+            pub fn to_variant(&self) -> Variant {
+                match *self {
+                    $(ElementRef::$arm(elem) => Variant::$arm(elem.clone()),)*
+                    $(ElementRef::$ptr_arm(p) => Variant::$ptr_arm(p.clone().into_raw()),)*
+                    ElementRef::Variant(v) => v.clone(),
+                }
+            }
+        }
+
+        impl<T> RSafeArray<T> {
+            //index is into SAFEARRAY memory order, see RSafeArrayNd for multi-dimensional indices
+            pub fn get(&self, index: usize) -> Option<ElementRef<'_>> {
+                match *self {
+                    $(RSafeArray::$enum_name(ref vc) => vc.get(index).map(ElementRef::$arm),)*
+                    $(RSafeArray::$ptr_enum_name(ref vc, _) => vc.get(index).map(ElementRef::$ptr_arm),)*
+                    RSafeArray::Variants(ref vc) => vc.get(index).map(ElementRef::Variant),
+                }
+            }
+
+            //Each element as the Variant arm of the same vartype, a VT_VARIANT array gives its elements back as is. 
+            //Interface pointers hand the array's reference on to the Variant.
+            pub fn into_variants(self) -> Vec<Variant> {
+                match self {
+                    $(RSafeArray::$enum_name(vc) => vc.into_iter().map(Variant::$arm).collect(),)*
+                    $(RSafeArray::$ptr_enum_name(vc, _) => vc.into_iter().map(|p| Variant::$ptr_arm(p.into_raw())).collect(),)*
+                    RSafeArray::Variants(vc) => vc,
+                }
+            }
+        }
+    };
+}
+
+ELEMENT_REFS!{
+    values {
+        Shorts => Short(i16),
+        Longs => Long(i32),
+        Floats => Float(f32),
+        Doubles => Double(f64),
+        Currencies => Currency(Currency),
+        Dates => Date(Date),
+        BStrings => BString(String),
+        Bools => Bool(bool),
+        Decimals => Decimal(Decimal),
+        Records => Record(Record),
+        Chars => Char(i8),
+        UChars => Byte(u8),
+        UShorts => UShort(u16),
+        ULongs => ULong(u32),
+        Ints => Int(Int),
+        UInts => UInt(UInt),
+        WStrings => WString(BString),
+    }
+    ptrs {
+        Dispatchs => Dispatch(IDispatch),
+        Unknowns => Unknown(IUnknown),
+    }
+}
+
+pub struct Iter<'a, T: 'a> {
+    rsa: &'a RSafeArray<T>, 
+    front: usize, 
+    back: usize
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = ElementRef<'a>;
+    fn next(&mut self) -> Option<ElementRef<'a>> {
+        if self.front == self.back {
+            return None;
+        }
+        self.front += 1;
+        self.rsa.get(self.front - 1)
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.back - self.front;
+        (len, Some(len))
+    }
+}
+
+impl<'a, T> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<ElementRef<'a>> {
+        if self.front == self.back {
+            return None;
+        }
+        self.back -= 1;
+        self.rsa.get(self.back)
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+impl<'a, T> IntoIterator for &'a RSafeArray<T> {
+    type Item = ElementRef<'a>;
+    type IntoIter = Iter<'a, T>;
+    fn into_iter(self) -> Iter<'a, T> {
+        self.iter()
+    }
+}
+
+impl<T> RSafeArray<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            rsa: self, 
+            front: 0, 
+            back: self.len()
+        }
+    }
+
+    //Switches element type, eg. rsa.map(|l: i32| l as f64) turns Longs into Doubles. 
+    //VartypeMismatch if the array doesn't hold A.
+    pub fn map<A, B, F>(self, f: F) -> Result<RSafeArray<T>> 
+        where A: SafeArrayElement, 
+              B: SafeArrayElement, 
+              F: FnMut(A) -> B
+    {
+        let elems = A::from_rsafearray(self)?;
+        Ok(B::into_rsafearray(elems.into_iter().map(f).collect()))
+    }
+
+    //Like map, stopping at the first error, eg. rsa.try_map(|v: Variant| v.try_into_i32())
+    pub fn try_map<A, B, F>(self, f: F) -> Result<RSafeArray<T>> 
+        where A: SafeArrayElement, 
+              B: SafeArrayElement, 
+              F: FnMut(A) -> Result<B>
+    {
+        let elems = A::from_rsafearray(self)?;
+        let mapped = elems.into_iter().map(f).collect::<Result<Vec<B>>>()?;
+        Ok(B::into_rsafearray(mapped))
+    }
+}
+
+impl<T, E: SafeArrayElement> FromIterator<E> for RSafeArray<T> {
+    fn from_iter<I: IntoIterator<Item=E>>(iter: I) -> RSafeArray<T> {
+        E::into_rsafearray(iter.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use result::ClrError;
+
+    #[test]
+    fn access() {
+        let rsa: RSafeArray = RSafeArray::Longs(vec![1, 2, 3]);
+        assert!(!rsa.is_empty());
+        assert!(RSafeArray::<i32>::Bools(Vec::new()).is_empty());
+        assert_eq!(rsa.get(1), Some(ElementRef::Long(&2)));
+        assert_eq!(rsa.get(3), None);
+        let rev: Vec<Variant> = rsa.iter().rev().map(|e| e.to_variant()).collect();
+        assert_eq!(rev, vec![Variant::Long(3), Variant::Long(2), Variant::Long(1)]);
+        assert_eq!(rsa.iter().len(), 3);
+
+        let rsa: RSafeArray = RSafeArray::Variants(vec![Variant::Short(1), Variant::Empty(())]);
+        let mut count = 0;
+        for elem in &rsa {
+            if let ElementRef::Variant(_) = elem {
+                count += 1;
+            }
+        }
+        assert_eq!(count, 2);
+        assert_eq!(rsa.get(0).map(|e| e.to_variant()), Some(Variant::Short(1)));
+    }
+
+    #[test]
+    fn into_variants() {
+        let rsa: RSafeArray = RSafeArray::UChars(vec![7]);
+        assert_eq!(rsa.into_variants(), vec![Variant::Byte(7)]);
+        let rsa: RSafeArray = RSafeArray::Dispatchs(vec![ComPtr::null()], None);
+        assert_eq!(rsa.into_variants(), vec![Variant::Dispatch(ptr::null_mut())]);
+        let rsa: RSafeArray = RSafeArray::Variants(vec![Variant::Bool(true)]);
+        assert_eq!(rsa.into_variants(), vec![Variant::Bool(true)]);
+    }
+
+    #[test]
+    fn map_and_collect() {
+        let rsa: RSafeArray = (1..4).collect();
+        assert_eq!(rsa, RSafeArray::Longs(vec![1, 2, 3]));
+        let rsa: RSafeArray = vec!["a", "b"].into_iter().map(String::from).collect();
+        assert_eq!(rsa, RSafeArray::BStrings(vec![String::from("a"), String::from("b")]));
+
+        let doubled = RSafeArray::<i32>::Longs(vec![1, 2]).map(|l: i32| f64::from(l) * 1.5).unwrap();
+        assert_eq!(doubled, RSafeArray::Doubles(vec![1.5, 3.0]));
+        match RSafeArray::<i32>::Shorts(vec![1]).map(|l: i32| l) {
+            Err(ClrError::VartypeMismatch{..}) => {}, 
+            other => panic!("expected a mismatch, got {:?}", other)
+        }
+
+        let rsa: RSafeArray = RSafeArray::Variants(vec![Variant::Short(4), Variant::BString(String::from("5"))]);
+        assert_eq!(rsa.clone().try_map(|v: Variant| v.try_into_i32()).unwrap(), RSafeArray::Longs(vec![4, 5]));
+        let rsa: RSafeArray = RSafeArray::Variants(vec![Variant::BString(String::from("x"))]);
+        assert!(rsa.try_map(|v: Variant| v.try_into_i32()).is_err());
+    }
+}
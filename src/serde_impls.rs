@@ -0,0 +1,425 @@
+// serde_impls.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+//serde support for Variant and RSafeArray, for capturing COM traffic and replaying it.
+//Variants are adjacently tagged with their vartype, eg. {"vt": "VT_I4", "value": 5} or
+//{"vt": "VT_BYREF|VT_CY", "value": "1.25"}. Currency and Decimal values are exact decimal strings
+//and Dates their DATE double. A BSTR is a string, or an array of UTF-16 code units for a BString.
+//Interface and void pointers are written as null or the token "opaque", and only null ones can be read back.
+//Records carry an IRecordInfo and refuse to serialize.
+
+use std::ptr;
+
+use rust_decimal::Decimal;
+use serde::de::Error as DeError;
+use serde::ser::Error as SerError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+
+use winapi::um::oaidl::SAFEARRAYBOUND;
+
+use bstring::BString;
+use com_ptr::ComPtr;
+use new_safearray::{RSafeArray, RSafeArrayNd};
+use new_variant::{Currency, Date, Int, SCode, UInt, Variant};
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Currency, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
+
+macro_rules! NEWTYPE_SERDE {
+    ($($name:ident($inner:ty)),*) => {
+        $(
+            impl Serialize for $name {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    self.0.serialize(serializer)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $name {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+                    <$inner>::deserialize(deserializer).map($name)
+                }
+            }
+        )*
+    };
+}
+
+NEWTYPE_SERDE!{Date(f64), SCode(i32), Int(i32), UInt(u32)}
+
+impl Serialize for BString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_slice().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BString, D::Error> {
+        Vec::<u16>::deserialize(deserializer).map(BString::from_vec)
+    }
+}
+
+//A pointer that can be logged but not replayed
+struct Opaque;
+
+impl Opaque {
+    fn new<T>(p: *mut T) -> Option<Opaque> {
+        if p.is_null() {None} else {Some(Opaque)}
+    }
+}
+
+impl Serialize for Opaque {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("opaque")
+    }
+}
+
+impl<'de> Deserialize<'de> for Opaque {
+    fn deserialize<D: Deserializer<'de>>(_deserializer: D) -> Result<Opaque, D::Error> {
+        Err(D::Error::custom("pointers can't be deserialized, only null"))
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StrRepr {
+    Str(String),
+    Wide(BString)
+}
+
+impl StrRepr {
+    fn into_variant(self, by_ref: bool) -> Variant {
+        match (self, by_ref) {
+            (StrRepr::Str(s), false) => Variant::BString(s),
+            (StrRepr::Str(s), true) => Variant::PBString(Box::new(s)),
+            (StrRepr::Wide(s), false) => Variant::WString(s),
+            (StrRepr::Wide(s), true) => Variant::PWString(Box::new(s)),
+        }
+    }
+}
+
+macro_rules! VARIANT_REPR {
+    (values {$($val:ident($vty:ty) = $vname:tt,)*} refs {$($rf:ident($rty:ty) = $rname:tt,)*}) => {
+        #[derive(Serialize, Deserialize)]
+        #[serde(tag = "vt", content = "value")]
+        enum VariantRepr {
+            $(#[serde(rename = $vname)] $val($vty),)*
+            $(#[serde(rename = $rname)] $rf($rty),)*
+            #[serde(rename = "VT_EMPTY")] Empty,
+            #[serde(rename = "VT_NULL")] Null,
+            #[serde(rename = "VT_BSTR")] Str(StrRepr),
+            #[serde(rename = "VT_BYREF|VT_BSTR")] PStr(StrRepr),
+            #[serde(rename = "VT_UNKNOWN")] Unknown(Option<Opaque>),
+            #[serde(rename = "VT_DISPATCH")] Dispatch(Option<Opaque>),
+            #[serde(rename = "VT_BYREF|VT_UNKNOWN")] PUnknown(Option<Opaque>),
+            #[serde(rename = "VT_BYREF|VT_DISPATCH")] PDispatch(Option<Opaque>),
+            #[serde(rename = "VT_BYREF")] ByRef(Option<Opaque>),
+            #[serde(rename = "VT_ARRAY")] Array(RSafeArrayNd),
+            #[serde(rename = "VT_BYREF|VT_ARRAY")] PArray(RSafeArrayNd),
+            #[serde(rename = "VT_BYREF|VT_VARIANT")] PVariant(Box<Variant>),
+        }
+
+        impl VariantRepr {
+            fn from_variant(v: &Variant) -> Result<VariantRepr, &'static str> {
+                let repr = match *v {
+                    $(Variant::$val(ref x) => VariantRepr::$val(x.clone()),)*
+                    $(Variant::$rf(ref x) => VariantRepr::$rf((**x).clone()),)*
+                    Variant::Empty(_) => VariantRepr::Empty,
+                    Variant::Null(_) => VariantRepr::Null,
+                    Variant::BString(ref s) => VariantRepr::Str(StrRepr::Str(s.clone())),
+                    Variant::WString(ref s) => VariantRepr::Str(StrRepr::Wide(s.clone())),
+                    Variant::PBString(ref s) => VariantRepr::PStr(StrRepr::Str((**s).clone())),
+                    Variant::PWString(ref s) => VariantRepr::PStr(StrRepr::Wide((**s).clone())),
+                    Variant::Unknown(p) => VariantRepr::Unknown(Opaque::new(p)),
+                    Variant::Dispatch(p) => VariantRepr::Dispatch(Opaque::new(p)),
+                    Variant::PUnknown(ref p) => VariantRepr::PUnknown(Opaque::new(**p)),
+                    Variant::PDispatch(ref p) => VariantRepr::PDispatch(Opaque::new(**p)),
+                    Variant::ByRef(p) => VariantRepr::ByRef(Opaque::new(p)),
+                    Variant::Array(ref a) => VariantRepr::Array(a.clone()),
+                    Variant::PArray(ref a) => VariantRepr::PArray((**a).clone()),
+                    Variant::PVariant(ref v) => VariantRepr::PVariant(v.clone()),
+                    Variant::Record(_) => return Err("records can't be serialized"),
+                };
+                Ok(repr)
+            }
+
+            //Only null pointers make it through deserializing, so every pointer here is null
+            fn into_variant(self) -> Variant {
+                match self {
+                    $(VariantRepr::$val(x) => Variant::$val(x),)*
+                    $(VariantRepr::$rf(x) => Variant::$rf(Box::new(x)),)*
+                    VariantRepr::Empty => Variant::Empty(()),
+                    VariantRepr::Null => Variant::Null(()),
+                    VariantRepr::Str(s) => s.into_variant(false),
+                    VariantRepr::PStr(s) => s.into_variant(true),
+                    VariantRepr::Unknown(_) => Variant::Unknown(ptr::null_mut()),
+                    VariantRepr::Dispatch(_) => Variant::Dispatch(ptr::null_mut()),
+                    VariantRepr::PUnknown(_) => Variant::PUnknown(Box::new(ptr::null_mut())),
+                    VariantRepr::PDispatch(_) => Variant::PDispatch(Box::new(ptr::null_mut())),
+                    VariantRepr::ByRef(_) => Variant::ByRef(ptr::null_mut()),
+                    VariantRepr::Array(a) => Variant::Array(a),
+                    VariantRepr::PArray(a) => Variant::PArray(Box::new(a)),
+                    VariantRepr::PVariant(v) => Variant::PVariant(v),
+                }
+            }
+        }
+    };
+}
+
+VARIANT_REPR!{
+    values {
+        LongLong(i64) = "VT_I8",
+        Long(i32) = "VT_I4",
+        Byte(u8) = "VT_UI1",
+        Short(i16) = "VT_I2",
+        Float(f32) = "VT_R4",
+        Double(f64) = "VT_R8",
+        Bool(bool) = "VT_BOOL",
+        ErrorCode(SCode) = "VT_ERROR",
+        Currency(Currency) = "VT_CY",
+        Date(Date) = "VT_DATE",
+        Char(i8) = "VT_I1",
+        UShort(u16) = "VT_UI2",
+        ULong(u32) = "VT_UI4",
+        ULongLong(u64) = "VT_UI8",
+        Int(Int) = "VT_INT",
+        UInt(UInt) = "VT_UINT",
+        Decimal(Decimal) = "VT_DECIMAL",
+    }
+    refs {
+        PByte(u8) = "VT_BYREF|VT_UI1",
+        PShort(i16) = "VT_BYREF|VT_I2",
+        PLong(i32) = "VT_BYREF|VT_I4",
+        PLongLong(i64) = "VT_BYREF|VT_I8",
+        PFloat(f32) = "VT_BYREF|VT_R4",
+        PDouble(f64) = "VT_BYREF|VT_R8",
+        PBool(bool) = "VT_BYREF|VT_BOOL",
+        PErrorCode(SCode) = "VT_BYREF|VT_ERROR",
+        PCurrency(Currency) = "VT_BYREF|VT_CY",
+        PDate(Date) = "VT_BYREF|VT_DATE",
+        PDecimal(Decimal) = "VT_BYREF|VT_DECIMAL",
+        PChar(i8) = "VT_BYREF|VT_I1",
+        PUShort(u16) = "VT_BYREF|VT_UI2",
+        PULong(u32) = "VT_BYREF|VT_UI4",
+        PULongLong(u64) = "VT_BYREF|VT_UI8",
+        PInt(Int) = "VT_BYREF|VT_INT",
+        PUInt(UInt) = "VT_BYREF|VT_UINT",
+    }
+}
+
+impl Serialize for Variant {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        VariantRepr::from_variant(self).map_err(S::Error::custom)?.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Variant {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Variant, D::Error> {
+        VariantRepr::deserialize(deserializer).map(VariantRepr::into_variant)
+    }
+}
+
+//An empty array of strings reads back as BStrings
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum StrsRepr {
+    Str(Vec<String>),
+    Wide(Vec<BString>)
+}
+
+macro_rules! ARRAY_REPR {
+    ($({$name:ident($ty:ty) = $vt:tt})*) => {
+        #[derive(Serialize, Deserialize)]
+        #[serde(tag = "vt", content = "value")]
+        enum ArrayRepr {
+            $(#[serde(rename = $vt)] $name(Vec<$ty>),)*
+            #[serde(rename = "VT_BSTR")] Strs(StrsRepr),
+            #[serde(rename = "VT_DISPATCH")] Dispatchs(Vec<Option<Opaque>>),
+            #[serde(rename = "VT_UNKNOWN")] Unknowns(Vec<Option<Opaque>>),
+        }
+
+        impl ArrayRepr {
+            fn from_array<T>(rsa: &RSafeArray<T>) -> Result<ArrayRepr, &'static str> {
+                let repr = match *rsa {
+                    $(RSafeArray::$name(ref v) => ArrayRepr::$name(v.clone()),)*
+                    RSafeArray::BStrings(ref v) => ArrayRepr::Strs(StrsRepr::Str(v.clone())),
+                    RSafeArray::WStrings(ref v) => ArrayRepr::Strs(StrsRepr::Wide(v.clone())),
+                    RSafeArray::Dispatchs(ref v, _) => ArrayRepr::Dispatchs(v.iter().map(|p| Opaque::new(p.as_ptr())).collect()),
+                    RSafeArray::Unknowns(ref v, _) => ArrayRepr::Unknowns(v.iter().map(|p| Opaque::new(p.as_ptr())).collect()),
+                    RSafeArray::Records(_) => return Err("records can't be serialized"),
+                };
+                Ok(repr)
+            }
+
+            fn into_array<T>(self) -> RSafeArray<T> {
+                match self {
+                    $(ArrayRepr::$name(v) => RSafeArray::$name(v),)*
+                    ArrayRepr::Strs(StrsRepr::Str(v)) => RSafeArray::BStrings(v),
+                    ArrayRepr::Strs(StrsRepr::Wide(v)) => RSafeArray::WStrings(v),
+                    ArrayRepr::Dispatchs(v) => RSafeArray::Dispatchs(vec![ComPtr::null(); v.len()], None),
+                    ArrayRepr::Unknowns(v) => RSafeArray::Unknowns(vec![ComPtr::null(); v.len()], None),
+                }
+            }
+        }
+    };
+}
+
+ARRAY_REPR!{
+    {Shorts(i16) = "VT_I2"}
+    {Longs(i32) = "VT_I4"}
+    {Floats(f32) = "VT_R4"}
+    {Doubles(f64) = "VT_R8"}
+    {Currencies(Currency) = "VT_CY"}
+    {Dates(Date) = "VT_DATE"}
+    {Bools(bool) = "VT_BOOL"}
+    {Variants(Variant) = "VT_VARIANT"}
+    {Decimals(Decimal) = "VT_DECIMAL"}
+    {Chars(i8) = "VT_I1"}
+    {UChars(u8) = "VT_UI1"}
+    {UShorts(u16) = "VT_UI2"}
+    {ULongs(u32) = "VT_UI4"}
+    {Ints(Int) = "VT_INT"}
+    {UInts(UInt) = "VT_UINT"}
+}
+
+impl<T> Serialize for RSafeArray<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ArrayRepr::from_array(self).map_err(S::Error::custom)?.serialize(serializer)
+    }
+}
+
+impl<'de, T> Deserialize<'de> for RSafeArray<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RSafeArray<T>, D::Error> {
+        ArrayRepr::deserialize(deserializer).map(ArrayRepr::into_array)
+    }
+}
+
+//Bounds in SafeArrayCreate order, elements in SAFEARRAY memory order, same as RSafeArrayNd keeps them
+#[derive(Serialize, Deserialize)]
+struct BoundRepr {
+    lower: i32,
+    count: u32
+}
+
+#[derive(Deserialize)]
+#[serde(bound = "")]
+struct ArrayNdRepr<T> {
+    bounds: Vec<BoundRepr>,
+    data: RSafeArray<T>
+}
+
+impl<T> Serialize for RSafeArrayNd<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let bounds: Vec<BoundRepr> = self.bounds().iter().map(|b| BoundRepr {lower: b.lLbound, count: b.cElements}).collect();
+        let data = ArrayRepr::from_array(self.column_major()).map_err(S::Error::custom)?;
+        (ArrayNdReprRef {bounds: bounds, data: data}).serialize(serializer)
+    }
+}
+
+//Serializing side of ArrayNdRepr, so the elements don't have to be cloned into an RSafeArray first
+#[derive(Serialize)]
+struct ArrayNdReprRef {
+    bounds: Vec<BoundRepr>,
+    data: ArrayRepr
+}
+
+impl<'de, T> Deserialize<'de> for RSafeArrayNd<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<RSafeArrayNd<T>, D::Error> {
+        let repr = ArrayNdRepr::<T>::deserialize(deserializer)?;
+        let bounds: Vec<SAFEARRAYBOUND> = repr.bounds.iter().map(|b| SAFEARRAYBOUND {cElements: b.count, lLbound: b.lower}).collect();
+        RSafeArrayNd::new(bounds, repr.data).map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json;
+    use winapi::um::unknwnbase::IUnknown;
+
+    fn round_trip(v: Variant) {
+        let json = serde_json::to_string(&v).unwrap();
+        let back: Variant = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, v, "{}", json);
+    }
+
+    #[test]
+    fn variants() {
+        round_trip(Variant::Long(-5));
+        round_trip(Variant::PULongLong(Box::new(u64::max_value())));
+        round_trip(Variant::Currency("-12.3456".parse().unwrap()));
+        round_trip(Variant::PDate(Box::new(Date(43101.25))));
+        round_trip(Variant::Decimal(Decimal::new(-123456789, 5)));
+        round_trip(Variant::BString(String::from("hello")));
+        round_trip(Variant::PWString(Box::new(BString::from_vec(vec![0xD800u16, 0x41]))));
+        round_trip(Variant::PVariant(Box::new(Variant::Bool(true))));
+        round_trip(Variant::Empty(()));
+        round_trip(Variant::Unknown(ptr::null_mut()));
+
+        assert_eq!(serde_json::to_string(&Variant::Long(5)).unwrap(), r#"{"vt":"VT_I4","value":5}"#);
+        assert_eq!(serde_json::to_string(&Variant::PCurrency(Box::new(Currency(12500)))).unwrap(), r#"{"vt":"VT_BYREF|VT_CY","value":"1.25"}"#);
+        assert_eq!(serde_json::to_string(&Variant::Null(())).unwrap(), r#"{"vt":"VT_NULL"}"#);
+    }
+
+    #[test]
+    fn arrays() {
+        let bounds = vec![SAFEARRAYBOUND {cElements: 2, lLbound: 1}, SAFEARRAYBOUND {cElements: 2, lLbound: 0}];
+        let nd = RSafeArrayNd::new(bounds, RSafeArray::Variants(vec![
+            Variant::Short(1),
+            Variant::Array(RSafeArrayNd::from(RSafeArray::Decimals(vec![Decimal::new(15, 1)]))),
+            Variant::BString(String::from("x")),
+            Variant::Dispatch(ptr::null_mut()),
+        ])).unwrap();
+        round_trip(Variant::Array(nd.clone()));
+
+        let json = serde_json::to_string(&RSafeArray::<i32>::Longs(vec![1, 2])).unwrap();
+        assert_eq!(json, r#"{"vt":"VT_I4","value":[1,2]}"#);
+        let json = serde_json::to_string(&RSafeArrayNd::from(RSafeArray::<i32>::BStrings(vec![String::from("a")]))).unwrap();
+        assert_eq!(json, r#"{"bounds":[{"lower":0,"count":1}],"data":{"vt":"VT_BSTR","value":["a"]}}"#);
+
+        let bad = r#"{"bounds":[{"lower":0,"count":3}],"data":{"vt":"VT_I4","value":[1,2]}}"#;
+        assert!(serde_json::from_str::<RSafeArrayNd>(bad).is_err());
+        let bad = r#"{"bounds":[],"data":{"vt":"VT_I4","value":[]}}"#;
+        assert!(serde_json::from_str::<RSafeArrayNd>(bad).is_err());
+    }
+
+    #[test]
+    fn pointers() {
+        let mut unk = 0u64;
+        let p = &mut unk as *mut u64 as *mut IUnknown;
+        let json = serde_json::to_string(&Variant::Unknown(p)).unwrap();
+        assert_eq!(json, r#"{"vt":"VT_UNKNOWN","value":"opaque"}"#);
+        assert!(serde_json::from_str::<Variant>(&json).is_err());
+
+        let json = serde_json::to_string(&Variant::ByRef(ptr::null_mut())).unwrap();
+        assert_eq!(json, r#"{"vt":"VT_BYREF","value":null}"#);
+        assert_eq!(serde_json::from_str::<Variant>(&json).unwrap(), Variant::ByRef(ptr::null_mut()));
+    }
+}
@@ -25,6 +25,7 @@ use mscorlib_sys::system::reflection::_MethodInfo;
 
 use wrappers::PtrContainer;
 use new_safearray::RSafeArray;
+use result::Result;
 
 pub struct InterfaceMapping<PtrTarget, PtrInterface, M> 
     where PtrTarget: PtrContainer<_Type>, 
@@ -37,18 +38,23 @@ pub struct InterfaceMapping<PtrTarget, PtrInterface, M>
     pub interface_methods: RSafeArray<M>,
 }
 
-impl<PtrTarget, PtrInterface, M>  From<comInterfaceMapping> for InterfaceMapping<PtrTarget, PtrInterface, M>  
+impl<PtrTarget, PtrInterface, M> InterfaceMapping<PtrTarget, PtrInterface, M>  
     where PtrTarget: PtrContainer<_Type>, 
           PtrInterface: PtrContainer<_Type>, 
           M: PtrContainer<_MethodInfo>
 {
-    fn from(cim: comInterfaceMapping) -> InterfaceMapping<PtrTarget, PtrInterface, M> {
-        InterfaceMapping {
-            target: PtrTarget::from(cim.TargetType), 
-            interface: PtrInterface::from(cim.interfaceType), 
-            target_methods: RSafeArray::from(cim.TargetMethods), 
-            interface_methods: RSafeArray::from(cim.InterfaceMethods)
-        }
+    //Takes over cim's references and destroys both of its safearrays, whether or not they could be read.
+    pub unsafe fn try_from_raw(cim: comInterfaceMapping) -> Result<InterfaceMapping<PtrTarget, PtrInterface, M>> {
+        let target = PtrTarget::from_raw(cim.TargetType);
+        let interface = PtrInterface::from_raw(cim.interfaceType);
+        let target_methods = RSafeArray::try_from_ptr(cim.TargetMethods);
+        let interface_methods = RSafeArray::try_from_ptr(cim.InterfaceMethods);
+        Ok(InterfaceMapping {
+            target: target, 
+            interface: interface, 
+            target_methods: target_methods?, 
+            interface_methods: interface_methods?
+        })
     }
 }
 
@@ -0,0 +1,378 @@
+// typed_safearray.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+//
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+//
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+//
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::fmt::Debug;
+use std::ops::Deref;
+
+use winapi::ctypes::c_long;
+use winapi::shared::wtypes::{VARENUM, VARTYPE, VT_BOOL, VT_BSTR, VT_CY, VT_DATE, VT_DECIMAL, VT_DISPATCH, 
+                             VT_I1, VT_I2, VT_I4, VT_INT, VT_R4, VT_R8, VT_RECORD, VT_UI1, VT_UI2, VT_UI4, 
+                             VT_UINT, VT_UNKNOWN, VT_VARIANT};
+use winapi::um::oaidl::{IDispatch, SAFEARRAY, SAFEARRAYBOUND};
+use winapi::um::unknwnbase::IUnknown;
+
+use rust_decimal::Decimal;
+
+use bstring::{BString, StringMode};
+use com_ptr::ComPtr;
+use new_safearray::{RSafeArray, RSafeArrayNd};
+use new_variant::{Currency, Date, Int, UInt, Variant};
+use record::Record;
+use result::{ClrError, Result};
+use safearray_descriptor::{check_bounds, single_bound};
+
+//Maps a Rust element type to its SAFEARRAY vartype and the RSafeArray arm that carries it
+pub trait SafeArrayElement: Sized {
+    fn vartype() -> VARENUM;
+    //How BSTR elements are read when the array comes from a pointer
+    fn string_mode() -> StringMode {
+        StringMode::Lossy
+    }
+    //VartypeMismatch if rsa holds some other element type
+    fn from_rsafearray<P>(rsa: RSafeArray<P>) -> Result<Vec<Self>>;
+    fn into_rsafearray<P>(vc: Vec<Self>) -> RSafeArray<P>;
+}
+
+fn mismatch<E: SafeArrayElement, P>(rsa: &RSafeArray<P>) -> ClrError {
+    ClrError::VartypeMismatch{expected: E::vartype() as VARTYPE, found: rsa.vartype() as VARTYPE}
+}
+
+macro_rules! ELEMENT_IMPLS {
+    (@wrap, Dispatchs, $var:ident) => {
+        RSafeArray::Dispatchs($var, None)
+    };
+    (@wrap, Unknowns, $var:ident) => {
+        RSafeArray::Unknowns($var, None)
+    };
+    (@wrap, $enum_name:ident, $var:ident) => {
+        RSafeArray::$enum_name($var)
+    };
+    (@unwrap, Dispatchs, $rsa:ident) => {
+        match $rsa {
+            RSafeArray::Dispatchs(vc, _) => Ok(vc), 
+            _ => Err(mismatch::<Self, P>(&$rsa))
+        }
+    };
+    (@unwrap, Unknowns, $rsa:ident) => {
+        match $rsa {
+            RSafeArray::Unknowns(vc, _) => Ok(vc), 
+            _ => Err(mismatch::<Self, P>(&$rsa))
+        }
+    };
+    (@unwrap, $enum_name:ident, $rsa:ident) => {
+        match $rsa {
+            RSafeArray::$enum_name(vc) => Ok(vc), 
+            _ => Err(mismatch::<Self, P>(&$rsa))
+        }
+    };
+    ($({$elem_type:ty, $enum_name:ident, $vt:ident})*) => {
+        $(
+            impl SafeArrayElement for $elem_type {
+                fn vartype() -> VARENUM {
+                    $vt
+                }
+                fn from_rsafearray<P>(rsa: RSafeArray<P>) -> Result<Vec<$elem_type>> {
+                    ELEMENT_IMPLS!(@unwrap, $enum_name, rsa)
+                }
+                fn into_rsafearray<P>(vc: Vec<$elem_type>) -> RSafeArray<P> {
+                    ELEMENT_IMPLS!(@wrap, $enum_name, vc)
+                }
+            }
+        )*
+    };
+}
+
+ELEMENT_IMPLS!{
+    {i16, Shorts, VT_I2}
+    {i32, Longs, VT_I4}
+    {f32, Floats, VT_R4}
+    {f64, Doubles, VT_R8}
+    {Currency, Currencies, VT_CY}
+    {Date, Dates, VT_DATE}
+    {ComPtr<IDispatch>, Dispatchs, VT_DISPATCH}
+    {bool, Bools, VT_BOOL}
+    {Variant, Variants, VT_VARIANT}
+    {ComPtr<IUnknown>, Unknowns, VT_UNKNOWN}
+    {Decimal, Decimals, VT_DECIMAL}
+    {Record, Records, VT_RECORD}
+    {i8, Chars, VT_I1}
+    {u8, UChars, VT_UI1}
+    {u16, UShorts, VT_UI2}
+    {u32, ULongs, VT_UI4}
+    {Int, Ints, VT_INT}
+    {UInt, UInts, VT_UINT}
+}
+
+//Both string types take either BSTR arm, so a mismatch always means a different vartype
+impl SafeArrayElement for String {
+    fn vartype() -> VARENUM {
+        VT_BSTR
+    }
+    fn from_rsafearray<P>(rsa: RSafeArray<P>) -> Result<Vec<String>> {
+        match rsa {
+            RSafeArray::BStrings(vc) => Ok(vc), 
+            RSafeArray::WStrings(vc) => Ok(vc.iter().map(|s| s.to_string_lossy()).collect()), 
+            _ => Err(mismatch::<String, P>(&rsa))
+        }
+    }
+    fn into_rsafearray<P>(vc: Vec<String>) -> RSafeArray<P> {
+        RSafeArray::BStrings(vc)
+    }
+}
+
+impl SafeArrayElement for BString {
+    fn vartype() -> VARENUM {
+        VT_BSTR
+    }
+    fn string_mode() -> StringMode {
+        StringMode::Wide
+    }
+    fn from_rsafearray<P>(rsa: RSafeArray<P>) -> Result<Vec<BString>> {
+        match rsa {
+            RSafeArray::WStrings(vc) => Ok(vc), 
+            RSafeArray::BStrings(vc) => Ok(vc.iter().map(BString::from_str).collect()), 
+            _ => Err(mismatch::<BString, P>(&rsa))
+        }
+    }
+    fn into_rsafearray<P>(vc: Vec<BString>) -> RSafeArray<P> {
+        RSafeArray::WStrings(vc)
+    }
+}
+
+//RSafeArrayNd with the element type fixed at compile time. 
+//Elements are kept in SAFEARRAY memory order and bounds in SafeArrayCreate order, like RSafeArrayNd.
+#[derive(Clone)]
+pub struct SafeArray<T: SafeArrayElement> {
+    bounds: Vec<SAFEARRAYBOUND>, 
+    data: Vec<T>,
+}
+
+impl<T: SafeArrayElement> SafeArray<T> {
+    //SafeArrayDimensions for empty bounds, SafeArrayLength if they don't hold data.len() elements
+    pub fn new(bounds: Vec<SAFEARRAYBOUND>, data: Vec<T>) -> Result<SafeArray<T>> {
+        check_bounds(&bounds, data.len())?;
+        Ok(SafeArray {
+            bounds: bounds, 
+            data: data
+        })
+    }
+
+    //Fails as safearray_descriptor::single_bound does
+    pub fn with_lower_bound(data: Vec<T>, lower_bound: c_long) -> Result<SafeArray<T>> {
+        let bounds = single_bound(data.len(), lower_bound)?;
+        Ok(SafeArray {
+            bounds: bounds, 
+            data: data
+        })
+    }
+
+    pub fn vartype() -> VARENUM {
+        T::vartype()
+    }
+
+    pub fn bounds(&self) -> &[SAFEARRAYBOUND] {
+        &self.bounds
+    }
+
+    pub fn dims(&self) -> usize {
+        self.bounds.len()
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        &self.data
+    }
+
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+
+    //Fallible counterpart to From<*mut SAFEARRAY>. 
+    //psa must be a SAFEARRAY the caller owns, or null. It is destroyed either way.
+    pub unsafe fn try_from_ptr(psa: *mut SAFEARRAY) -> Result<SafeArray<T>> {
+        RSafeArrayNd::<i32>::try_from_ptr_as(psa, T::string_mode()).and_then(SafeArray::try_from)
+    }
+
+    //Like try_from_ptr, but psa is left for the caller to destroy. psa must be a valid SAFEARRAY or null.
+    pub unsafe fn try_from_borrowed_ptr(psa: *mut SAFEARRAY) -> Result<SafeArray<T>> {
+        RSafeArrayNd::<i32>::try_from_borrowed_ptr_as(psa, T::string_mode()).and_then(SafeArray::try_from)
+    }
+
+    //Fallible counterpart to From<SafeArray<T>> for *mut SAFEARRAY. The caller owns the SAFEARRAY.
+    pub fn try_into_ptr(self) -> Result<*mut SAFEARRAY> {
+        let rsa: RSafeArrayNd = From::from(self);
+        rsa.try_into_ptr()
+    }
+}
+
+impl<T: SafeArrayElement> Deref for SafeArray<T> {
+    type Target = [T];
+    fn deref(&self) -> &[T] {
+        &self.data
+    }
+}
+
+impl<T: SafeArrayElement> From<Vec<T>> for SafeArray<T> {
+    fn from(data: Vec<T>) -> SafeArray<T> {
+        match SafeArray::with_lower_bound(data, 0) {
+            Ok(sa) => sa, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+impl<T: SafeArrayElement, P> TryFrom<RSafeArrayNd<P>> for SafeArray<T> {
+    type Error = ClrError;
+    fn try_from(rsa: RSafeArrayNd<P>) -> Result<SafeArray<T>> {
+        let bounds = rsa.bounds().to_vec();
+        let data = T::from_rsafearray(rsa.into_column_major())?;
+        Ok(SafeArray {
+            bounds: bounds, 
+            data: data
+        })
+    }
+}
+
+impl<T: SafeArrayElement, P> TryFrom<RSafeArray<P>> for SafeArray<T> {
+    type Error = ClrError;
+    fn try_from(rsa: RSafeArray<P>) -> Result<SafeArray<T>> {
+        T::from_rsafearray(rsa).map(SafeArray::from)
+    }
+}
+
+//psa is destroyed whether or not the conversion succeeds
+impl<T: SafeArrayElement> TryFrom<*mut SAFEARRAY> for SafeArray<T> {
+    type Error = ClrError;
+    fn try_from(psa: *mut SAFEARRAY) -> Result<SafeArray<T>> {
+        unsafe {SafeArray::try_from_ptr(psa)}
+    }
+}
+
+impl<T: SafeArrayElement, P> From<SafeArray<T>> for RSafeArrayNd<P> {
+    fn from(sa: SafeArray<T>) -> RSafeArrayNd<P> {
+        //bounds were checked when sa was built
+        RSafeArrayNd::new(sa.bounds, T::into_rsafearray(sa.data)).expect("SafeArray bounds out of step with its data")
+    }
+}
+
+impl<T: SafeArrayElement> From<SafeArray<T>> for *mut SAFEARRAY {
+    fn from(sa: SafeArray<T>) -> *mut SAFEARRAY {
+        match sa.try_into_ptr() {
+            Ok(psa) => psa, 
+            Err(e) => panic!("{}", e)
+        }
+    }
+}
+
+impl<T: SafeArrayElement> From<SafeArray<T>> for Variant {
+    fn from(sa: SafeArray<T>) -> Variant {
+        Variant::Array(From::from(sa))
+    }
+}
+
+impl<T: SafeArrayElement + Debug> Debug for SafeArray<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bounds: Vec<(c_long, u32)> = self.bounds.iter().map(|b| (b.lLbound, b.cElements)).collect();
+        f.debug_struct("SafeArray")
+            .field("bounds", &bounds)
+            .field("data", &self.data)
+            .finish()
+    }
+}
+
+impl<T: SafeArrayElement + PartialEq> PartialEq for SafeArray<T> {
+    fn eq(&self, other: &SafeArray<T>) -> bool {
+        self.bounds.len() == other.bounds.len() && 
+        self.bounds.iter().zip(other.bounds.iter()).all(|(l, r)| {
+            l.lLbound == r.lLbound && l.cElements == r.cElements
+        }) && 
+        self.data == other.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryInto;
+
+    #[test]
+    fn round_trip() {
+        let bounds = vec![SAFEARRAYBOUND {cElements: 2, lLbound: 1}, SAFEARRAYBOUND {cElements: 3, lLbound: -1}];
+        let sa = SafeArray::new(bounds, vec![1.5f64, 2.0, -3.25, 4.0, 0.0, 6.5]).unwrap();
+        let psa: *mut SAFEARRAY = sa.clone().into();
+        let back: SafeArray<f64> = psa.try_into().unwrap();
+        assert_eq!(back, sa);
+        assert_eq!(back.dims(), 2);
+        assert_eq!(back[2], -3.25);
+        assert!(SafeArray::new(back.bounds().to_vec(), vec![1.0f64; 7]).is_err());
+
+        let psa: *mut SAFEARRAY = SafeArray::from(vec![String::from("a"), String::from("b")]).into();
+        let back: SafeArray<String> = psa.try_into().unwrap();
+        assert_eq!(back.into_vec(), vec![String::from("a"), String::from("b")]);
+    }
+
+    #[test]
+    fn wide_strings() {
+        let lone = BString::from_vec(vec![0xDC00u16, 0x42]);
+        let psa: *mut SAFEARRAY = SafeArray::from(vec![lone.clone()]).into();
+        let back: SafeArray<BString> = psa.try_into().unwrap();
+        assert_eq!(back.as_slice(), &[lone][..]);
+    }
+
+    #[test]
+    fn mismatch() {
+        let psa: *mut SAFEARRAY = SafeArray::from(vec![1i32, 2, 3]).into();
+        let res: Result<SafeArray<f64>> = psa.try_into();
+        match res {
+            Err(ClrError::VartypeMismatch{expected, found}) => {
+                assert_eq!(expected, VT_R8 as VARTYPE);
+                assert_eq!(found, VT_I4 as VARTYPE);
+            }, 
+            other => panic!("expected a mismatch, got {:?}", other)
+        }
+
+        let res: Result<SafeArray<Int>> = SafeArray::try_from(RSafeArray::<i32>::Longs(vec![1]));
+        assert!(res.is_err());
+        let res: Result<SafeArray<String>> = SafeArray::try_from(RSafeArray::<i32>::WStrings(vec![BString::from_str("x")]));
+        assert_eq!(res.unwrap().into_vec(), vec![String::from("x")]);
+    }
+
+    #[test]
+    fn lower_bound_out_of_range() {
+        assert_eq!(SafeArray::with_lower_bound(vec![1i32, 2], i32::max_value() - 1).unwrap().len(), 2);
+        match SafeArray::with_lower_bound(vec![1i32, 2, 3], i32::max_value() - 1) {
+            Err(ClrError::SafeArrayBound{upper, ..}) => assert_eq!(upper, i32::max_value() as i64 + 1), 
+            other => panic!("Unexpected result: {:?}", other)
+        }
+    }
+
+    //more elements than cElements can count are refused rather than truncated
+    #[cfg(target_pointer_width = "64")]
+    #[test]
+    fn length_out_of_range() {
+        match single_bound(u32::max_value() as usize + 1, 0) {
+            Err(ClrError::SafeArrayLength{found, ..}) => assert_eq!(found, 1 << 32), 
+            other => panic!("Unexpected result: {:?}", other.err())
+        }
+    }
+}
@@ -34,9 +34,9 @@
 //}
 
 use std::collections::HashMap;
+use std::convert::TryInto;
 use std::fmt;
 use std::fmt::Display;
-use std::mem;
 use std::ptr;
 
 use winapi::ctypes::{c_long};
@@ -44,7 +44,6 @@ use winapi::ctypes::{c_long};
 use winapi::shared::guiddef::{GUID, REFIID, IID_NULL};
 use winapi::shared::minwindef::UINT;
 use winapi::shared::ntdef::LOCALE_NEUTRAL;
-use winapi::shared::wtypes::BSTR;
 use winapi::shared::wtypes::VARIANT_BOOL;
 
 use winapi::um::oaidl::IDispatch;
@@ -62,18 +61,27 @@ use mscorlib_sys::system::reflection::{BindingFlags, CallingConventions, ICustom
 use mscorlib_sys::system::security::policy::_Evidence;
 
 
-use bstring::{BString};
+use bstring::{BString, SysString};
 
+use com_ptr::ComPtr;
 use new_safearray::RSafeArray;
 use new_variant::Variant;
+use owned_variant::OwnedVariant;
 use result::{ClrError, SourceLocation, Result};
 use struct_wrappers::InterfaceMapping as WrappedInterfaceMapping;
+use typed_safearray::SafeArray;
 
+//ptr and ptr_mut lend the pointer, from takes over the reference it was given.
+//Interface pointers are held in a ComPtr, which releases that reference on drop.
+//into_variant lends the object's IUnknown, as QueryInterface returns it, to a VT_UNKNOWN Variant.
 pub trait PtrContainer<T> {
     fn ptr(&self) -> *const T;
     fn ptr_mut(&self) -> *mut T;
+    //Borrows p, a container that keeps a reference takes one of its own
     fn from(p: *mut T) -> Self where Self:Sized;
-    fn into_variant(&self) -> Variant;
+    //Takes over the reference p holds, eg. one returned through an [out] parameter
+    unsafe fn from_raw(p: *mut T) -> Self where Self:Sized;
+    fn into_variant(&self) -> Result<Variant>;
 }
 
 //#[incomplete]
@@ -138,7 +146,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetReferencedAssemblies(&mut passemblies)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Dispatchs, passemblies, *mut IDispatch, *mut _Assembly, A}, _Assembly)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Dispatchs, passemblies, IDispatch, _Assembly, A}, _Assembly)
     }
 
     fn module<M>(&self, name: String) -> Result<M> 
@@ -146,11 +154,11 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
     {
         let p = self.ptr_mut();
         let mut pmodule: *mut _Module = ptr::null_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let hr = unsafe {
-            (*p).GetModule(bs.as_sys(), &mut pmodule)
+            (*p).GetModule(bs.as_in(), &mut pmodule)
         };
-        SUCCEEDED!(hr, M::from(pmodule), _Assembly)
+        SUCCEEDED!(hr, unsafe {M::from_raw(pmodule)}, _Assembly)
     }
 
     fn modules<M>(&self, get_resource_modules: Option<bool>) -> Result<Vec<M>> 
@@ -167,7 +175,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
                 (*p).GetModules(&mut pmodules)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pmodules, *mut IUnknown, *mut _Module, M}, _Assembly)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pmodules, IUnknown, _Module, M}, _Assembly)
     }
 
     fn loaded_modules<M>(&self, get_resource_modules: Option<bool>) -> Result<Vec<M>> 
@@ -185,7 +193,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
             }
         };
         
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pmodules, *mut IUnknown, *mut _Module, M}, _Assembly)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pmodules, IUnknown, _Module, M}, _Assembly)
     }
     //#[incomplete]
     fn create_instance<V>(&self, _type_name: String, _ignore_case: Option<bool>) -> Result<V> 
@@ -227,7 +235,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
                 (*p).GetCustomAttributes_2(vb_inherit, &mut pattrs)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pattrs, *mut IUnknown, *mut _Attribute, A}, _Assembly )
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pattrs, IUnknown, _Attribute, A}, _Assembly )
     }
     
     fn manifest_resource_names(&self) -> Result<Vec<String>>
@@ -237,7 +245,9 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetManifestResourceNames(&mut pnames)
         };
-        SUCCEEDED!(hr, SIMPLE_EXTRACT!{BStrings, pnames, BString}, _Assembly )
+        SUCCEEDED!(hr, (), _Assembly )?;
+        let names: SafeArray<String> = pnames.try_into()?;
+        Ok(names.into_vec())
     }
 
     fn files<F>(&self, resource_modules: Option<bool>) -> Result<Vec<F>> 
@@ -254,26 +264,26 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
                 (*p).GetFiles(&mut pfiles)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pfiles, *mut IUnknown, *mut _FileStream, F}, _Assembly)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pfiles, IUnknown, _FileStream, F}, _Assembly)
     }
 
     fn to_str(&self) -> Result<String>{
         let p = self.ptr_mut();
-        let bs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).ToString_(bs)
+            (*p).ToString_(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe{*bs}).to_string(), _Assembly)
+        SUCCEEDED!(hr, bs.to_string_lossy(), _Assembly)
     }
 
     fn equals<V, TOut>(&self, value: V) -> Result<bool>
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let mut vb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).Equals(vt, &mut vb)
+            (*p).Equals(*vt.as_ptr(), &mut vb)
         };
         SUCCEEDED!(hr, vb < 0, _Assembly)
     }
@@ -295,25 +305,25 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetType(&mut t)
         };
-        SUCCEEDED!(hr, F::from(t), _Assembly)
+        SUCCEEDED!(hr, unsafe {F::from_raw(t)}, _Assembly)
     }
 
     fn codebase(&self) -> Result<String>{
         let p = self.ptr_mut();
-        let bs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).get_CodeBase(bs)
+            (*p).get_CodeBase(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe{*bs}).to_string(), _Assembly)
+        SUCCEEDED!(hr, bs.to_string_lossy(), _Assembly)
     }
 
     fn escaped_codebase(&self) -> Result<String>{
         let p = self.ptr_mut();
-        let bs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).get_EscapedCodeBase(bs)
+            (*p).get_EscapedCodeBase(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe{*bs}).to_string(), _Assembly)
+        SUCCEEDED!(hr, bs.to_string_lossy(), _Assembly)
     }
     //#[todo("Merge both name methods")]
     fn name<A>(&self) -> Result<A>
@@ -324,7 +334,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetName(&mut an)
         };
-        SUCCEEDED!(hr, A::from(an), _Assembly)
+        SUCCEEDED!(hr, unsafe {A::from_raw(an)}, _Assembly)
     }
 
     fn name_2<A>(&self, use_code_base_after_shadow_copy: bool) -> Result<A>
@@ -335,16 +345,16 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetName_2(if use_code_base_after_shadow_copy  {-1} else {0} as VARIANT_BOOL, &mut an)
         };
-        SUCCEEDED!(hr, A::from(an), _Assembly)
+        SUCCEEDED!(hr, unsafe {A::from_raw(an)}, _Assembly)
     }
 
     fn full_name(&self) -> Result<String>{
         let p = self.ptr_mut();
-        let bs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).get_FullName(bs)
+            (*p).get_FullName(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe{*bs}).to_string(), _Assembly)
+        SUCCEEDED!(hr, bs.to_string_lossy(), _Assembly)
     }
 
     fn entry_point<M>(&self) -> Result<M>
@@ -355,31 +365,31 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).get_EntryPoint(&mut mi)
         };
-        SUCCEEDED!(hr, M::from(mi),  _Assembly)
+        SUCCEEDED!(hr, unsafe {M::from_raw(mi)},  _Assembly)
     }
 
     fn type_2<T>(&self, name: &'static str) -> Result<T> 
         where T: PtrContainer<_Type>
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let t: *mut *mut _Type = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetType_2(bs.as_sys(), t)
+            (*p).GetType_2(bs.as_in(), t)
         };
-        SUCCEEDED!(hr, T::from(unsafe {*t}),  _Assembly)
+        SUCCEEDED!(hr, unsafe {T::from_raw(*t)},  _Assembly)
     }
     
     fn type_3<T>(&self, name: &'static str, throw_on_error: bool) -> Result<T> 
         where T: PtrContainer<_Type>
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let t: *mut *mut _Type = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetType_3(bs.as_sys(), if throw_on_error {-1} else {0} as VARIANT_BOOL, t)
+            (*p).GetType_3(bs.as_in(), if throw_on_error {-1} else {0} as VARIANT_BOOL, t)
         };
-        SUCCEEDED!(hr, T::from(unsafe {*t}),  _Assembly)
+        SUCCEEDED!(hr, unsafe {T::from_raw(*t)},  _Assembly)
     }
     
     fn exported_types<S>(&self) -> Result<Vec<S>> 
@@ -390,7 +400,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetExportedTypes(&mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _Type, S} , _Assembly)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Type, S} , _Assembly)
     }
 
     fn types<S>(&self) -> Result<Vec<S>> 
@@ -401,7 +411,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).GetTypes(&mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _Type, S}, _Assembly)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Type, S}, _Assembly)
     }
 
     fn manifest_resource_stream<T, S>(&self, t: T, name: String) -> Result<S> 
@@ -410,57 +420,57 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
     {
         let p = self.ptr_mut();
         let t = t.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut s: *mut _Stream = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetManifestResourceStream(t, bs.as_sys(), &mut s)
+            (*p).GetManifestResourceStream(t, bs.as_in(), &mut s)
         };
-        SUCCEEDED!(hr, S::from(s), _Assembly)
+        SUCCEEDED!(hr, unsafe {S::from_raw(s)}, _Assembly)
     }
 
     fn manifest_resource_stream_2<S>(&self, name: String) -> Result<S> 
         where S: PtrContainer<_Stream> 
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut s: *mut _Stream = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetManifestResourceStream_2(bs.as_sys(), &mut s)
+            (*p).GetManifestResourceStream_2(bs.as_in(), &mut s)
         };
-        SUCCEEDED!(hr, S::from(s), _Assembly)
+        SUCCEEDED!(hr, unsafe {S::from_raw(s)}, _Assembly)
     }
 
     fn file<F>(&self, name: String) -> Result<F> 
         where F: PtrContainer<_FileStream>
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut f: *mut _FileStream = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetFile(bs.as_sys(), &mut f)
+            (*p).GetFile(bs.as_in(), &mut f)
         };
-        SUCCEEDED!(hr, F::from(f), _Assembly)
+        SUCCEEDED!(hr, unsafe {F::from_raw(f)}, _Assembly)
     }
 
     fn manifest_resource_info<I>(&self, name: String) -> Result<I> 
         where I: PtrContainer<_ManifestResourceInfo> 
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let i: *mut *mut _ManifestResourceInfo = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetManifestResourceInfo(bs.as_sys(), i)
+            (*p).GetManifestResourceInfo(bs.as_in(), i)
         };
-        SUCCEEDED!(hr, I::from(unsafe {*i}),  _Assembly)
+        SUCCEEDED!(hr, unsafe {I::from_raw(*i)},  _Assembly)
     }
     
     fn location(&self) -> Result<String> {
         let p = self.ptr_mut();
-        let bs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).get_Location(bs)
+            (*p).get_Location(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe {*bs}).to_string(), _Assembly)
+        SUCCEEDED!(hr, bs.to_string_lossy(), _Assembly)
     }
 
     fn evidence<E>(&self) -> Result<E>
@@ -471,7 +481,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         let hr = unsafe {
             (*p).get_Evidence(e)
         };
-        SUCCEEDED!(hr, E::from(unsafe {*e}),  _Assembly)
+        SUCCEEDED!(hr, unsafe {E::from_raw(*e)},  _Assembly)
     }
 
     fn is_defined<T>(&self, attr_type: T, inherit: bool) -> Result<bool> 
@@ -491,14 +501,14 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
         where T: PtrContainer<_Type> 
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let vb_throw: VARIANT_BOOL = if throw_on_error {-1} else {0};
         let vb_ignore: VARIANT_BOOL = if ignore_case {-1} else {0};
         let mut t: *mut _Type = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetType_4(bs.as_sys(), vb_throw, vb_ignore, &mut t)
+            (*p).GetType_4(bs.as_in(), vb_throw, vb_ignore, &mut t)
         };
-        SUCCEEDED!(hr, T::from(t), _Assembly)
+        SUCCEEDED!(hr, unsafe {T::from_raw(t)}, _Assembly)
     }
 
     fn satellite_assembly<A, C, V>(&self, culture: C, version: Option<V>) -> Result<A> 
@@ -518,7 +528,7 @@ pub trait Assembly where Self: PtrContainer<_Assembly> {
                 (*p).GetSatelliteAssembly(c, asm)
             }
         };
-        SUCCEEDED!(hr, A::from(unsafe{*asm}), _Assembly)
+        SUCCEEDED!(hr, unsafe {A::from_raw(*asm)}, _Assembly)
     }
 }
 
@@ -550,19 +560,19 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetProperties(binding_attrs, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _PropertyInfo, PI}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _PropertyInfo, PI}, _Type)
     }
 
     fn property<P>(&self, name: String, binding_attrs: BindingFlags) -> Result<P>
         where P: PtrContainer<_PropertyInfo>
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut ppi: *mut _PropertyInfo = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetProperty(bs.as_sys(), binding_attrs, &mut ppi)
+            (*p).GetProperty(bs.as_in(), binding_attrs, &mut ppi)
         };
-        SUCCEEDED!(hr, P::from(ppi), _Type)
+        SUCCEEDED!(hr, unsafe {P::from_raw(ppi)}, _Type)
     }
 
     fn fields<F>(&self, binding_attrs: BindingFlags) -> Result<Vec<F>> 
@@ -573,7 +583,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetFields(binding_attrs, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _FieldInfo, F}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _FieldInfo, F}, _Type)
     }
 
     fn field<F>(&self, binding_attrs: BindingFlags) -> Result<Vec<F>> 
@@ -584,7 +594,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetField(binding_attrs, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _FieldInfo, F}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _FieldInfo, F}, _Type)
     }
 
     fn methods<M>(&self, binding_attrs: BindingFlags) -> Result<Vec<M>> 
@@ -595,7 +605,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetMethods(binding_attrs, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _MethodInfo, M}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _MethodInfo, M}, _Type)
     }
 
     //still need to implement GetMethod with binder, types, and modifiers
@@ -604,12 +614,12 @@ pub trait Type where Self: PtrContainer<_Type> {
         where M: PtrContainer<_MethodInfo> 
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let ppm: *mut *mut _MethodInfo = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetMethod_2(bs.as_sys(), binding_attrs, ppm)
+            (*p).GetMethod_2(bs.as_in(), binding_attrs, ppm)
         };
-        SUCCEEDED!(hr, M::from(unsafe{*ppm}), _Type)
+        SUCCEEDED!(hr, unsafe {M::from_raw(*ppm)}, _Type)
     }
     fn interface_map<T, P, P2, M>(&self, interface_type: T) -> Result<WrappedInterfaceMapping<P, P2, M>>
         where T: PtrContainer<_Type>, 
@@ -623,16 +633,16 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetInterfaceMap(t, &mut pim)
         };
-        SUCCEEDED!(hr, WrappedInterfaceMapping::from(unsafe{*pim}), _Type)
+        SUCCEEDED!(hr, unsafe {WrappedInterfaceMapping::try_from_raw(*pim)}?, _Type)
     }
 
     fn instance_of_type(&self, variant: Variant) -> Result<bool> 
     {
         let p = self.ptr_mut();
-        let v = variant.into_c_variant();
+        let v = OwnedVariant::try_new(variant)?;
         let mut vb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).IsInstanceOfType(v, &mut vb)
+            (*p).IsInstanceOfType(*v.as_ptr(), &mut vb)
         };
         SUCCEEDED!(hr, vb < 0, _Type)        
     }
@@ -675,7 +685,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetDefaultMembers(&mut pm)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pm, *mut IUnknown, *mut _MemberInfo, M}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, pm, IUnknown, _MemberInfo, M}, _Type)
     }
 
     //#[incomplete]
@@ -691,36 +701,36 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetMembers(binding_attr, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _MemberInfo, M}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _MemberInfo, M}, _Type)
     }
 
     fn member<M>(&self, name: String, member_types: Option<MemberTypes>, binding_flags: BindingFlags) -> Result<Vec<M>>
         where M: PtrContainer<_MemberInfo>
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut ppm: *mut SAFEARRAY = ptr::null_mut();
         let hr = match member_types {
             Some(member_types) => unsafe {
-                (*p).GetMember(bs.as_sys(), member_types, binding_flags, &mut ppm)
+                (*p).GetMember(bs.as_in(), member_types, binding_flags, &mut ppm)
             }, 
             None => unsafe {
-                (*p).GetMember_2(bs.as_sys(), binding_flags, &mut ppm)
+                (*p).GetMember_2(bs.as_in(), binding_flags, &mut ppm)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, ppm, *mut IUnknown, *mut _MemberInfo, M}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, ppm, IUnknown, _MemberInfo, M}, _Type)
     }
 
     fn nested_type<T>(&self, name: String, binding_flags: BindingFlags) -> Result<T> 
         where T: PtrContainer<_Type> 
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut ppt: *mut _Type = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetNestedType(bs.as_sys(), binding_flags, &mut ppt)
+            (*p).GetNestedType(bs.as_in(), binding_flags, &mut ppt)
         };
-        SUCCEEDED!(hr, T::from(ppt), _Type)
+        SUCCEEDED!(hr, unsafe {T::from_raw(ppt)}, _Type)
     }
 
     fn nested_types<T>(&self, binding_flags: BindingFlags) -> Result<Vec<T>> 
@@ -731,7 +741,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetNestedTypes(binding_flags, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _Type, T}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Type, T}, _Type)
     }
 
     fn events<E>(&self, binding_flags: Option<BindingFlags>) -> Result<Vec<E>> 
@@ -748,19 +758,19 @@ pub trait Type where Self: PtrContainer<_Type> {
                 (*p).GetEvents(&mut e)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, e, *mut IUnknown, *mut _EventInfo, E}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, e, IUnknown, _EventInfo, E}, _Type)
     }
 
     fn event<E>(&self, name: String, flags: BindingFlags) -> Result<E>
         where E: PtrContainer<_EventInfo> 
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut e: *mut _EventInfo = ptr::null_mut();
         let hr = unsafe {
-            (*p).GetEvent(bs.as_sys(), flags, &mut e)
+            (*p).GetEvent(bs.as_in(), flags, &mut e)
         };
-        SUCCEEDED!(hr, E::from(e), _Type)
+        SUCCEEDED!(hr, unsafe {E::from_raw(e)}, _Type)
     }
 
     //#[incomplete]
@@ -779,20 +789,20 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetInterfaces(&mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _Type, T}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Type, T}, _Type)
     }
 
     fn interface<T>(&self, name: String, ignore_case: bool) -> Result<T> 
         where T: PtrContainer<_Type>
     {
         let p = self.ptr_mut();
-        let bs: BString = From::from(name);
+        let bs: SysString = From::from(name);
         let mut t: *mut _Type = ptr::null_mut();
         let vb: VARIANT_BOOL = if ignore_case{-1} else{0};
         let hr = unsafe {
-            (*p).GetInterface(bs.as_sys(), vb, &mut t)
+            (*p).GetInterface(bs.as_in(), vb, &mut t)
         };
-        SUCCEEDED!(hr, T::from(t), _Type)
+        SUCCEEDED!(hr, unsafe {T::from_raw(t)}, _Type)
     }
 
     fn constructors<C>(&self, binding_attrs: BindingFlags) -> Result<Vec<C>> 
@@ -803,7 +813,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetConstructors(binding_attrs, &mut psa)
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _ConstructorInfo, C}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _ConstructorInfo, C}, _Type)
     }
 
     fn defined<T>(&self, attr_type: T, inherit: bool) -> Result<bool>
@@ -835,7 +845,7 @@ pub trait Type where Self: PtrContainer<_Type> {
                 (*p).GetCustomAttributes_2(vb, &mut psa)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _Attribute, A}, _Type)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Attribute, A}, _Type)
     }
 
     PROPERTY!{get_DeclaringType _Type { get { declaring_type(_Type) }}}
@@ -886,12 +896,12 @@ pub trait Type where Self: PtrContainer<_Type> {
 
     fn name(&self) -> Result<String> {
         let p = self.ptr_mut();
-        let pbs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).get_Name(pbs)
+            (*p).get_Name(bs.as_out())
         };
         
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe {*pbs}).to_string(), _Type)
+        SUCCEEDED!(hr, bs.to_string_lossy(), _Type)
     }
 
     fn member_types(&self) -> Result<MemberTypes>{
@@ -907,10 +917,10 @@ pub trait Type where Self: PtrContainer<_Type> {
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let mut vb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).Equals(vt, &mut vb)
+            (*p).Equals(*vt.as_ptr(), &mut vb)
         };
         SUCCEEDED!(hr, vb < 0, _Type)
     }
@@ -944,7 +954,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetType(&mut t)
         };
-        SUCCEEDED!(hr, F::from(t), _Type)
+        SUCCEEDED!(hr, unsafe {F::from_raw(t)}, _Type)
     }
 
     //#[incomplete]
@@ -958,10 +968,9 @@ pub trait Type where Self: PtrContainer<_Type> {
         let c_names = names.len();
         let mut disp_ids: Vec<i32> = Vec::with_capacity(c_names);
         let copy_names = names.clone();
-        let mut us_names: Vec<*mut u16> = names.into_iter().map(|name| unsafe {
-            let bs: BString = From::from(name);
-            bs.as_sys()
-        }).collect();
+        //the guards keep the names alive until GetIDsOfNames returns
+        let sys_names: Vec<SysString> = names.into_iter().map(From::from).collect();
+        let mut us_names: Vec<*mut u16> = sys_names.iter().map(SysString::as_in).collect();
         let hr = unsafe {
             (*p).GetIDsOfNames(riid, us_names[..].as_mut_ptr(), c_names as UINT, LOCALE_NEUTRAL, disp_ids[..].as_mut_ptr())
         };
@@ -982,7 +991,7 @@ pub trait Type where Self: PtrContainer<_Type> {
         let hr = unsafe {
             (*p).GetTypeInfo(index as UINT, LOCALE_NEUTRAL,iti )
         };
-        SUCCEEDED!(hr, T::from(unsafe {*iti}), _Type)
+        SUCCEEDED!(hr, unsafe {T::from_raw(*iti)}, _Type)
     }
 
     fn type_info_count(&self) -> Result<u32> {
@@ -996,32 +1005,32 @@ pub trait Type where Self: PtrContainer<_Type> {
 
     fn to_str(&self) -> Result<BString>{
         let p = self.ptr_mut();
-        let mut bs: BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).ToString_(&mut bs)
+            (*p).ToString_(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(bs), _Type)
+        SUCCEEDED!(hr, bs.to_bstring(), _Type)
     }
 }
 //#[incomplete]
 pub trait MemberInfo where Self: PtrContainer<_MemberInfo> {
     fn to_str(&self) -> Result<String> {
         let p = self.ptr_mut();
-        let pbs: *mut BSTR = ptr::null_mut();
+        let mut bs = SysString::new();
         let hr = unsafe {
-            (*p).ToString_(pbs)
+            (*p).ToString_(bs.as_out())
         };
-        SUCCEEDED!(hr, BString::from_ptr_safe(unsafe{*pbs}).to_string(), _MemberInfo )
+        SUCCEEDED!(hr, bs.to_string_lossy(), _MemberInfo )
     }
 
     fn equals<V, TOut>(&self, value: V) -> Result<bool>
         where V: PtrContainer<TOut>
     {
         let p = self.ptr_mut();
-        let vt = value.into_variant().into_c_variant();
+        let vt = OwnedVariant::try_new(value.into_variant()?)?;
         let mut vb: VARIANT_BOOL = 0;
         let hr = unsafe {
-            (*p).Equals(vt, &mut vb)
+            (*p).Equals(*vt.as_ptr(), &mut vb)
         };
         SUCCEEDED!(hr, vb < 0, _MemberInfo)
     }
@@ -1043,7 +1052,7 @@ pub trait MemberInfo where Self: PtrContainer<_MemberInfo> {
         let hr = unsafe {
             (*p).GetType(&mut t)
         };
-        SUCCEEDED!(hr, F::from(t), _MemberInfo)
+        SUCCEEDED!(hr, unsafe {F::from_raw(t)}, _MemberInfo)
     }
 
     fn member_types(&self) -> Result<MemberTypes>{
@@ -1075,7 +1084,7 @@ pub trait MemberInfo where Self: PtrContainer<_MemberInfo> {
                 (*p).GetCustomAttributes_2(vb, &mut psa)
             }
         };
-        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, *mut IUnknown, *mut _Attribute, A}, _MemberInfo)
+        SUCCEEDED!(hr, EXTRACT_VECTOR_FROM_SAFEARRAY!{Unknowns, psa, IUnknown, _Attribute, A}, _MemberInfo)
     }
 
     fn is_defined<T>(&self, attr_type: T, inherit: bool) -> Result<bool> 
@@ -1212,26 +1221,30 @@ pub trait Binder where Self: PtrContainer<_Binder> {
 
 }
 
+#[derive(Clone)]
 pub struct ClrType {
-    ptr: *mut _Type,
+    ptr: ComPtr<_Type>,
 }
 
 impl PtrContainer<_Type> for ClrType {
     fn ptr(&self) -> *const _Type {
-        self.ptr
+        self.ptr.as_ptr()
     }
     fn ptr_mut(&self) -> *mut _Type {
-        self.ptr
+        self.ptr.as_ptr()
     }
     
     fn from(pt: *mut _Type) -> ClrType {
-        ClrType{ ptr: pt}
+        ClrType{ ptr: unsafe {ComPtr::from_raw_add_ref(pt)}}
+    }
+
+    unsafe fn from_raw(pt: *mut _Type) -> ClrType {
+        ClrType{ ptr: ComPtr::from_raw(pt)}
     }
      
-    fn into_variant(&self) -> Variant {
-        let p = self.ptr_mut();
-        let p: *mut IUnknown = unsafe {mem::transmute::<*mut _Type, *mut IUnknown>(p)};
-        Variant::from(p)
+    fn into_variant(&self) -> Result<Variant> {
+        let unk: ComPtr<IUnknown> = self.ptr.query_interface()?;
+        Ok(Variant::from(unk.as_ptr()))
     }
 }
 
@@ -0,0 +1,147 @@
+// derive.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+
+extern crate mscorlib_safe;
+#[macro_use]
+extern crate mscorlib_safe_derive;
+extern crate mscorlib_sys;
+extern crate winapi;
+
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::ptr;
+
+use winapi::ctypes::c_void;
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_NOINTERFACE, HRESULT};
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
+
+use mscorlib_sys::system::reflection::{_MemberInfo, _Type};
+
+use mscorlib_safe::{ClrError, ComPtr, PtrContainer};
+use mscorlib_safe::new_variant::Variant;
+
+#[derive(PtrContainer)]
+struct Raw {
+    ptr: *mut _Type
+}
+
+#[derive(PtrContainer)]
+struct Tuple(ComPtr<_Type>);
+
+//Every field but the pointer is Default'd by from
+#[derive(PtrContainer)]
+#[ptr_container(field = "member")]
+struct Generic<M: Default> {
+    declaring: ComPtr<_Type>, 
+    member: ComPtr<_MemberInfo>, 
+    extra: M, 
+    marker: PhantomData<M>
+}
+
+#[test]
+fn raw_pointer_field() {
+    let p = 0x1000 as *mut _Type;
+    let raw: Raw = PtrContainer::from(p);
+    assert_eq!(raw.ptr_mut(), p);
+    assert_eq!(raw.ptr(), p as *const _Type);
+}
+
+#[test]
+fn tuple_struct() {
+    let t: Tuple = PtrContainer::from(ptr::null_mut());
+    assert!(t.0.is_null());
+    assert!(t.ptr().is_null());
+}
+
+#[test]
+fn selected_field() {
+    let g: Generic<Vec<u8>> = PtrContainer::<_MemberInfo>::from(ptr::null_mut());
+    assert!(g.member.is_null());
+    assert!(g.declaring.is_null());
+    assert!(g.extra.is_empty());
+    assert_eq!(g.marker, PhantomData);
+    assert!(g.ptr_mut().is_null());
+}
+
+#[test]
+fn null_into_variant() {
+    let t: Tuple = PtrContainer::from(ptr::null_mut());
+    match t.into_variant() {
+        Err(ClrError::InnerCall{..}) => {},
+        Err(e) => panic!("expected E_POINTER, got {}", e),
+        Ok(v) => panic!("expected E_POINTER, got {:?}", v)
+    }
+}
+
+//Counts references, the count lives outside the object as the vtable methods only see its vtable pointer
+#[repr(C)]
+struct MockUnknown {
+    vtbl: *const IUnknownVtbl
+}
+
+thread_local! {
+    static REFS: Cell<ULONG> = Cell::new(1);
+}
+
+unsafe extern "system" fn mock_query_interface(_this: *mut IUnknown, _riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+    *ppv = ptr::null_mut();
+    E_NOINTERFACE
+}
+
+unsafe extern "system" fn mock_add_ref(_this: *mut IUnknown) -> ULONG {
+    REFS.with(|refs| {
+        refs.set(refs.get() + 1);
+        refs.get()
+    })
+}
+
+unsafe extern "system" fn mock_release(_this: *mut IUnknown) -> ULONG {
+    REFS.with(|refs| {
+        refs.set(refs.get() - 1);
+        refs.get()
+    })
+}
+
+static VTBL: IUnknownVtbl = IUnknownVtbl {
+    QueryInterface: mock_query_interface, 
+    AddRef: mock_add_ref, 
+    Release: mock_release
+};
+
+#[test]
+fn from_borrows_from_raw_owns() {
+    let refs = || REFS.with(Cell::get);
+    let mut mock = MockUnknown {vtbl: &VTBL};
+    let p = &mut mock as *mut MockUnknown as *mut _Type;
+
+    let borrowed: Tuple = PtrContainer::from(p);
+    assert_eq!(refs(), 2);
+    drop(borrowed);
+    assert_eq!(refs(), 1);
+
+    let owned: Tuple = unsafe {PtrContainer::from_raw(p)};
+    assert_eq!(refs(), 1);
+    drop(owned);
+    assert_eq!(refs(), 0);
+}
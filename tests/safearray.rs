@@ -21,20 +21,29 @@
 
 extern crate mscorlib_safe;
 extern crate mscorlib_sys;
+extern crate rust_decimal;
 extern crate winapi;
 
+use std::cell::Cell;
 use std::ptr;
 
 use winapi::ctypes::c_void;
-use winapi::shared::wtypes::{VT_I2, VARTYPE};
+use winapi::shared::guiddef::REFIID;
+use winapi::shared::minwindef::ULONG;
+use winapi::shared::winerror::{E_NOINTERFACE, HRESULT};
+use winapi::shared::wtypes::{VT_ARRAY, VT_BYREF, VT_I2, VT_I4, VT_VARIANT, VARTYPE};
 use winapi::um::oaidl::LPSAFEARRAY;
 use winapi::um::oaidl::SAFEARRAYBOUND;
 use winapi::um::oaidl::IDispatch;
+use winapi::um::unknwnbase::{IUnknown, IUnknownVtbl};
 
 use mscorlib_sys::system::reflection::_Type;
 
-use mscorlib_safe::new_variant::{Variant, UInt, Int};
-use mscorlib_safe::new_safearray::{RSafeArray, SafeArrayCreate, SafeArrayDestroy, SafeArrayPutElement, SafeArrayGetVartype, SafeArrayGetElement};
+use rust_decimal::Decimal;
+
+use mscorlib_safe::{BString, ClrError, ComPtr};
+use mscorlib_safe::new_variant::{Currency, Date, Variant, UInt, Int};
+use mscorlib_safe::new_safearray::{RSafeArray, RSafeArrayNd, SafeArrayCreate, SafeArrayDestroy, SafeArrayGetDim, SafeArrayGetLBound, SafeArrayPutElement, SafeArrayGetVartype, SafeArrayGetElement};
 
 #[test]
 fn from_rust_safearray_to_low_level() {
@@ -76,8 +85,8 @@ fn from_low_level_to_rust_safearray(){
         assert_eq!(hr, 0);
     }
 
-    let rsa: RSafeArray<u16> = RSafeArray::from(psa);
-    assert_eq!(rsa.len(), 9);
+    let rsa: RSafeArray<u16> = unsafe {RSafeArray::try_from_ptr(psa)}.unwrap();
+    assert_eq!(rsa.len(), 10);
     assert_eq!(rsa.vartype(), VT_I2);
     if let RSafeArray::Shorts(array) = rsa {
         assert_eq!(array[3], 3)
@@ -156,7 +165,7 @@ fn test_str() {
 fn test_idispatch() {
     let mut vc = Vec::new();
     for _ix in 0..100 {
-        let p: *mut IDispatch = ptr::null_mut();
+        let p: ComPtr<IDispatch> = ComPtr::null();
         vc.push(p);
     }
     let rsa: RSafeArray<_Type> = RSafeArray::from(vc);
@@ -290,3 +299,282 @@ fn test_uint() {
         panic!("Incorrect type");
     }
 }
+
+#[test]
+fn test_nd_offsets() {
+    let bounds = vec![SAFEARRAYBOUND{cElements: 2, lLbound: 0}, SAFEARRAYBOUND{cElements: 3, lLbound: 1}];
+    let rsa: RSafeArrayNd<i32> = RSafeArrayNd::new(bounds, RSafeArray::Longs((0..6).collect())).unwrap();
+    assert_eq!(rsa.dims(), 2);
+    assert_eq!(rsa.shape(), vec![2, 3]);
+    assert_eq!(rsa.column_major_offset(&[1, 1]), Some(1));
+    assert_eq!(rsa.column_major_offset(&[0, 2]), Some(2));
+    assert_eq!(rsa.row_major_offset(&[1, 1]), Some(3));
+    assert_eq!(rsa.row_major_offset(&[0, 2]), Some(1));
+    assert_eq!(rsa.column_major_offset(&[0, 0]), None);
+    assert_eq!(rsa.column_major_offset(&[2, 1]), None);
+    assert_eq!(rsa.column_major_offset(&[0]), None);
+}
+
+#[test]
+fn test_nd_bad_bounds() {
+    let bounds = vec![SAFEARRAYBOUND{cElements: 2, lLbound: 0}, SAFEARRAYBOUND{cElements: 3, lLbound: 1}];
+    match RSafeArrayNd::<i32>::new(bounds, RSafeArray::Longs((0..5).collect())) {
+        Err(ClrError::SafeArrayLength{expected: 6, found: 5}) => {}, 
+        other => panic!("{:?}", other)
+    }
+    match RSafeArrayNd::<i32>::from_row_major(Vec::new(), RSafeArray::Longs(Vec::new())) {
+        Err(ClrError::SafeArrayDimensions{dims: 0, ..}) => {}, 
+        other => panic!("{:?}", other)
+    }
+    //the last index would be past i32::max_value()
+    let bounds = vec![SAFEARRAYBOUND{cElements: 2, lLbound: i32::max_value()}];
+    match RSafeArrayNd::<i32>::new(bounds, RSafeArray::Longs(vec![1, 2])) {
+        Err(ClrError::SafeArrayBound{upper, ..}) => assert_eq!(upper, i32::max_value() as i64 + 1), 
+        other => panic!("{:?}", other)
+    }
+    let empty: RSafeArrayNd<i32> = RSafeArray::Longs(Vec::new()).with_lower_bound(i32::min_value()).unwrap();
+    assert_eq!(empty.upper_bound(), None);
+}
+
+#[test]
+fn test_bounds_at_the_edge() {
+    let rsa: RSafeArrayNd<i32> = RSafeArray::Longs(vec![1, 2]).with_lower_bound(i32::max_value() - 1).unwrap();
+    assert_eq!(rsa.upper_bound(), Some(i32::max_value()));
+    let psa = LPSAFEARRAY::from(rsa.clone());
+    assert_eq!(unsafe {RSafeArrayNd::<i32>::try_from_ptr(psa)}.unwrap(), rsa);
+}
+
+#[test]
+fn test_nd_row_major() {
+    let bounds = vec![SAFEARRAYBOUND{cElements: 2, lLbound: 0}, SAFEARRAYBOUND{cElements: 3, lLbound: 0}];
+    //[[0, 1, 2], [3, 4, 5]]
+    let rsa: RSafeArrayNd<i32> = RSafeArrayNd::from_row_major(bounds, RSafeArray::Longs((0..6).collect())).unwrap();
+    assert_eq!(rsa.column_major(), &RSafeArray::Longs(vec![0, 3, 1, 4, 2, 5]));
+    assert_eq!(rsa.into_row_major(), RSafeArray::Longs(vec![0, 1, 2, 3, 4, 5]));
+}
+
+#[test]
+fn test_nd_round_trip() {
+    let bounds = vec![SAFEARRAYBOUND{cElements: 2, lLbound: 0}, SAFEARRAYBOUND{cElements: 3, lLbound: 1}];
+    let rsa: RSafeArrayNd<i32> = RSafeArrayNd::from_row_major(bounds, RSafeArray::Longs((0..6).collect())).unwrap();
+    let expected = rsa.clone();
+    let psa = LPSAFEARRAY::from(rsa);
+
+    assert_eq!(unsafe {SafeArrayGetDim(psa)}, 2);
+    let mut vt: VARTYPE = 0;
+    let hr = unsafe {
+        SafeArrayGetVartype(psa, &mut vt)
+    };
+    assert_eq!(hr, 0);
+    assert_eq!(vt as u32, VT_I4);
+
+    let mut val: i32 = 0;
+    let hr = unsafe {
+        SafeArrayGetElement(psa, [1, 2].as_ptr(), &mut val as *mut _ as *mut c_void)
+    };
+    assert_eq!(hr, 0);
+    assert_eq!(val, 4);
+
+    let rsa: RSafeArrayNd<i32> = unsafe {RSafeArrayNd::try_from_ptr(psa)}.unwrap();
+    assert_eq!(rsa, expected);
+}
+
+#[test]
+fn test_lower_bound_round_trip() {
+    let rsa: RSafeArrayNd<i32> = RSafeArray::Longs(vec![10, 20, 30]).with_lower_bound(1).unwrap();
+    assert_eq!(rsa.lower_bound(), 1);
+    assert_eq!(rsa.upper_bound(), Some(3));
+    let psa = LPSAFEARRAY::from(rsa);
+
+    let mut lower_bound = 0;
+    let hr = unsafe {
+        SafeArrayGetLBound(psa, 1, &mut lower_bound)
+    };
+    assert_eq!(hr, 0);
+    assert_eq!(lower_bound, 1);
+
+    let mut val: i32 = 0;
+    let hr = unsafe {
+        SafeArrayGetElement(psa, &1, &mut val as *mut _ as *mut c_void)
+    };
+    assert_eq!(hr, 0);
+    assert_eq!(val, 10);
+
+    let rsa: RSafeArrayNd<i32> = unsafe {RSafeArrayNd::try_from_ptr(psa)}.unwrap();
+    assert_eq!(rsa.lower_bound(), 1);
+    assert_eq!(rsa.into_column_major(), RSafeArray::Longs(vec![10, 20, 30]));
+}
+
+#[test]
+fn test_lower_bound_variant() {
+    let rsa: RSafeArrayNd = RSafeArray::Shorts(vec![1, 2]).with_lower_bound(1).unwrap();
+    let vt = Variant::Array(rsa.clone()).into_c_variant();
+    if let Variant::Array(array) = Variant::try_from_c_variant(vt).unwrap() {
+        assert_eq!(array, rsa);
+    } else {
+        panic!("Incorrect type");
+    }
+}
+
+#[test]
+fn test_try_from_null() {
+    match unsafe {RSafeArray::<i32>::try_from_ptr(ptr::null_mut())} {
+        Err(ClrError::InnerCall{..}) => {}, 
+        other => panic!("Unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_try_from_too_many_dims() {
+    let mut sabs = [SAFEARRAYBOUND{cElements: 2, lLbound: 0}, SAFEARRAYBOUND{cElements: 2, lLbound: 0}];
+    let psa = unsafe{SafeArrayCreate(VT_I2 as u16, 2, sabs.as_mut_ptr())};
+    match unsafe {RSafeArray::<i32>::try_from_ptr(psa)} {
+        Err(ClrError::SafeArrayDimensions{dims, ..}) => assert_eq!(dims, 2), 
+        other => panic!("Unexpected result: {:?}", other)
+    }
+}
+
+#[test]
+fn test_try_into_ptr() {
+    let rsa: RSafeArray<i32> = RSafeArray::BStrings(vec![String::from("a"), String::from("b")]);
+    let psa = rsa.clone().try_into_ptr().unwrap();
+    assert_eq!(unsafe {RSafeArray::<i32>::try_from_ptr(psa)}.unwrap(), rsa);
+
+    let nested = RSafeArray::<i32>::Variants(vec![Variant::Array(RSafeArrayNd::from(rsa.clone())), Variant::Long(3)]);
+    let psa = nested.clone().try_into_ptr().unwrap();
+    assert_eq!(unsafe {RSafeArray::<i32>::try_from_ptr(psa)}.unwrap(), nested);
+}
+
+#[test]
+fn test_by_ref_variants_rejected() {
+    let rsa = RSafeArray::<i32>::Variants(vec![Variant::Long(1), Variant::PLong(Box::new(2))]);
+    match rsa.try_into_ptr() {
+        Err(ClrError::UnsupportedVartype{vt, ..}) => assert_eq!(vt, (VT_BYREF | VT_I4) as VARTYPE),
+        other => panic!("expected UnsupportedVartype, got {:?}", other.map(|_| ()))
+    }
+}
+
+//Just enough of a COM object to count references. 
+//The count lives outside the object, winapi's methods take &self which only covers the vtable pointer.
+#[repr(C)]
+struct MockUnknown {
+    vtbl: *const IUnknownVtbl
+}
+
+thread_local! {
+    static MOCK_REFS: Cell<ULONG> = Cell::new(1);
+}
+
+unsafe extern "system" fn mock_query_interface(_this: *mut IUnknown, _riid: REFIID, ppv: *mut *mut c_void) -> HRESULT {
+    *ppv = ptr::null_mut();
+    E_NOINTERFACE
+}
+
+unsafe extern "system" fn mock_add_ref(_this: *mut IUnknown) -> ULONG {
+    MOCK_REFS.with(|refs| {
+        refs.set(refs.get() + 1);
+        refs.get()
+    })
+}
+
+unsafe extern "system" fn mock_release(_this: *mut IUnknown) -> ULONG {
+    MOCK_REFS.with(|refs| {
+        refs.set(refs.get() - 1);
+        refs.get()
+    })
+}
+
+static MOCK_VTBL: IUnknownVtbl = IUnknownVtbl {
+    QueryInterface: mock_query_interface, 
+    AddRef: mock_add_ref, 
+    Release: mock_release
+};
+
+#[test]
+fn test_variant_round_trip() {
+    let nested: RSafeArrayNd = RSafeArray::Longs(vec![7, 8, 9]).with_lower_bound(1).unwrap();
+    let vc = vec![
+        Variant::Long(42), 
+        Variant::BString(String::from("object[]")), 
+        Variant::Array(nested.clone()), 
+        Variant::Empty(()), 
+        Variant::Array(RSafeArray::Variants(vec![Variant::Short(-1), Variant::Null(())]).into()), 
+    ];
+    let psa: LPSAFEARRAY = RSafeArray::<i32>::from(vc.clone()).into();
+    let mut vt: VARTYPE = 0;
+    unsafe {SafeArrayGetVartype(psa, &mut vt)};
+    assert_eq!(vt, VT_VARIANT as VARTYPE);
+    let rsa: RSafeArray<i32> = unsafe {RSafeArray::try_from_ptr(psa)}.unwrap();
+    assert_eq!(rsa, RSafeArray::Variants(vc));
+    assert_eq!(Variant::Array(nested).vartype(), (VT_ARRAY | VT_I4) as VARTYPE);
+}
+
+#[test]
+fn test_variant_dispatch() {
+    let mut mock = MockUnknown {vtbl: &MOCK_VTBL};
+    let punk = &mut mock as *mut MockUnknown as *mut IUnknown;
+    let refs = || MOCK_REFS.with(Cell::get);
+    let psa: LPSAFEARRAY = RSafeArray::<i32>::from(vec![Variant::Unknown(punk), Variant::Dispatch(ptr::null_mut())]).into();
+    //the array holds a reference of its own
+    assert_eq!(refs(), 2);
+    let rsa: RSafeArray<i32> = unsafe {RSafeArray::try_from_ptr(psa)}.unwrap();
+    //psa is gone, the reference read back belongs to the caller now
+    assert_eq!(rsa, RSafeArray::Variants(vec![Variant::Unknown(punk), Variant::Dispatch(ptr::null_mut())]));
+    assert_eq!(refs(), 2);
+    unsafe {(*punk).Release()};
+    assert_eq!(refs(), 1);
+}
+
+fn round_trip(rsa: RSafeArray<i32>) -> RSafeArray<i32> {
+    let psa: LPSAFEARRAY = rsa.into();
+    unsafe {RSafeArray::try_from_ptr(psa)}.unwrap()
+}
+
+#[test]
+fn test_currency() {
+    let vc = vec![Currency(0), Currency(-12345), Currency(i64::max_value()), Currency(i64::min_value())];
+    assert_eq!(round_trip(RSafeArray::from(vc.clone())), RSafeArray::Currencies(vc));
+}
+
+#[test]
+fn test_date() {
+    let vc = vec![Date(0.0), Date(43101.75), Date(-657434.0), Date(2958465.999)];
+    assert_eq!(round_trip(RSafeArray::from(vc.clone())), RSafeArray::Dates(vc));
+}
+
+#[test]
+fn test_decimal() {
+    let vc = vec![Decimal::new(0, 0), Decimal::new(-123456789, 4), Decimal::from_parts(u32::max_value(), u32::max_value(), u32::max_value(), false, 28)];
+    assert_eq!(round_trip(RSafeArray::from(vc.clone())), RSafeArray::Decimals(vc));
+}
+
+//Records need an IRecordInfo, see record.rs
+#[test]
+fn test_every_kind() {
+    let arrays: Vec<RSafeArray<i32>> = vec![
+        RSafeArray::Shorts(vec![i16::min_value(), 0, i16::max_value()]), 
+        RSafeArray::Longs(vec![i32::min_value(), 0, i32::max_value()]), 
+        RSafeArray::Floats(vec![-1.5, 0.0, 3.25]), 
+        RSafeArray::Doubles(vec![-1.5, 0.0, 1e300]), 
+        RSafeArray::Currencies(vec![Currency(-1), Currency(10000)]), 
+        RSafeArray::Dates(vec![Date(1.5), Date(-1.25)]), 
+        RSafeArray::BStrings(vec![String::new(), String::from("b")]), 
+        RSafeArray::Dispatchs(vec![ComPtr::null()], None), 
+        RSafeArray::Bools(vec![true, false]), 
+        RSafeArray::Variants(vec![Variant::Long(1), Variant::Date(Date(2.0))]), 
+        RSafeArray::Unknowns(vec![ComPtr::null()], None), 
+        RSafeArray::Decimals(vec![Decimal::new(15, 1)]), 
+        RSafeArray::Chars(vec![i8::min_value(), i8::max_value()]), 
+        RSafeArray::UChars(vec![0, u8::max_value()]), 
+        RSafeArray::UShorts(vec![0, u16::max_value()]), 
+        RSafeArray::ULongs(vec![0, u32::max_value()]), 
+        RSafeArray::Ints(vec![Int(-1), Int(1)]), 
+        RSafeArray::UInts(vec![UInt(0), UInt(u32::max_value())]), 
+    ];
+    for rsa in arrays {
+        assert_eq!(round_trip(rsa.clone()), rsa);
+    }
+    //WStrings come back as BStrings unless read with StringMode::Wide
+    let rsa: RSafeArray<i32> = RSafeArray::WStrings(vec![BString::from_str("w")]);
+    assert_eq!(round_trip(rsa), RSafeArray::BStrings(vec![String::from("w")]));
+}
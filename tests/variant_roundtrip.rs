@@ -0,0 +1,204 @@
+// variant_roundtrip.rs - MIT License
+//  Copyright (c) 2018 Tyler Laing (ZerothLaw)
+// 
+//  Permission is hereby granted, free of charge, to any person obtaining a copy
+//  of this software and associated documentation files (the "Software"), to deal
+//  in the Software without restriction, including without limitation the rights
+//  to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+//  copies of the Software, and to permit persons to whom the Software is
+//  furnished to do so, subject to the following conditions:
+// 
+//  The above copyright notice and this permission notice shall be included in all
+//  copies or substantial portions of the Software.
+// 
+//  THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+//  IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+//  FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+//  AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+//  LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+//  OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+//  SOFTWARE.
+
+
+//Every Variant arm through write_c_variant and back, by value, by reference and nested in a PVariant.
+//Needs oleaut32, so Windows only. OwnedVariant frees what write_c_variant allocated with VariantClear.
+//Records need an IRecordInfo and are covered by record.rs instead.
+
+extern crate proptest;
+extern crate mscorlib_safe;
+extern crate rust_decimal;
+extern crate winapi;
+
+use std::ptr;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+
+use winapi::um::oaidl::SAFEARRAYBOUND;
+
+use mscorlib_safe::{BString, StringMode};
+use mscorlib_safe::new_safearray::{RSafeArray, RSafeArrayNd};
+use mscorlib_safe::new_variant::{Currency, Date, Int, SCode, UInt, Variant};
+use mscorlib_safe::owned_variant::OwnedVariant;
+
+//NaN never compares equal, everything else including the infinities round trips bit for bit
+fn f32s() -> BoxedStrategy<f32> {
+    any::<f32>().prop_filter("NaN", |f| !f.is_nan()).boxed()
+}
+
+fn f64s() -> BoxedStrategy<f64> {
+    any::<f64>().prop_filter("NaN", |f| !f.is_nan()).boxed()
+}
+
+fn decimals() -> BoxedStrategy<Decimal> {
+    (any::<u32>(), any::<u32>(), any::<u32>(), any::<bool>(), 0u32..29)
+        .prop_map(|(lo, mid, hi, negative, scale)| Decimal::from_parts(lo, mid, hi, negative, scale))
+        .boxed()
+}
+
+fn wide_strings() -> BoxedStrategy<BString> {
+    vec(any::<u16>(), 0..16).prop_map(BString::from_vec).boxed()
+}
+
+fn by_value(wide: bool) -> BoxedStrategy<Variant> {
+    let string = if wide {
+        wide_strings().prop_map(Variant::WString).boxed()
+    } else {
+        any::<String>().prop_map(Variant::BString).boxed()
+    };
+    prop_oneof![
+        Just(Variant::Empty(())),
+        Just(Variant::Null(())),
+        any::<i64>().prop_map(Variant::LongLong),
+        any::<i32>().prop_map(Variant::Long),
+        any::<u8>().prop_map(Variant::Byte),
+        any::<i16>().prop_map(Variant::Short),
+        f32s().prop_map(Variant::Float),
+        f64s().prop_map(Variant::Double),
+        any::<bool>().prop_map(Variant::Bool),
+        any::<i32>().prop_map(|n| Variant::ErrorCode(SCode(n))),
+        any::<i64>().prop_map(|n| Variant::Currency(Currency(n))),
+        f64s().prop_map(|d| Variant::Date(Date(d))),
+        string,
+        Just(Variant::Unknown(ptr::null_mut())),
+        Just(Variant::Dispatch(ptr::null_mut())),
+        any::<i8>().prop_map(Variant::Char),
+        any::<u16>().prop_map(Variant::UShort),
+        any::<u32>().prop_map(Variant::ULong),
+        any::<u64>().prop_map(Variant::ULongLong),
+        any::<i32>().prop_map(|n| Variant::Int(Int(n))),
+        any::<u32>().prop_map(|n| Variant::UInt(UInt(n))),
+        decimals().prop_map(Variant::Decimal),
+    ].boxed()
+}
+
+fn by_ref(wide: bool) -> BoxedStrategy<Variant> {
+    let string = if wide {
+        wide_strings().prop_map(|s| Variant::PWString(Box::new(s))).boxed()
+    } else {
+        any::<String>().prop_map(|s| Variant::PBString(Box::new(s))).boxed()
+    };
+    prop_oneof![
+        any::<u8>().prop_map(|n| Variant::PByte(Box::new(n))),
+        any::<i16>().prop_map(|n| Variant::PShort(Box::new(n))),
+        any::<i32>().prop_map(|n| Variant::PLong(Box::new(n))),
+        any::<i64>().prop_map(|n| Variant::PLongLong(Box::new(n))),
+        f32s().prop_map(|f| Variant::PFloat(Box::new(f))),
+        f64s().prop_map(|f| Variant::PDouble(Box::new(f))),
+        any::<bool>().prop_map(|b| Variant::PBool(Box::new(b))),
+        any::<i32>().prop_map(|n| Variant::PErrorCode(Box::new(SCode(n)))),
+        any::<i64>().prop_map(|n| Variant::PCurrency(Box::new(Currency(n)))),
+        f64s().prop_map(|d| Variant::PDate(Box::new(Date(d)))),
+        string,
+        Just(Variant::PUnknown(Box::new(ptr::null_mut()))),
+        Just(Variant::PDispatch(Box::new(ptr::null_mut()))),
+        Just(Variant::ByRef(ptr::null_mut())),
+        decimals().prop_map(|d| Variant::PDecimal(Box::new(d))),
+        any::<i8>().prop_map(|n| Variant::PChar(Box::new(n))),
+        any::<u16>().prop_map(|n| Variant::PUShort(Box::new(n))),
+        any::<u32>().prop_map(|n| Variant::PULong(Box::new(n))),
+        any::<u64>().prop_map(|n| Variant::PULongLong(Box::new(n))),
+        any::<i32>().prop_map(|n| Variant::PInt(Box::new(Int(n)))),
+        any::<u32>().prop_map(|n| Variant::PUInt(Box::new(UInt(n)))),
+    ].boxed()
+}
+
+fn array_data(wide: bool, len: usize) -> BoxedStrategy<RSafeArray> {
+    let strings = if wide {
+        vec(wide_strings(), len).prop_map(RSafeArray::WStrings).boxed()
+    } else {
+        vec(any::<String>(), len).prop_map(RSafeArray::BStrings).boxed()
+    };
+    prop_oneof![
+        vec(any::<i16>(), len).prop_map(RSafeArray::Shorts),
+        vec(any::<i32>(), len).prop_map(RSafeArray::Longs),
+        vec(f32s(), len).prop_map(RSafeArray::Floats),
+        vec(f64s(), len).prop_map(RSafeArray::Doubles),
+        vec(any::<i64>().prop_map(Currency), len).prop_map(RSafeArray::Currencies),
+        vec(f64s().prop_map(Date), len).prop_map(RSafeArray::Dates),
+        strings,
+        vec(any::<bool>(), len).prop_map(RSafeArray::Bools),
+        vec(any::<i8>(), len).prop_map(RSafeArray::Chars),
+        vec(any::<u8>(), len).prop_map(RSafeArray::UChars),
+        vec(any::<u16>(), len).prop_map(RSafeArray::UShorts),
+        vec(any::<u32>(), len).prop_map(RSafeArray::ULongs),
+        vec(any::<i32>().prop_map(Int), len).prop_map(RSafeArray::Ints),
+        vec(any::<u32>().prop_map(UInt), len).prop_map(RSafeArray::UInts),
+        vec(by_value(wide), len).prop_map(RSafeArray::Variants),
+        vec(decimals(), len).prop_map(RSafeArray::Decimals),
+    ].boxed()
+}
+
+//One to three dimensions with arbitrary lower bounds, including empty ones
+fn arrays(wide: bool) -> BoxedStrategy<RSafeArrayNd> {
+    vec((0u32..4, -3i32..4), 1..4).prop_flat_map(move |dims| {
+        let bounds: Vec<SAFEARRAYBOUND> = dims.iter().map(|&(count, lower)| SAFEARRAYBOUND {cElements: count, lLbound: lower}).collect();
+        let len = bounds.iter().map(|b| b.cElements as usize).product();
+        array_data(wide, len).prop_map(move |data| RSafeArrayNd::new(bounds.clone(), data).unwrap())
+    }).boxed()
+}
+
+fn variants(wide: bool) -> BoxedStrategy<Variant> {
+    let leaf = prop_oneof![
+        by_value(wide),
+        by_ref(wide),
+        arrays(wide).prop_map(Variant::Array),
+        arrays(wide).prop_map(|a| Variant::PArray(Box::new(a))),
+    ].boxed();
+    //A VT_BYREF | VT_VARIANT can't point at another one, so PVariant only goes one level deep
+    prop_oneof![
+        3 => leaf.clone(),
+        1 => leaf.prop_map(|v| Variant::PVariant(Box::new(v))),
+    ].boxed()
+}
+
+fn round_trip(v: &Variant, mode: StringMode) -> Variant {
+    let owned = OwnedVariant::new(v.clone());
+    assert_eq!(owned.vartype(), v.vartype());
+    owned.to_variant_as(mode).unwrap()
+}
+
+proptest! {
+    #[test]
+    fn lossy_round_trip(v in variants(false)) {
+        prop_assert_eq!(round_trip(&v, StringMode::Lossy), v);
+    }
+
+    #[test]
+    fn wide_round_trip(v in variants(true)) {
+        prop_assert_eq!(round_trip(&v, StringMode::Wide), v);
+    }
+
+    //winapi's VARIANT union is made of u64s, so moving one out of into_c_variant
+    //strips the provenance off any pointer in it as far as Miri is concerned
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn into_c_variant_round_trip(v in prop_oneof![by_value(false), arrays(false).prop_map(Variant::Array)]) {
+        let raw = v.clone().into_c_variant();
+        let back = Variant::try_from_c_variant_ref(&raw).unwrap();
+        //by value, so all the VARIANT owns is what VariantClear frees
+        drop(unsafe {OwnedVariant::from_raw(raw)});
+        prop_assert_eq!(back, v);
+    }
+}